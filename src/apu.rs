@@ -0,0 +1,766 @@
+use shared::*;
+use std::collections::VecDeque;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+///Bits that always read back as 1, for 0xff10 - 0xff2f
+const READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3f, 0x00, 0xff, 0xbf, //NR10-NR14
+    0xff, 0x3f, 0x00, 0xff, 0xbf, //unused, NR21-NR24
+    0x7f, 0xff, 0x9f, 0xff, 0xbf, //NR30-NR34
+    0xff, 0xff, 0x00, 0x00, 0xbf, //unused, NR41-NR44
+    0x00, 0x00, 0x70, //NR50-NR52
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, //unused
+];
+
+const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+const NOISE_DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+struct LengthCounter {
+    counter: u16,
+    ///64 for most channels, 256 for the wave channel
+    max: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    fn new(max: u16) -> Self {
+        LengthCounter {
+            counter: 0,
+            max,
+            enabled: false,
+        }
+    }
+    fn load(&mut self, length: u16) {
+        self.counter = self.max - length;
+    }
+    ///Returns true if the counter ran out
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+    ///Enabling length in the half of the frame sequencer period that doesn't clock it clocks it once
+    ///Returns true if that made the counter run out
+    fn set_enabled(&mut self, enabled: bool, frame_step: u8) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = enabled;
+        if frame_step % 2 == 1 && !was_enabled && enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+    fn trigger(&mut self, frame_step: u8) {
+        if self.counter == 0 {
+            self.counter = self.max;
+            if self.enabled && frame_step % 2 == 1 {
+                self.counter -= 1;
+            }
+        }
+    }
+}
+
+struct Envelope {
+    initial: u8,
+    increase: bool,
+    period: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Envelope {
+            initial: 0,
+            increase: false,
+            period: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+    fn write(&mut self, dat: u8) {
+        self.initial = dat >> 4;
+        self.increase = dat & 0x08 != 0;
+        self.period = dat & 0x07;
+    }
+    ///The DAC is powered as long as any of the upper 5 bits are set
+    fn dac_enabled(&self) -> bool {
+        self.initial != 0 || self.increase
+    }
+    fn trigger(&mut self) {
+        self.volume = self.initial;
+        self.timer = self.period;
+    }
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+///Channel 1's frequency sweep
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    timer: u8,
+    enabled: bool,
+    shadow: u16,
+    ///a subtraction happened since the last trigger, clearing negate now kills the channel
+    negate_used: bool,
+}
+
+impl Sweep {
+    fn new() -> Self {
+        Sweep {
+            period: 0,
+            negate: false,
+            shift: 0,
+            timer: 0,
+            enabled: false,
+            shadow: 0,
+            negate_used: false,
+        }
+    }
+    fn reload_timer(&mut self) {
+        //a period of 0 is treated as 8
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+    fn calculate(&mut self) -> u16 {
+        let delta = self.shadow >> self.shift;
+        if self.negate {
+            self.negate_used = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        }
+    }
+}
+
+///Channels 1 and 2
+struct SquareChannel {
+    enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    timer: u32,
+    length: LengthCounter,
+    envelope: Envelope,
+    ///only channel 1 has one
+    sweep: Option<Sweep>,
+}
+
+impl SquareChannel {
+    fn new(has_sweep: bool) -> Self {
+        SquareChannel {
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep: if has_sweep { Some(Sweep::new()) } else { None },
+        }
+    }
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+    fn step(&mut self, mut cycles: u32) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+        self.timer -= cycles;
+    }
+    fn output(&self) -> u8 {
+        if self.enabled && DUTY_PATTERNS[self.duty as usize][self.duty_step as usize] == 1 {
+            self.envelope.volume
+        } else {
+            0
+        }
+    }
+    fn trigger(&mut self, frame_step: u8) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger(frame_step);
+        self.timer = self.period();
+        self.envelope.trigger();
+        let frequency = self.frequency;
+        if let Some(ref mut sweep) = self.sweep {
+            sweep.shadow = frequency;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+            sweep.negate_used = false;
+            if sweep.shift != 0 && sweep.calculate() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+    fn clock_sweep(&mut self) {
+        let mut frequency = None;
+        let mut overflow = false;
+        if let Some(ref mut sweep) = self.sweep {
+            if sweep.timer > 0 {
+                sweep.timer -= 1;
+            }
+            if sweep.timer == 0 {
+                sweep.reload_timer();
+                if sweep.enabled && sweep.period != 0 {
+                    let new = sweep.calculate();
+                    if new > 2047 {
+                        overflow = true;
+                    } else if sweep.shift != 0 {
+                        sweep.shadow = new;
+                        frequency = Some(new);
+                        //the new frequency is checked again straight away, but not written back
+                        overflow = sweep.calculate() > 2047;
+                    }
+                }
+            }
+        }
+        if let Some(frequency) = frequency {
+            self.frequency = frequency;
+        }
+        if overflow {
+            self.enabled = false;
+        }
+    }
+}
+
+///Channel 3
+struct WaveChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    ///0 mute, 1 full, 2 half, 3 quarter
+    volume_code: u8,
+    frequency: u16,
+    timer: u32,
+    ///which of the 32 samples is playing
+    position: u8,
+    sample: u8,
+    length: LengthCounter,
+    ///0xff30 - 0xff3f, two 4 bit samples per byte, high nibble first
+    ram: [u8; 0x10],
+}
+
+impl WaveChannel {
+    fn new() -> Self {
+        WaveChannel {
+            enabled: false,
+            dac_enabled: false,
+            volume_code: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            length: LengthCounter::new(256),
+            ram: [0; 0x10],
+        }
+    }
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+    fn step(&mut self, mut cycles: u32) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+            let byte = self.ram[self.position as usize / 2];
+            self.sample = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0f
+            };
+        }
+        self.timer -= cycles;
+    }
+    fn output(&self) -> u8 {
+        if !self.enabled || self.volume_code == 0 {
+            return 0;
+        }
+        self.sample >> (self.volume_code - 1)
+    }
+    fn trigger(&mut self, frame_step: u8) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger(frame_step);
+        self.timer = self.period();
+        self.position = 0;
+    }
+}
+
+///Channel 4
+struct NoiseChannel {
+    enabled: bool,
+    timer: u32,
+    ///15 bit linear feedback shift register
+    lfsr: u16,
+    clock_shift: u8,
+    ///7 bit mode, gives a more metallic sound
+    short_mode: bool,
+    divisor_code: u8,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl NoiseChannel {
+    fn new() -> Self {
+        NoiseChannel {
+            enabled: false,
+            timer: 0,
+            lfsr: 0x7fff,
+            clock_shift: 0,
+            short_mode: false,
+            divisor_code: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+        }
+    }
+    fn period(&self) -> u32 {
+        NOISE_DIVISORS[self.divisor_code as usize] << self.clock_shift
+    }
+    fn step(&mut self, mut cycles: u32) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (bit << 14);
+            if self.short_mode {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (bit << 6);
+            }
+        }
+        self.timer -= cycles;
+    }
+    fn output(&self) -> u8 {
+        //bit 0 inverted selects the volume
+        if self.enabled && self.lfsr & 1 == 0 {
+            self.envelope.volume
+        } else {
+            0
+        }
+    }
+    fn trigger(&mut self, frame_step: u8) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger(frame_step);
+        self.timer = self.period();
+        self.envelope.trigger();
+        self.lfsr = 0x7fff;
+    }
+}
+
+///Audio processing unit, 0xff10 - 0xff3f
+pub struct Apu {
+    ch1: SquareChannel,
+    ch2: SquareChannel,
+    ch3: WaveChannel,
+    ch4: NoiseChannel,
+    ///NR52 bit 7
+    enabled: bool,
+    ///0xff10 - 0xff2f as last written, for reading back
+    registers: [u8; 0x20],
+    ///next step of the 512Hz frame sequencer (length at 0/2/4/6, sweep at 2/6, envelope at 7)
+    frame_step: u8,
+
+    sample_rate: u32,
+    ///sample_rate added every T-cycle, a sample is due each time it passes CLOCK_SPEED
+    sample_clock: u64,
+    ///charge of the high pass filter capacitors that take out the DC offset
+    capacitor: (f32, f32),
+    ///fraction of the capacitor charge kept per output sample
+    charge_factor: f32,
+    ///interleaved left/right samples waiting to be pulled
+    samples: VecDeque<f32>,
+    ///each channel's DAC output on its own, only kept while capture is on
+    channel_samples: Option<[VecDeque<f32>; 4]>,
+}
+
+impl Apu {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut apu = Apu {
+            ch1: SquareChannel::new(true),
+            ch2: SquareChannel::new(false),
+            ch3: WaveChannel::new(),
+            ch4: NoiseChannel::new(),
+            enabled: false,
+            registers: [0; 0x20],
+            frame_step: 0,
+            sample_rate: 0,
+            sample_clock: 0,
+            capacitor: (0.0, 0.0),
+            charge_factor: 0.0,
+            samples: VecDeque::new(),
+            channel_samples: None,
+        };
+        apu.set_sample_rate(sample_rate);
+        apu
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    ///Changes the output rate, samples already buffered are kept
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate.max(1);
+        self.sample_clock = 0;
+        //the DMG's output capacitor loses 0.999958 of its charge every T-cycle
+        self.charge_factor = 0.999958f32.powf(CLOCK_SPEED as f32 / self.sample_rate as f32);
+    }
+    ///Stereo frames waiting to be pulled
+    pub fn samples_available(&self) -> usize {
+        self.samples.len() / 2
+    }
+    ///Moves as many interleaved left/right samples (-1.0 - 1.0) into buf as fit, returns how many were written
+    ///Only whole stereo frames are written, so the count is always even
+    pub fn pull_samples(&mut self, buf: &mut [f32]) -> usize {
+        let count = buf.len().min(self.samples.len()) & !1;
+        for (to, from) in buf.iter_mut().zip(self.samples.drain(..count)) {
+            *to = from;
+        }
+        count
+    }
+    ///Starts or stops keeping a separate mono stream per channel, for debugging
+    ///The streams are the raw DAC output, before panning, master volume and filtering
+    pub fn set_channel_capture(&mut self, capture: bool) {
+        self.channel_samples = if capture {
+            Some(Default::default())
+        } else {
+            None
+        };
+    }
+    ///Moves as many samples of one channel (0-3) into buf as fit, returns how many were written
+    pub fn pull_channel_samples(&mut self, channel: usize, buf: &mut [f32]) -> usize {
+        let samples = match self.channel_samples {
+            Some(ref mut channels) => &mut channels[channel],
+            None => return 0,
+        };
+        let count = buf.len().min(samples.len());
+        for (to, from) in buf.iter_mut().zip(samples.drain(..count)) {
+            *to = from;
+        }
+        count
+    }
+
+    pub fn read8(&self, addr: u16) -> u8 {
+        match addr {
+            0xff26 => {
+                let status = [
+                    self.ch1.enabled,
+                    self.ch2.enabled,
+                    self.ch3.enabled,
+                    self.ch4.enabled,
+                ];
+                let channels = status
+                    .iter()
+                    .enumerate()
+                    .fold(0, |acc, (i, &on)| acc | ((on as u8) << i));
+                READ_MASKS[0x16] | ((self.enabled as u8) << 7) | channels
+            }
+            0xff10..=0xff2f => {
+                let index = addr as usize - 0xff10;
+                self.registers[index] | READ_MASKS[index]
+            }
+            0xff30..=0xff3f => self.ch3.ram[addr as usize - 0xff30],
+            _ => 0xff,
+        }
+    }
+
+    pub fn write8(&mut self, addr: u16, dat: u8) {
+        match addr {
+            0xff26 => self.set_power(dat & 0x80 != 0),
+            //wave ram works even when powered off
+            0xff30..=0xff3f => self.ch3.ram[addr as usize - 0xff30] = dat,
+            //with the power off only the length counters can be written (on DMG)
+            0xff11 | 0xff16 | 0xff20 if !self.enabled => self.write_length(addr, dat),
+            0xff1b if !self.enabled => self.write_length(addr, dat),
+            0xff10..=0xff25 if self.enabled => {
+                self.registers[addr as usize - 0xff10] = dat;
+                self.write_register(addr, dat);
+            }
+            _ => (),
+        }
+    }
+
+    fn write_length(&mut self, addr: u16, dat: u8) {
+        match addr {
+            0xff11 => self.ch1.length.load(dat as u16 & 0x3f),
+            0xff16 => self.ch2.length.load(dat as u16 & 0x3f),
+            0xff1b => self.ch3.length.load(dat as u16),
+            0xff20 => self.ch4.length.load(dat as u16 & 0x3f),
+            _ => (),
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, dat: u8) {
+        let frame_step = self.frame_step;
+        match addr {
+            0xff10 => {
+                if let Some(ref mut sweep) = self.ch1.sweep {
+                    sweep.period = (dat >> 4) & 0x07;
+                    sweep.negate = dat & 0x08 != 0;
+                    sweep.shift = dat & 0x07;
+                    if !sweep.negate && sweep.negate_used {
+                        self.ch1.enabled = false;
+                    }
+                }
+            }
+            0xff11 | 0xff16 => {
+                let channel = if addr == 0xff11 {
+                    &mut self.ch1
+                } else {
+                    &mut self.ch2
+                };
+                channel.duty = dat >> 6;
+                channel.length.load(dat as u16 & 0x3f);
+            }
+            0xff12 | 0xff17 => {
+                let channel = if addr == 0xff12 {
+                    &mut self.ch1
+                } else {
+                    &mut self.ch2
+                };
+                channel.envelope.write(dat);
+                if !channel.envelope.dac_enabled() {
+                    channel.enabled = false;
+                }
+            }
+            0xff13 | 0xff18 => {
+                let channel = if addr == 0xff13 {
+                    &mut self.ch1
+                } else {
+                    &mut self.ch2
+                };
+                channel.frequency = (channel.frequency & 0x700) | dat as u16;
+            }
+            0xff14 | 0xff19 => {
+                let channel = if addr == 0xff14 {
+                    &mut self.ch1
+                } else {
+                    &mut self.ch2
+                };
+                channel.frequency = (channel.frequency & 0xff) | ((dat as u16 & 0x07) << 8);
+                let expired = channel.length.set_enabled(dat & 0x40 != 0, frame_step);
+                if dat & 0x80 != 0 {
+                    channel.trigger(frame_step);
+                } else if expired {
+                    channel.enabled = false;
+                }
+            }
+            0xff1a => {
+                self.ch3.dac_enabled = dat & 0x80 != 0;
+                if !self.ch3.dac_enabled {
+                    self.ch3.enabled = false;
+                }
+            }
+            0xff1b => self.ch3.length.load(dat as u16),
+            0xff1c => self.ch3.volume_code = (dat >> 5) & 0x03,
+            0xff1d => self.ch3.frequency = (self.ch3.frequency & 0x700) | dat as u16,
+            0xff1e => {
+                let channel = &mut self.ch3;
+                channel.frequency = (channel.frequency & 0xff) | ((dat as u16 & 0x07) << 8);
+                let expired = channel.length.set_enabled(dat & 0x40 != 0, frame_step);
+                if dat & 0x80 != 0 {
+                    channel.trigger(frame_step);
+                } else if expired {
+                    channel.enabled = false;
+                }
+            }
+            0xff20 => self.ch4.length.load(dat as u16 & 0x3f),
+            0xff21 => {
+                self.ch4.envelope.write(dat);
+                if !self.ch4.envelope.dac_enabled() {
+                    self.ch4.enabled = false;
+                }
+            }
+            0xff22 => {
+                self.ch4.clock_shift = dat >> 4;
+                self.ch4.short_mode = dat & 0x08 != 0;
+                self.ch4.divisor_code = dat & 0x07;
+            }
+            0xff23 => {
+                let channel = &mut self.ch4;
+                let expired = channel.length.set_enabled(dat & 0x40 != 0, frame_step);
+                if dat & 0x80 != 0 {
+                    channel.trigger(frame_step);
+                } else if expired {
+                    channel.enabled = false;
+                }
+            }
+            //NR50 and NR51 are only ever read back from registers
+            _ => (),
+        }
+    }
+
+    fn set_power(&mut self, on: bool) {
+        if self.enabled && !on {
+            //powering off clears every register, but not wave ram or (on DMG) the length counters
+            let ram = self.ch3.ram;
+            let lengths = [
+                self.ch1.length.counter,
+                self.ch2.length.counter,
+                self.ch3.length.counter,
+                self.ch4.length.counter,
+            ];
+            self.ch1 = SquareChannel::new(true);
+            self.ch2 = SquareChannel::new(false);
+            self.ch3 = WaveChannel::new();
+            self.ch4 = NoiseChannel::new();
+            self.ch3.ram = ram;
+            self.ch1.length.counter = lengths[0];
+            self.ch2.length.counter = lengths[1];
+            self.ch3.length.counter = lengths[2];
+            self.ch4.length.counter = lengths[3];
+            self.registers = [0; 0x20];
+        } else if !self.enabled && on {
+            self.frame_step = 0;
+        }
+        self.enabled = on;
+    }
+
+    ///Advances the channels by the given number of T-cycles
+    ///frame_sequencer_clocks is how many times DIV's bit 4 fell since the last tick
+    pub fn tick(&mut self, cycles: u32, frame_sequencer_clocks: u32) {
+        if self.enabled {
+            for _ in 0..frame_sequencer_clocks {
+                self.clock_frame_sequencer();
+            }
+        }
+        let rate = self.sample_rate as u64;
+        let mut remaining = cycles;
+        while remaining > 0 {
+            //run up to the next output sample
+            let until_sample = (CLOCK_SPEED as u64 - self.sample_clock).div_ceil(rate);
+            let step = (until_sample.max(1) as u32).min(remaining);
+            if self.enabled {
+                self.ch1.step(step);
+                self.ch2.step(step);
+                self.ch3.step(step);
+                self.ch4.step(step);
+            }
+            remaining -= step;
+            self.sample_clock += step as u64 * rate;
+            if self.sample_clock >= CLOCK_SPEED as u64 {
+                self.sample_clock -= CLOCK_SPEED as u64;
+                self.push_sample();
+            }
+        }
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        let step = self.frame_step;
+        if step.is_multiple_of(2) {
+            if self.ch1.length.clock() {
+                self.ch1.enabled = false;
+            }
+            if self.ch2.length.clock() {
+                self.ch2.enabled = false;
+            }
+            if self.ch3.length.clock() {
+                self.ch3.enabled = false;
+            }
+            if self.ch4.length.clock() {
+                self.ch4.enabled = false;
+            }
+        }
+        if step == 2 || step == 6 {
+            self.ch1.clock_sweep();
+        }
+        if step == 7 {
+            self.ch1.envelope.clock();
+            self.ch2.envelope.clock();
+            self.ch4.envelope.clock();
+        }
+        self.frame_step = (step + 1) % 8;
+    }
+
+    ///Analog level (-1.0 - 1.0) of each channel's DAC, 0 when the DAC is off
+    fn dac_outputs(&self) -> [f32; 4] {
+        let dac = |on: bool, value: u8| {
+            if on {
+                value as f32 / 7.5 - 1.0
+            } else {
+                0.0
+            }
+        };
+        [
+            dac(self.ch1.envelope.dac_enabled(), self.ch1.output()),
+            dac(self.ch2.envelope.dac_enabled(), self.ch2.output()),
+            dac(self.ch3.dac_enabled, self.ch3.output()),
+            dac(self.ch4.envelope.dac_enabled(), self.ch4.output()),
+        ]
+    }
+
+    fn push_sample(&mut self) {
+        let (mut left, mut right) = (0.0, 0.0);
+        let outputs = if self.enabled {
+            self.dac_outputs()
+        } else {
+            [0.0; 4]
+        };
+        if self.enabled {
+            let nr50 = self.registers[0x14];
+            let nr51 = self.registers[0x15];
+            for (i, value) in outputs.iter().enumerate() {
+                if nr51 & (0x10 << i) != 0 {
+                    left += value;
+                }
+                if nr51 & (0x01 << i) != 0 {
+                    right += value;
+                }
+            }
+            //average the channels, then apply the master volume (1-8)
+            left *= (((nr50 >> 4) & 0x07) + 1) as f32 / 32.0;
+            right *= ((nr50 & 0x07) + 1) as f32 / 32.0;
+        }
+        let left = self.high_pass(left, true);
+        let right = self.high_pass(right, false);
+        //if nobody is pulling keep the last second of audio, dropping the oldest frame
+        if self.samples.len() >= self.sample_rate as usize * 2 {
+            self.samples.drain(..2);
+        }
+        self.samples.push_back(left);
+        self.samples.push_back(right);
+        if let Some(ref mut channels) = self.channel_samples {
+            for (samples, &value) in channels.iter_mut().zip(outputs.iter()) {
+                if samples.len() >= self.sample_rate as usize {
+                    samples.pop_front();
+                }
+                samples.push_back(value);
+            }
+        }
+    }
+
+    fn high_pass(&mut self, input: f32, left: bool) -> f32 {
+        let capacitor = if left {
+            &mut self.capacitor.0
+        } else {
+            &mut self.capacitor.1
+        };
+        let output = input - *capacitor;
+        *capacitor = input - output * self.charge_factor;
+        output
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Apu::new(DEFAULT_SAMPLE_RATE)
+    }
+}
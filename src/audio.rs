@@ -0,0 +1,171 @@
+use apu::Apu;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+///Somewhere to put the APU's output, as -1.0 - 1.0 samples interleaved by channel
+pub trait AudioSink {
+    fn write_samples(&mut self, samples: &[f32]) -> io::Result<()>;
+    ///Writes out anything buffered, the sink shouldn't be used afterwards
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+///Converts a sample to signed 16 bit, clipping anything out of range
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+///16 bit PCM .wav file
+///The sizes in the header are only filled in by `finish`, until then they read as 0
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    ///bytes of sample data written so far
+    data_len: u32,
+}
+
+///Size of the RIFF/fmt/data headers in front of the samples
+const WAV_HEADER_LEN: u32 = 44;
+///Most sample data the RIFF size field can describe, the sizes stop growing past it
+const WAV_MAX_DATA_LEN: u32 = u32::MAX - (WAV_HEADER_LEN - 8);
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        WavWriter::new(BufWriter::new(File::create(path)?), sample_rate, channels)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        let block_align = channels * 2;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        //format 1 is integer PCM
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(WavWriter {
+            writer,
+            data_len: 0,
+        })
+    }
+    ///Finishes the file and hands back the writer
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.finish()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavWriter<W> {
+    fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            self.writer.write_all(&to_i16(sample).to_le_bytes())?;
+        }
+        let len = (samples.len() as u64 * 2).min(WAV_MAX_DATA_LEN as u64) as u32;
+        self.data_len = self.data_len.saturating_add(len).min(WAV_MAX_DATA_LEN);
+        Ok(())
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(WAV_HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.writer
+            .seek(SeekFrom::Start(WAV_HEADER_LEN as u64 - 4))?;
+        self.writer.write_all(&self.data_len.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+}
+
+///Headerless signed 16 bit little endian samples, e.g. for `aplay -f S16_LE` or piping into another tool
+pub struct RawPcmWriter<W: Write> {
+    writer: W,
+}
+
+impl RawPcmWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(RawPcmWriter::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> RawPcmWriter<W> {
+    pub fn new(writer: W) -> Self {
+        RawPcmWriter { writer }
+    }
+}
+
+impl<W: Write> AudioSink for RawPcmWriter<W> {
+    fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            self.writer.write_all(&to_i16(sample).to_le_bytes())?;
+        }
+        Ok(())
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+///Drains the APU into a stereo sink for the mix, and optionally a mono sink per channel
+pub struct AudioRecorder {
+    mix: Box<dyn AudioSink>,
+    channels: Option<[Box<dyn AudioSink>; 4]>,
+    buffer: Vec<f32>,
+}
+
+impl AudioRecorder {
+    pub fn new(mix: Box<dyn AudioSink>) -> Self {
+        AudioRecorder {
+            mix,
+            channels: None,
+            buffer: vec![0.0; 4096],
+        }
+    }
+    ///Also records channels 1-4 separately, needs `Apu::set_channel_capture` to be turned on
+    pub fn with_channels(mut self, channels: [Box<dyn AudioSink>; 4]) -> Self {
+        self.channels = Some(channels);
+        self
+    }
+    pub fn records_channels(&self) -> bool {
+        self.channels.is_some()
+    }
+    ///Writes out everything the APU has produced since the last call
+    pub fn record(&mut self, apu: &mut Apu) -> io::Result<()> {
+        loop {
+            let count = apu.pull_samples(&mut self.buffer);
+            if count == 0 {
+                break;
+            }
+            self.mix.write_samples(&self.buffer[..count])?;
+        }
+        if let Some(ref mut channels) = self.channels {
+            for (i, sink) in channels.iter_mut().enumerate() {
+                loop {
+                    let count = apu.pull_channel_samples(i, &mut self.buffer);
+                    if count == 0 {
+                        break;
+                    }
+                    sink.write_samples(&self.buffer[..count])?;
+                }
+            }
+        }
+        Ok(())
+    }
+    ///Finishes every sink
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.mix.finish()?;
+        if let Some(ref mut channels) = self.channels {
+            for sink in channels.iter_mut() {
+                sink.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use interrupt::*;
+use shared::*;
+
+///Memory the cpu runs against, the whole 16 bit address space
+pub trait Bus {
+    fn read8(&self, addr: u16) -> u8;
+    fn write8(&mut self, addr: u16, dat: u8);
+    ///Advances anything driven by the system clock by the given number of T-cycles
+    fn tick(&mut self, cycles: u32);
+
+    ///Reads a little endian word (low byte at addr), low byte first like the hardware does
+    fn read16(&self, addr: u16) -> u16 {
+        let lo = self.read8(addr);
+        join_u8(self.read8(addr.wrapping_add(1)), lo)
+    }
+    ///Writes a little endian word (low byte at addr)
+    fn write16(&mut self, addr: u16, dat: u16) {
+        let (hi, lo) = split_u16(dat);
+        self.write8(addr, lo);
+        self.write8(addr.wrapping_add(1), hi);
+    }
+    ///Pushes the high byte first, like the hardware does
+    fn push_stack(&mut self, sp: &mut u16, val: u16) {
+        let (hi, lo) = split_u16(val);
+        *sp = sp.wrapping_sub(1);
+        self.write8(*sp, hi);
+        *sp = sp.wrapping_sub(1);
+        self.write8(*sp, lo);
+    }
+    fn pop_stack(&mut self, sp: &mut u16) -> u16 {
+        let val = self.read16(*sp);
+        *sp = sp.wrapping_add(2);
+        val
+    }
+    ///Interrupts that are both requested and enabled (IE & IF)
+    fn pending_interrupts(&self) -> u8 {
+        self.read8(0xffff) & self.read8(0xff0f) & INTERRUPT_MASK
+    }
+    ///Clears the interrupt's bit in IF once the cpu has started servicing it
+    fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        let flags = self.read8(0xff0f);
+        self.write8(0xff0f, flags & !interrupt.bit());
+    }
+}
+
+///64KiB of plain ram with nothing mapped into it, for running instructions in tests and tools
+pub struct FlatRam {
+    memory: Vec<u8>,
+    ///T-cycles ticked so far
+    cycles: u64,
+}
+
+impl FlatRam {
+    pub fn new() -> Self {
+        FlatRam {
+            memory: vec![0; 0x10000],
+            cycles: 0,
+        }
+    }
+    ///Copies dat in starting at addr, wrapping around at the end of the address space
+    pub fn load(&mut self, addr: u16, dat: &[u8]) {
+        for (i, &x) in dat.iter().enumerate() {
+            self.memory[addr.wrapping_add(i as u16) as usize] = x;
+        }
+    }
+    pub fn get_memory(&self) -> &[u8] {
+        &self.memory
+    }
+    pub fn get_memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+}
+
+impl Default for FlatRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus for FlatRam {
+    fn read8(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+    fn write8(&mut self, addr: u16, dat: u8) {
+        self.memory[addr as usize] = dat;
+    }
+    fn tick(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+    }
+}
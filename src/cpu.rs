@@ -1,22 +1,35 @@
+use instructions::*;
+use interrupt::*;
+use bus::Bus;
 use register::*;
 use shared::*;
-use instructions::*;
-use mmu;
-use std::rc::*;
-use std::cell::*;
-use std::boxed::Box;
-use log;
 
 pub struct Cpu {
     ///CPU register
     register: CpuRegister,
 
-    ///stupid hack way to not increment PC after jumping
+    ///set when the last instruction jumped, which makes conditional branches take longer
     jumped: bool,
 
     ///are we halted for interrupts?
     halted: bool,
+    ///stopped until a button is pressed
+    stopped: bool,
+    ///an illegal opcode ran, nothing but a reset gets the cpu going again
+    locked: bool,
+
+    ///interrupt master enable
+    ime: bool,
+    ///EI only enables interrupts after the following instruction has run
+    ime_scheduled: bool,
+    ///HALT was executed with IME off and an interrupt pending, so the next opcode byte is read twice
+    halt_bug: bool,
+    ///LD B,B was executed, test roms use it as a breakpoint
+    breakpoint: bool,
 }
+
+///M-cycles taken to push PC and jump to an interrupt vector
+const INTERRUPT_DISPATCH_CYCLES: u32 = 5;
 ///ALU logic
 impl Cpu {
     ///Adds an immediate ubyte to the A register with optional carry
@@ -52,9 +65,7 @@ impl Cpu {
     fn and8(&mut self, reg: Reg8Name, imm: Du8) {
         let val = self.register.get_reg8(reg.clone()) & imm;
         self.register.set_reg8(reg, val);
-        if val == 0 {
-            self.register.set_flag(BitFlag::Z);
-        }
+        self.register.set_flag_b(BitFlag::Z, val == 0);
         self.register.set_flag(BitFlag::H);
         self.register.clear_flag(BitFlag::C);
         self.register.clear_flag(BitFlag::N);
@@ -64,9 +75,7 @@ impl Cpu {
     fn or8(&mut self, reg: Reg8Name, imm: Du8) {
         let val = self.register.get_reg8(reg.clone()) | imm;
         self.register.set_reg8(reg, val);
-        if val == 0 {
-            self.register.set_flag(BitFlag::Z);
-        }
+        self.register.set_flag_b(BitFlag::Z, val == 0);
         self.register.clear_flag(BitFlag::H);
         self.register.clear_flag(BitFlag::C);
         self.register.clear_flag(BitFlag::N);
@@ -76,9 +85,7 @@ impl Cpu {
     fn xor8(&mut self, reg: Reg8Name, imm: Du8) {
         let val = self.register.get_reg8(reg.clone()) ^ imm;
         self.register.set_reg8(reg, val);
-        if val == 0 {
-            self.register.set_flag(BitFlag::Z);
-        }
+        self.register.set_flag_b(BitFlag::Z, val == 0);
         self.register.clear_flag(BitFlag::H);
         self.register.clear_flag(BitFlag::C);
         self.register.clear_flag(BitFlag::N);
@@ -96,16 +103,14 @@ impl Cpu {
     ///Increases the referenced value by one
     ///Sets Z,N(0),H
     fn inc8(&mut self, byte: &mut u8) {
-        let val = match byte.clone() {
+        let val = match *byte {
             0xFF => 0,
             x => x + 1,
         };
         *byte = val;
-        self.register.set_flag_b(BitFlag::Z, *byte == 0);
-
-        if nth_bit(val, 3) {
-            self.register.set_flag(BitFlag::H);
-        }
+        self.register.set_flag_b(BitFlag::Z, val == 0);
+        //carried out of the low nibble
+        self.register.set_flag_b(BitFlag::H, low_nibble(val) == 0);
         self.register.clear_flag(BitFlag::N);
     }
     ///Increases the referenced register by one
@@ -117,53 +122,44 @@ impl Cpu {
         };
         self.register.set_reg8(reg, val);
         self.register.set_flag_b(BitFlag::Z, val == 0);
-
-        if nth_bit(val, 3) {
-            self.register.set_flag(BitFlag::H);
-        }
+        self.register.set_flag_b(BitFlag::H, low_nibble(val) == 0);
         self.register.clear_flag(BitFlag::N);
     }
     ///Decreases the referenced value by one
-    ///Sets Z, N(0), H
+    ///Sets Z, N(1), H
     fn dec8(&mut self, byte: &mut u8) {
-        let val = match byte.clone() {
+        let val = match *byte {
             0 => 0xFF,
             x => x - 1,
         };
         *byte = val;
-        if val == 0 {
-            self.register.set_flag(BitFlag::Z);
-        }
-        if nth_bit(val, 3) {
-            self.register.set_flag(BitFlag::H);
-        }
-        self.register.clear_flag(BitFlag::N);
+        self.register.set_flag_b(BitFlag::Z, val == 0);
+        //borrowed from the high nibble
+        self.register.set_flag_b(BitFlag::H, low_nibble(val) == 0x0f);
+        self.register.set_flag(BitFlag::N);
     }
     ///Decreases the referenced value by one
-    ///Sets Z, N(0), H
+    ///Sets Z, N(1), H
     fn dec8_reg(&mut self, reg: Reg8Name) {
         let val = match self.register.get_reg8(reg.clone()) {
             0 => 0xFF,
             x => x - 1,
         };
         self.register.set_reg8(reg, val);
-        if val == 0 {
-            self.register.set_flag(BitFlag::Z);
-        }
-        if nth_bit(val, 3) {
-            self.register.set_flag(BitFlag::H);
-        }
-        self.register.clear_flag(BitFlag::N);
+        self.register.set_flag_b(BitFlag::Z, val == 0);
+        self.register.set_flag_b(BitFlag::H, low_nibble(val) == 0x0f);
+        self.register.set_flag(BitFlag::N);
     }
-    ///This instruction adds the contents of the given register pair to register pair HL
-    ///Sets C, N(0)
-    fn add16(&mut self, reg: Reg16Name, imm: u16) {
-        let val = self.register.get_reg16(reg.clone()) as u32 + imm as u32;
-        if val > 0xffff {
-            self.register.set_flag(BitFlag::C)
-        }
+    ///Adds a signed byte to a 16 bit register, for ADD SP,e and LD HL,SP+e
+    ///The flags come from adding the offset to the low byte as if it were unsigned
+    ///Sets Z(0), N(0), H, C
+    fn add16_signed(&mut self, val: u16, offset: Ds8) -> u16 {
+        let (_, carry, half) = add(val as u8, offset as u8, false);
+        self.register.clear_flag(BitFlag::Z);
         self.register.clear_flag(BitFlag::N);
-        self.register.set_reg16(reg, val as u16);
+        self.register.set_flag_b(BitFlag::H, half);
+        self.register.set_flag_b(BitFlag::C, carry);
+        val.wrapping_add(offset as u16)
     }
     ///Increments the value of the given register pair
     ///Sets {}
@@ -184,12 +180,13 @@ impl Cpu {
         self.register.set_reg16(reg, val);
     }
     ///Rotate Left Circular Accumulator. This instruction rotates A left one bit, placing bit 7 at bit 0 AND in the Carry flag.
-    ///Sets: C, N(0),H(0)
+    ///Sets: Z(0), C, N(0),H(0)
     fn rlca(&mut self) {
-        let newcarry = nth_bit(self.register.a.clone(), 7);
+        let newcarry = nth_bit(self.register.a, 7);
 
         self.register.set_flag_b(BitFlag::C, newcarry);
         self.register.a = self.register.a.rotate_left(1);
+        self.register.clear_flag(BitFlag::Z);
         self.register.clear_flag(BitFlag::N);
         self.register.clear_flag(BitFlag::H);
     }
@@ -197,7 +194,7 @@ impl Cpu {
     /// Sets Z,C,N(0),H(0)
     fn rlc(&mut self, byte: &mut u8) {
         self.register
-            .set_flag_b(BitFlag::C, nth_bit(byte.clone(), 7));
+            .set_flag_b(BitFlag::C, nth_bit(*byte, 7));
         *byte = byte.rotate_left(1);
         self.register.clear_flag(BitFlag::N);
         self.register.clear_flag(BitFlag::H);
@@ -206,8 +203,8 @@ impl Cpu {
     ///Rotate Left Circular. This instruction rotates either register r of the byte located at the address in HL left one bit, placing bit 7 at bit 0 AND in the Carry flag.
     /// Sets Z,C,N(0),H(0)
     fn rlc_reg(&mut self, reg: Reg8Name) {
-        let old = self.register.get_reg8(reg.clone()).clone();
-        let new = old.clone().rotate_left(1);
+        let old = self.register.get_reg8(reg.clone());
+        let new = old.rotate_left(1);
         self.register.set_reg8(reg, new);
         self.register.set_flag_b(BitFlag::C, nth_bit(old, 7));
         self.register.clear_flag(BitFlag::N);
@@ -215,13 +212,14 @@ impl Cpu {
         self.register.set_flag_b(BitFlag::Z, new == 0);
     }
     /// Rotate Left Accumulator. This instruction rotates A left one bit, placing bit 7 into the Carry flag and the contents of the Carry flag into bit 0 of A
-    /// Sets C,N(0),H(0)
+    /// Sets Z(0),C,N(0),H(0)
     fn rla(&mut self) {
-        let newcarry = nth_bit(self.register.a.clone(), 7);
+        let newcarry = nth_bit(self.register.a, 7);
         let carry: u8 = self.register.flag_is_set(BitFlag::C) as u8;
         self.register.set_flag_b(BitFlag::C, newcarry);
 
         self.register.a = (self.register.a << 1) | carry;
+        self.register.clear_flag(BitFlag::Z);
         self.register.clear_flag(BitFlag::N);
         self.register.clear_flag(BitFlag::H);
     }
@@ -230,7 +228,7 @@ impl Cpu {
     fn rl(&mut self, byte: &mut u8) {
         let carry: u8 = self.register.flag_is_set(BitFlag::C) as u8;
         self.register
-            .set_flag_b(BitFlag::C, nth_bit(byte.clone(), 7));
+            .set_flag_b(BitFlag::C, nth_bit(*byte, 7));
         *byte = (*byte << 1) | carry;
         self.register.clear_flag(BitFlag::N);
         self.register.clear_flag(BitFlag::H);
@@ -242,22 +240,23 @@ impl Cpu {
     fn rl_reg(&mut self, reg: Reg8Name) {
         let old = self.register.get_reg8(reg.clone());
         let carry: u8 = self.register.flag_is_set(BitFlag::C) as u8;
-        let new = old.clone() << 1 | carry;
+        let new = old << 1 | carry;
 
         self.register
-            .set_flag_b(BitFlag::C, nth_bit(old.clone(), 7));
+            .set_flag_b(BitFlag::C, nth_bit(old, 7));
         self.register.set_reg8(reg, new);
         self.register.clear_flag(BitFlag::N);
         self.register.clear_flag(BitFlag::H);
         self.register.set_flag_b(BitFlag::Z, new == 0);
     }
     /// Rotate Right Circular Accumulator. This instruction rotates A right one bit, placing bit 0 at bit 7 AND in the Carry flag.
-    /// Sets C,N(0),H(0)
+    /// Sets Z(0),C,N(0),H(0)
     fn rrca(&mut self) {
-        let newcarry = nth_bit(self.register.a.clone(), 0);
+        let newcarry = nth_bit(self.register.a, 0);
 
         self.register.set_flag_b(BitFlag::C, newcarry);
         self.register.a = self.register.a.rotate_right(1);
+        self.register.clear_flag(BitFlag::Z);
         self.register.clear_flag(BitFlag::N);
         self.register.clear_flag(BitFlag::H);
     }
@@ -265,7 +264,7 @@ impl Cpu {
     /// Sets Z,C,N(0),H(0)
     fn rrc(&mut self, byte: &mut u8) {
         self.register
-            .set_flag_b(BitFlag::C, nth_bit(byte.clone(), 0));
+            .set_flag_b(BitFlag::C, nth_bit(*byte, 0));
         *byte = byte.rotate_right(1);
         self.register.clear_flag(BitFlag::N);
         self.register.clear_flag(BitFlag::H);
@@ -285,13 +284,14 @@ impl Cpu {
         self.register.set_flag_b(BitFlag::Z, new == 0);
     }
     /// Rotate Right Accumulator. This instruction rotates A right one bit, placing bit 0 into the Carry flag and the contents of the Carry flag into bit 7 of A
-    /// Sets C,N(0),H(0)
+    /// Sets Z(0),C,N(0),H(0)
     fn rra(&mut self) {
-        let newcarry = nth_bit(self.register.a.clone(), 0);
+        let newcarry = nth_bit(self.register.a, 0);
         let carry: u8 = (self.register.flag_is_set(BitFlag::C) as u8) << 7;
         self.register.set_flag_b(BitFlag::C, newcarry);
 
         self.register.a = (self.register.a >> 1) | carry;
+        self.register.clear_flag(BitFlag::Z);
         self.register.clear_flag(BitFlag::N);
         self.register.clear_flag(BitFlag::H);
     }
@@ -300,7 +300,7 @@ impl Cpu {
     fn rr(&mut self, byte: &mut u8) {
         let carry: u8 = (self.register.flag_is_set(BitFlag::C) as u8) << 7;
         self.register
-            .set_flag_b(BitFlag::C, nth_bit(byte.clone(), 0));
+            .set_flag_b(BitFlag::C, nth_bit(*byte, 0));
         *byte = (*byte >> 1) | carry;
         self.register.clear_flag(BitFlag::N);
         self.register.clear_flag(BitFlag::H);
@@ -322,8 +322,8 @@ impl Cpu {
     /// Sets Z,C,N(0),H(0)
     fn sla(&mut self, byte: &mut u8) {
         self.register
-            .set_flag_b(BitFlag::C, nth_bit(byte.clone(), 7));
-        *byte = *byte << 1;
+            .set_flag_b(BitFlag::C, nth_bit(*byte, 7));
+        *byte <<= 1;
         self.register.clear_flag(BitFlag::N);
         self.register.clear_flag(BitFlag::H);
         self.register.set_flag_b(BitFlag::Z, *byte == 0);
@@ -344,7 +344,7 @@ impl Cpu {
     fn sra(&mut self, byte: &mut u8) {
         let mask = *byte & 0b10000000;
         self.register
-            .set_flag_b(BitFlag::C, nth_bit(byte.clone(), 0));
+            .set_flag_b(BitFlag::C, nth_bit(*byte, 0));
         *byte = (*byte >> 1) | mask;
         self.register.clear_flag(BitFlag::N);
         self.register.clear_flag(BitFlag::H);
@@ -367,8 +367,8 @@ impl Cpu {
     /// Sets Z,C,H(0),N(0)
     fn srl(&mut self, byte: &mut u8) {
         self.register
-            .set_flag_b(BitFlag::C, nth_bit(byte.clone(), 0));
-        *byte = *byte >> 1;
+            .set_flag_b(BitFlag::C, nth_bit(*byte, 0));
+        *byte >>= 1;
         self.register.clear_flag(BitFlag::N);
         self.register.clear_flag(BitFlag::H);
         self.register.set_flag_b(BitFlag::Z, *byte == 0);
@@ -387,8 +387,8 @@ impl Cpu {
 
     ///Tests bit b in register r or the byte addressed in HL. Basically the specified bit gets copied to the Z flag AND INVERTED.
     ///Sets Z, N(0),H(1)
-    fn bit(&mut self, byte: &mut u8, b: u8) {
-        self.register.set_flag_b(BitFlag::Z, !nth_bit(*byte, b));
+    fn bit(&mut self, byte: u8, b: u8) {
+        self.register.set_flag_b(BitFlag::Z, !nth_bit(byte, b));
         self.register.set_flag(BitFlag::H);
         self.register.clear_flag(BitFlag::N);
     }
@@ -400,6 +400,15 @@ impl Cpu {
         self.register.set_flag(BitFlag::H);
         self.register.clear_flag(BitFlag::N);
     }
+    ///Swaps the high and low nibbles of register r or the byte addressed in HL
+    ///Sets Z, N(0), H(0), C(0)
+    fn swap(&mut self, byte: &mut u8) {
+        swap8(byte);
+        self.register.set_flag_b(BitFlag::Z, *byte == 0);
+        self.register.clear_flag(BitFlag::N);
+        self.register.clear_flag(BitFlag::H);
+        self.register.clear_flag(BitFlag::C);
+    }
     ///Sets (1) bit b in register r or the byte addressed in HL.
     ///No flags
     fn set(&mut self, byte: &mut u8, b: u8) {
@@ -414,54 +423,196 @@ impl Cpu {
     ///Resets (0) bit b in register r or the byte addressed in HL.
     ///No flags
     fn reset(&mut self, byte: &mut u8, b: u8) {
-        let mut mask: u8 = 0b11111110;
-        mask.rotate_left(b as u32);
+        let mask: u8 = 0b11111110_u8.rotate_left(b as u32);
         *byte &= mask;
     }
     ///Resets (0) bit b in register r or the byte addressed in HL.
     ///No flags
     fn reset_reg(&mut self, reg: Reg8Name, b: u8) {
-        let mut mask: u8 = 0b11111110;
-        mask.rotate_left(b as u32);
+        let mask: u8 = 0b11111110_u8.rotate_left(b as u32);
         let old = self.register.get_reg8(reg.clone());
         self.register.set_reg8(reg, old & mask);
     }
 }
 ///Instruction logic
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Cpu {
     pub fn new() -> Self {
         Cpu {
             register: CpuRegister::new(),
             jumped: false,
             halted: false,
+            stopped: false,
+            locked: false,
+            ime: false,
+            ime_scheduled: false,
+            halt_bug: false,
+            breakpoint: false,
+        }
+    }
+    ///Cpu in the state the boot rom leaves it in, for running without one
+    pub fn post_boot() -> Self {
+        Cpu {
+            register: CpuRegister::post_boot(),
+            ..Cpu::new()
+        }
+    }
+    pub fn get_register(&self) -> &CpuRegister {
+        &self.register
+    }
+    pub fn get_register_mut(&mut self) -> &mut CpuRegister {
+        &mut self.register
+    }
+    ///Runs one instruction (or services an interrupt) and returns the T-cycles it took
+    pub fn step<B: Bus>(&mut self, bus: &mut B) -> u32 {
+        if self.locked {
+            return 4;
+        }
+        //STOP ignores interrupts, only a selected button being held wakes it up
+        if self.stopped {
+            if !joypad_line_low(bus) {
+                return 4;
+            }
+            self.stopped = false;
+        }
+        let interrupt_cycles = self.handle_interrupts(bus);
+        if interrupt_cycles > 0 {
+            return interrupt_cycles * 4;
         }
+        //halted cpus still burn cycles while waiting
+        if self.halted {
+            return 4;
+        }
+        //EI takes effect after the instruction following it
+        let enable_ime = self.ime_scheduled;
+        let pc = self.register.pc;
+        let ins = if self.halt_bug {
+            decode(&HaltBugView { bus, pc }, pc)
+        } else {
+            decode(bus, pc)
+        };
+        let size = ins.clone().get_size() as u16;
+        if self.halt_bug {
+            //pc fails to increment past the opcode, so the opcode byte is read again as the next byte
+            self.halt_bug = false;
+            self.register.pc = self.register.pc.wrapping_add(size - 1);
+        } else {
+            self.register.pc = self.register.pc.wrapping_add(size);
+        }
+        self.run_ins(bus, ins.clone());
+        if enable_ime && self.ime_scheduled {
+            self.ime = true;
+            self.ime_scheduled = false;
+        }
+        ins.get_cycles(self.jumped) as u32 * 4
+    }
+
+    pub fn interrupts_enabled(&self) -> bool {
+        self.ime
     }
-    pub fn step(&mut self, mmu: &mut mmu::Mmu) {
-        if !self.halted {
-            let ins = decode(mmu, self.register.sp);
-            self.register.pc += ins.clone().get_size() as u16;
-            println!("{:?}", ins);
-            self.run_ins(mmu, ins);
+    ///Sets IME directly, cancelling any EI still waiting to take effect
+    pub fn set_interrupts_enabled(&mut self, ime: bool) {
+        self.ime = ime;
+        self.ime_scheduled = false;
+    }
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+    ///Returns true once for each time LD B,B has run since the last call
+    pub fn take_breakpoint(&mut self) -> bool {
+        let breakpoint = self.breakpoint;
+        self.breakpoint = false;
+        breakpoint
+    }
+
+    ///Wakes from HALT and services the highest priority pending interrupt if IME is set
+    ///Returns the M-cycles spent (0 if nothing was dispatched)
+    fn handle_interrupts<B: Bus>(&mut self, bus: &mut B) -> u32 {
+        if bus.pending_interrupts() == 0 {
+            return 0;
         }
+        //any pending interrupt ends HALT, even with IME off
+        let mut cycles = 0;
+        if self.halted {
+            self.halted = false;
+            cycles += 1;
+        }
+        if !self.ime {
+            return cycles;
+        }
+        self.ime = false;
+        self.ime_scheduled = false;
+        let (hi, lo) = split_u16(self.register.pc);
+        self.register.sp = self.register.sp.wrapping_sub(1);
+        bus.write8(self.register.sp, hi);
+        //IE is checked again after the high byte is pushed, so a push onto 0xffff can cancel the dispatch
+        let pending = bus.pending_interrupts();
+        self.register.sp = self.register.sp.wrapping_sub(1);
+        bus.write8(self.register.sp, lo);
+        self.register.pc = match Interrupt::highest_priority(pending) {
+            Some(interrupt) => {
+                bus.acknowledge_interrupt(interrupt);
+                interrupt.vector()
+            }
+            None => 0x0000,
+        };
+        cycles + INTERRUPT_DISPATCH_CYCLES
     }
 
-    pub fn run_ins(&mut self, mmu: &mut mmu::Mmu, ins: Instruction) {
+    pub fn run_ins<B: Bus>(&mut self, bus: &mut B, ins: Instruction) {
         //reset internal jump flag
         self.jumped = false;
         use instructions::Instruction::*;
         use register::Reg16Name::HL;
         match ins {
             Nop => (),
-            Halt => self.halted = true,
-            Stop => (),
-            SwapR8(reg) => swap8(self.register.get_reg8_ref(reg)),
+            Illegal(op) => {
+                let addr = self.register.pc.wrapping_sub(1);
+                warn!("Illegal opcode {:02x} at {:04x}, cpu locked up", op, addr);
+                self.locked = true;
+            }
+            Halt => {
+                //right after EI the interrupt is serviced as usual instead of triggering the bug
+                if !(self.ime || self.ime_scheduled) && bus.pending_interrupts() != 0 {
+                    self.halt_bug = true;
+                } else {
+                    self.halted = true;
+                }
+            }
+            Stop(_) => {
+                //the divider is reset on entering STOP
+                bus.write8(0xff04, 0);
+                //with a button already held STOP doesn't stop at all
+                if !joypad_line_low(bus) {
+                    self.stopped = true;
+                }
+            }
+            SwapR8(reg) => {
+                let mut val = self.register.get_reg8(reg.clone());
+                self.swap(&mut val);
+                self.register.set_reg8(reg, val);
+            }
             SwapAR16(reg) => {
-                let v = swap16(self.register.get_reg16(reg.clone()));
-                self.register.set_reg16(reg.clone(), v)
+                let addr = self.register.get_reg16(reg);
+                let mut val = bus.read8(addr);
+                self.swap(&mut val);
+                bus.write8(addr, val);
             }
             LdR8D8(reg, imm) => *self.register.get_reg8_ref(reg) = imm,
-            LdR8A16(reg, addr) => *self.register.get_reg8_ref(reg) = mmu.read8(addr),
-            LdA16R8(addr, reg) => mmu.write8(addr, self.register.get_reg8(reg)),
+            LdR8A16(reg, addr) => *self.register.get_reg8_ref(reg) = bus.read8(addr),
+            LdA16R8(addr, reg) => bus.write8(addr, self.register.get_reg8(reg)),
+            LdR8R8(Reg8Name::B, Reg8Name::B) => self.breakpoint = true,
             LdR8R8(to, from) => {
                 let val = self.register.get_reg8(from);
                 self.register.set_reg8(to, val);
@@ -473,101 +624,110 @@ impl Cpu {
             }
             LdAR16R8(add_reg, reg) => {
                 let addr = self.register.get_reg16(add_reg);
-                mmu.write8(addr, self.register.get_reg8(reg));
+                bus.write8(addr, self.register.get_reg8(reg));
             }
-            LdAR16D8(reg, imm) => mmu.write8(self.register.get_reg16(reg), imm),
+            LdAR16D8(reg, imm) => bus.write8(self.register.get_reg16(reg), imm),
             LdR8AR16(to, from) => {
-                let val = mmu.read8(self.register.get_reg16(from));
+                let val = bus.read8(self.register.get_reg16(from));
                 *self.register.get_reg8_ref(to) = val;
             }
-            LdA16R16(to, from) => mmu.write16(to, self.register.get_reg16(from)),
+            LdA16R16(to, from) => bus.write16(to, self.register.get_reg16(from)),
             LdiAR16R8(to, from) => {
                 let val = self.register.get_reg8(from);
                 let addr = self.register.get_reg16(to.clone());
-                mmu.write8(addr, val);
+                bus.write8(addr, val);
                 self.register.inc_reg16(to);
             }
             LddAR16R8(to, from) => {
                 let val = self.register.get_reg8(from);
                 let addr = self.register.get_reg16(to.clone());
-                mmu.write8(addr, val);
+                bus.write8(addr, val);
                 self.register.dec_reg16(to);
             }
             LdiR8AR16(to, from) => {
                 let addr = self.register.get_reg16(from.clone());
-                let val = mmu.read8(addr);
+                let val = bus.read8(addr);
                 self.register.set_reg8(to, val);
                 self.register.inc_reg16(from);
             }
             LddR8AR16(to, from) => {
                 let addr = self.register.get_reg16(from.clone());
-                let val = mmu.read8(addr);
+                let val = bus.read8(addr);
                 self.register.set_reg8(to, val);
                 self.register.dec_reg16(from);
             }
             LdhR8A8(to, from_lo) => {
-                let val = mmu.read8(0xff00 | from_lo as u16);
+                let val = bus.read8(0xff00 | from_lo as u16);
                 self.register.set_reg8(to, val);
             }
             LdhA8R8(to_lo, from) => {
                 let val = self.register.get_reg8(from);
-                mmu.write8(0xff00 | to_lo as u16, val);
+                bus.write8(0xff00 | to_lo as u16, val);
             }
             LdhAR8R8(to_lo_reg, from) => {
                 let addr = 0xff00 | self.register.get_reg8(to_lo_reg) as u16;
-                mmu.write8(addr, self.register.get_reg8(from));
+                bus.write8(addr, self.register.get_reg8(from));
             }
-            //no clue if this is right
             LdhlR16D8(from, imm) => {
-                let new = self.register
-                    .get_reg16(from)
-                    .clone()
-                    .wrapping_add(imm as u16);
+                let val = self.register.get_reg16(from);
+                let new = self.add16_signed(val, imm);
                 self.register.set_reg16(HL, new);
             }
+            LdhR8AR8(to, from_lo_reg) => {
+                let addr = 0xff00 | self.register.get_reg8(from_lo_reg) as u16;
+                let val = bus.read8(addr);
+                self.register.set_reg8(to, val);
+            }
             IncR8(reg) => self.inc8_reg(reg),
             IncR16(reg) => self.inc16(reg),
             IncAR16(reg) => {
-                let addr = self.register.get_reg16(reg).clone();
-                let mut val = mmu.read8(addr);
+                let addr = self.register.get_reg16(reg);
+                let mut val = bus.read8(addr);
                 self.inc8(&mut val);
-                mmu.write8(addr, val);
+                bus.write8(addr, val);
             }
             DecR8(reg) => self.dec8_reg(reg),
             DecR16(reg) => self.dec16(reg),
             DecAR16(reg) => {
-                let addr = self.register.get_reg16(reg).clone();
-                let mut val = mmu.read8(addr);
+                let addr = self.register.get_reg16(reg);
+                let mut val = bus.read8(addr);
                 self.dec8(&mut val);
-                mmu.write8(addr, val);
+                bus.write8(addr, val);
+            }
+            Scf => {
+                self.register.set_flag(BitFlag::C);
+                self.register.clear_flag(BitFlag::N);
+                self.register.clear_flag(BitFlag::H);
+            }
+            Ccf => {
+                let carry = self.register.flag_is_set(BitFlag::C);
+                self.register.set_flag_b(BitFlag::C, !carry);
+                self.register.clear_flag(BitFlag::N);
+                self.register.clear_flag(BitFlag::H);
             }
-            Scf => self.register.set_flag(BitFlag::C),
-            Ccf => self.register.clear_flag(BitFlag::C),
             BitR8(bit, reg) => self.bit_reg(reg, bit),
             BitAR16(bit, reg) => {
-                let addr = self.register.get_reg16(reg);
-                let mut val = mmu.read8(addr);
-                self.bit(&mut val, bit);
-                mmu.write8(addr, val);
+                let val = bus.read8(self.register.get_reg16(reg));
+                self.bit(val, bit);
             }
             ResR8(bit, reg) => self.reset_reg(reg, bit),
             ResAR16(bit, reg) => {
                 let addr = self.register.get_reg16(reg);
-                let mut val = mmu.read8(addr);
+                let mut val = bus.read8(addr);
                 self.reset(&mut val, bit);
-                mmu.write8(addr, val);
+                bus.write8(addr, val);
             }
             SetR8(bit, reg) => self.set_reg(reg, bit),
             SetAR16(bit, reg) => {
                 let addr = self.register.get_reg16(reg);
-                let mut val = mmu.read8(addr);
+                let mut val = bus.read8(addr);
                 self.set(&mut val, bit);
-                mmu.write8(addr, val);
+                bus.write8(addr, val);
             }
             Cpl => {
                 self.register.set_flag(BitFlag::N);
                 self.register.set_flag(BitFlag::H);
-                self.register.a = self.register.a ^ 0xff;
+                self.register.a ^= 0xff;
             }
             Rlca => self.rlca(),
             Rla => self.rla(),
@@ -576,51 +736,51 @@ impl Cpu {
             RlcR8(reg) => self.rlc_reg(reg),
             RlcAR16(reg) => {
                 let addr = self.register.get_reg16(reg);
-                let mut val = mmu.read8(addr.clone());
+                let mut val = bus.read8(addr);
                 self.rlc(&mut val);
-                mmu.write8(addr, val);
+                bus.write8(addr, val);
             }
             RlR8(reg) => self.rl_reg(reg),
             RlAR16(reg) => {
                 let addr = self.register.get_reg16(reg);
-                let mut val = mmu.read8(addr.clone());
+                let mut val = bus.read8(addr);
                 self.rl(&mut val);
-                mmu.write8(addr, val);
+                bus.write8(addr, val);
             }
             RrcR8(reg) => self.rrc_reg(reg),
             RrcAR16(reg) => {
                 let addr = self.register.get_reg16(reg);
-                let mut val = mmu.read8(addr.clone());
+                let mut val = bus.read8(addr);
                 self.rrc(&mut val);
-                mmu.write8(addr, val);
+                bus.write8(addr, val);
             }
             RrR8(reg) => self.rr_reg(reg),
             RrAR16(reg) => {
                 let addr = self.register.get_reg16(reg);
-                let mut val = mmu.read8(addr.clone());
+                let mut val = bus.read8(addr);
                 self.rr(&mut val);
-                mmu.write8(addr, val);
+                bus.write8(addr, val);
             }
             SlaR8(reg) => self.sla_reg(reg),
             SlaAR16(reg) => {
                 let addr = self.register.get_reg16(reg);
-                let mut val = mmu.read8(addr.clone());
+                let mut val = bus.read8(addr);
                 self.sla(&mut val);
-                mmu.write8(addr, val);
+                bus.write8(addr, val);
             }
             SraR8(reg) => self.sra_reg(reg),
             SraAR16(reg) => {
                 let addr = self.register.get_reg16(reg);
-                let mut val = mmu.read8(addr.clone());
+                let mut val = bus.read8(addr);
                 self.sra(&mut val);
-                mmu.write8(addr, val);
+                bus.write8(addr, val);
             }
             SrlR8(reg) => self.srl_reg(reg),
             SrlAR16(reg) => {
                 let addr = self.register.get_reg16(reg);
-                let mut val = mmu.read8(addr.clone());
+                let mut val = bus.read8(addr);
                 self.srl(&mut val);
-                mmu.write8(addr, val);
+                bus.write8(addr, val);
             }
             JpA16(addr) => {
                 self.register.pc = addr;
@@ -644,21 +804,18 @@ impl Cpu {
                 }
             }
             JrA8(offset) => {
-                let val = (self.register.sp as i16).wrapping_add(offset as i16);
-                self.register.sp = val as u16;
+                self.register.pc = self.register.pc.wrapping_add(offset as u16);
                 self.jumped = true;
             }
             JrFA8(flag, offset) => {
                 if self.register.flag_is_set(flag) {
-                    let val = (self.register.sp as i16).wrapping_add(offset as i16);
-                    self.register.sp = val as u16;
+                    self.register.pc = self.register.pc.wrapping_add(offset as u16);
                     self.jumped = true;
                 }
             }
             JrNfA8(flag, offset) => {
                 if self.register.flag_is_unset(flag) {
-                    let val = (self.register.sp as i16).wrapping_add(offset as i16);
-                    self.register.sp = val as u16;
+                    self.register.pc = self.register.pc.wrapping_add(offset as u16);
                     self.jumped = true;
                 }
             }
@@ -668,27 +825,31 @@ impl Cpu {
             }
             AddR8D8(reg, imm) => self.add8(reg, imm, false),
             AddR8AR16(to, from) => {
-                let val = mmu.read8(self.register.get_reg16(from));
+                let val = bus.read8(self.register.get_reg16(from));
                 self.add8(to, val, false);
             }
             AddR16R16(to, from) => {
                 let fval = self.register.get_reg16(from.clone());
                 let tval = self.register.get_reg16(to.clone());
+                //Z is left alone
                 let (res, carry, half) = add16(tval, fval, false);
                 self.register.set_flag_b(BitFlag::C, carry);
                 self.register.set_flag_b(BitFlag::H, half);
-                self.register.set_flag_b(BitFlag::Z, res == 0);
                 self.register.set_flag_b(BitFlag::N, false);
                 self.register.set_reg16(to, res);
             }
-            AddR16D8(to, imm) => self.add16(to, imm as u16),
+            AddR16D8(to, imm) => {
+                let val = self.register.get_reg16(to.clone());
+                let new = self.add16_signed(val, imm);
+                self.register.set_reg16(to, new);
+            }
             AdcR8R8(to, from) => {
                 let val = self.register.get_reg8(from);
                 self.add8(to, val, true);
             }
             AdcR8D8(reg, imm) => self.add8(reg, imm, true),
             AdcR8AR16(to, from) => {
-                let val = mmu.read8(self.register.get_reg16(from));
+                let val = bus.read8(self.register.get_reg16(from));
                 self.add8(to, val, true);
             }
             SubR8R8(to, from) => {
@@ -697,7 +858,7 @@ impl Cpu {
             }
             SubR8D8(to, imm) => self.sub8(to, imm, false),
             SubR8AR16(to, from) => {
-                let val = mmu.read8(self.register.get_reg16(from));
+                let val = bus.read8(self.register.get_reg16(from));
                 self.sub8(to, val, false);
             }
             SbcR8R8(to, from) => {
@@ -705,7 +866,7 @@ impl Cpu {
                 self.sub8(to, val, true);
             }
             SbcR8AR16(to, from) => {
-                let val = mmu.read8(self.register.get_reg16(from));
+                let val = bus.read8(self.register.get_reg16(from));
                 self.sub8(to, val, true);
             }
             SbcR8D8(to, imm) => self.sub8(to, imm, true),
@@ -715,7 +876,7 @@ impl Cpu {
             }
             AndR8D8(to, imm) => self.and8(to, imm),
             AndR8AR16(to, from) => {
-                let val = mmu.read8(self.register.get_reg16(from));
+                let val = bus.read8(self.register.get_reg16(from));
                 self.and8(to, val);
             }
             OrR8R8(to, from) => {
@@ -724,7 +885,7 @@ impl Cpu {
             }
             OrR8D8(to, imm) => self.or8(to, imm),
             OrR8AR16(to, from) => {
-                let val = mmu.read8(self.register.get_reg16(from));
+                let val = bus.read8(self.register.get_reg16(from));
                 self.or8(to, val);
             }
             XorR8R8(to, from) => {
@@ -733,94 +894,135 @@ impl Cpu {
             }
             XorR8D8(to, imm) => self.xor8(to, imm),
             XorR8AR16(to, from) => {
-                let val = mmu.read8(self.register.get_reg16(from));
+                let val = bus.read8(self.register.get_reg16(from));
                 self.xor8(to, val);
             }
-            Ei => mmu.enable_interrupts(),
-            Di => mmu.disable_interrupts(),
+            Ei => self.ime_scheduled = true,
+            Di => {
+                self.ime = false;
+                self.ime_scheduled = false;
+            }
             CpR8R8(to, from) => {
-                let val = self.register.get_reg8(from).clone();
+                let val = self.register.get_reg8(from);
                 self.cp8(to, val);
             }
             CpR8AR16(to, from) => {
-                let val = mmu.read8(self.register.get_reg16(from));
+                let val = bus.read8(self.register.get_reg16(from));
                 self.cp8(to, val);
             }
             CpR8D8(to, imm) => self.cp8(to, imm),
             DaaR8(reg) => {
-                //todo: there is no way this shit is correct
-                let val = self.register.get_reg8(reg.clone()) as u16;
-                let lo = val % 0x10;
-                let hi = ((val % 0x100) - lo) / 0x10;
-                let rs = (hi << 4) | lo;
-                self.register.set_flag_b(BitFlag::Z, rs == 0);
+                //fixes up the result of the last add or subtract using N, H and C
+                let mut val = self.register.get_reg8(reg.clone());
+                let mut carry = self.register.flag_is_set(BitFlag::C);
+                if self.register.flag_is_set(BitFlag::N) {
+                    if carry {
+                        val = val.wrapping_sub(0x60);
+                    }
+                    if self.register.flag_is_set(BitFlag::H) {
+                        val = val.wrapping_sub(0x06);
+                    }
+                } else {
+                    if carry || val > 0x99 {
+                        val = val.wrapping_add(0x60);
+                        carry = true;
+                    }
+                    if self.register.flag_is_set(BitFlag::H) || low_nibble(val) > 0x09 {
+                        val = val.wrapping_add(0x06);
+                    }
+                }
+                self.register.set_flag_b(BitFlag::Z, val == 0);
                 self.register.clear_flag(BitFlag::H);
-                self.register.set_flag_b(BitFlag::C, val >= 0x100);
-                self.register.set_reg8(reg, val as u8);
+                self.register.set_flag_b(BitFlag::C, carry);
+                self.register.set_reg8(reg, val);
             }
             PushR16(reg) => {
                 let val = self.register.get_reg16(reg);
-                mmu.push_stack(&mut self.register.sp, val);
+                bus.push_stack(&mut self.register.sp, val);
             }
             PopR16(reg) => {
-                let val = mmu.pop_stack(&mut self.register.sp);
+                let val = bus.pop_stack(&mut self.register.sp);
                 self.register.set_reg16(reg, val);
             }
             CallA16(addr) => {
-                let pc = self.register.pc + 3;
-                mmu.push_stack(&mut self.register.sp, pc);
+                let pc = self.register.pc;
+                bus.push_stack(&mut self.register.sp, pc);
                 self.register.pc = addr;
                 self.jumped = true;
             }
             CallFA16(flag, addr) => {
                 if self.register.flag_is_set(flag) {
-                    let pc = self.register.pc + 3;
-                    mmu.push_stack(&mut self.register.sp, pc);
+                    let pc = self.register.pc;
+                    bus.push_stack(&mut self.register.sp, pc);
                     self.register.pc = addr;
                     self.jumped = true;
                 }
             }
             CallNfA16(flag, addr) => {
                 if self.register.flag_is_unset(flag) {
-                    let pc = self.register.pc + 3;
-                    mmu.push_stack(&mut self.register.sp, pc);
+                    let pc = self.register.pc;
+                    bus.push_stack(&mut self.register.sp, pc);
                     self.register.pc = addr;
                     self.jumped = true;
                 }
             }
             Ret => {
-                let pc = mmu.pop_stack(&mut self.register.sp);
+                let pc = bus.pop_stack(&mut self.register.sp);
                 self.register.pc = pc;
                 self.jumped = true;
             }
             Reti => {
-                let pc = mmu.pop_stack(&mut self.register.sp);
+                let pc = bus.pop_stack(&mut self.register.sp);
                 self.register.pc = pc;
                 self.jumped = true;
-                mmu.enable_interrupts();
+                //unlike EI there's no delay
+                self.ime = true;
             }
             RetF(flag) => {
                 if self.register.flag_is_set(flag) {
-                    let pc = mmu.read16(self.register.sp);
-                    self.register.sp += 2;
+                    let pc = bus.pop_stack(&mut self.register.sp);
                     self.register.pc = pc;
                     self.jumped = true;
                 }
             }
             RetNf(flag) => {
                 if self.register.flag_is_unset(flag) {
-                    let pc = mmu.read16(self.register.sp);
-                    self.register.sp += 2;
+                    let pc = bus.pop_stack(&mut self.register.sp);
                     self.register.pc = pc;
                     self.jumped = true;
                 }
             }
             Rst(addr) => {
-                let pc = self.register.pc + 1;
-                mmu.push_stack(&mut self.register.sp, pc);
+                let pc = self.register.pc;
+                bus.push_stack(&mut self.register.sp, pc);
                 self.register.pc = addr;
                 self.jumped = true;
             }
         }
     }
 }
+
+///A pressed button is pulling one of the selected joypad lines low, which is what wakes the cpu from STOP
+fn joypad_line_low<B: Bus>(bus: &B) -> bool {
+    bus.read8(0xff00) & 0x0f != 0x0f
+}
+
+///What the cpu fetches right after the HALT bug: pc doesn't move past the opcode, so the opcode
+///byte is read twice and every byte after it comes one address early
+struct HaltBugView<'a, B: Bus + 'a> {
+    bus: &'a B,
+    pc: u16,
+}
+
+impl<'a, B: Bus> Bus for HaltBugView<'a, B> {
+    fn read8(&self, addr: u16) -> u8 {
+        if addr == self.pc {
+            self.bus.read8(addr)
+        } else {
+            self.bus.read8(addr.wrapping_sub(1))
+        }
+    }
+    //only ever used for decoding
+    fn write8(&mut self, _addr: u16, _dat: u8) {}
+    fn tick(&mut self, _cycles: u32) {}
+}
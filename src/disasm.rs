@@ -0,0 +1,177 @@
+use bus::{Bus, FlatRam};
+use instructions::{self, Instruction};
+use shared::*;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+///Size of a rom bank, bank 0 is mapped at 0x0000 and the others at 0x4000
+pub const ROM_BANK_SIZE: usize = 0x4000;
+
+///One decoded instruction
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub addr: Addr,
+    ///The opcode and its operands, as read from memory
+    pub bytes: Vec<u8>,
+    pub instruction: Instruction,
+}
+
+impl Line {
+    ///Address a jump, call or rst goes to, relative jumps are resolved against the line's address
+    pub fn target(&self) -> Option<Addr> {
+        use instructions::Instruction::*;
+        match self.instruction {
+            JrA8(e) | JrFA8(_, e) | JrNfA8(_, e) => {
+                Some(self.addr.wrapping_add(2).wrapping_add(e as u16))
+            }
+            JpA16(a)
+            | JpFA16(_, a)
+            | JpNfA16(_, a)
+            | CallA16(a)
+            | CallFA16(_, a)
+            | CallNfA16(_, a)
+            | Rst(a) => Some(a),
+            _ => None,
+        }
+    }
+}
+
+///Decodes instructions one after the other, starting at start and stopping once len bytes are covered
+///The last instruction may run past the end
+pub fn disassemble<B: Bus + ?Sized>(bus: &B, start: Addr, len: usize) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < len {
+        let addr = start.wrapping_add(offset as u16);
+        let instruction = instructions::decode(bus, addr);
+        let size = instruction.clone().get_size() as u16;
+        let bytes = (0..size).map(|i| bus.read8(addr.wrapping_add(i))).collect();
+        lines.push(Line {
+            addr,
+            bytes,
+            instruction,
+        });
+        offset += size as usize;
+    }
+    lines
+}
+
+///Maps a rom bank where the cpu would see it (bank 0 along with it), None if the rom doesn't have the bank
+pub fn map_rom_bank(rom: &[u8], bank: usize) -> Option<FlatRam> {
+    let start = bank * ROM_BANK_SIZE;
+    if start >= rom.len() {
+        return None;
+    }
+    let end = rom.len().min(start + ROM_BANK_SIZE);
+    let mut ram = FlatRam::new();
+    ram.load(0, &rom[..rom.len().min(ROM_BANK_SIZE)]);
+    if bank > 0 {
+        ram.load(ROM_BANK_SIZE as u16, &rom[start..end]);
+    }
+    Some(ram)
+}
+
+///Formats lines as an RGBDS style listing, using symbol names for addresses where it knows them
+#[derive(Default)]
+pub struct Disassembler {
+    symbols: HashMap<Addr, String>,
+}
+
+impl Disassembler {
+    pub fn new() -> Self {
+        Disassembler {
+            symbols: HashMap::new(),
+        }
+    }
+    pub fn add_symbol(&mut self, addr: Addr, name: &str) {
+        self.symbols.insert(addr, name.to_string());
+    }
+    pub fn get_symbol(&self, addr: Addr) -> Option<&str> {
+        self.symbols.get(&addr).map(|x| x.as_str())
+    }
+    ///Reads an RGBDS .sym file ("01:4000 Name" per line), keeping the symbols visible while bank is mapped
+    ///Returns the number of symbols added
+    pub fn load_symbols(&mut self, text: &str, bank: usize) -> usize {
+        let mut added = 0;
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            let mut parts = line.split_whitespace();
+            let (location, name) = match (parts.next(), parts.next()) {
+                (Some(location), Some(name)) => (location, name),
+                _ => continue,
+            };
+            let mut location = location.split(':');
+            let parsed = match (location.next(), location.next()) {
+                (Some(b), Some(a)) => usize::from_str_radix(b, 16)
+                    .ok()
+                    .and_then(|b| u16::from_str_radix(a, 16).ok().map(|a| (b, a))),
+                _ => None,
+            };
+            let (sym_bank, addr) = match parsed {
+                Some(x) => x,
+                None => continue,
+            };
+            //only the switchable rom bank can hold another bank's code, ram banks are ignored
+            if (0x4000..0x8000).contains(&addr) && sym_bank != bank {
+                continue;
+            }
+            self.add_symbol(addr, name);
+            added += 1;
+        }
+        added
+    }
+
+    ///The symbol for addr, or addr in hex
+    fn name(&self, addr: Addr) -> String {
+        match self.get_symbol(addr) {
+            Some(name) => name.to_string(),
+            None => format!("${:04x}", addr),
+        }
+    }
+
+    ///The instruction with jump targets resolved and addresses replaced by symbols
+    pub fn format_instruction(&self, line: &Line) -> String {
+        use instructions::Instruction::*;
+        let target = line.target().map(|x| self.name(x)).unwrap_or_default();
+        match line.instruction {
+            JrA8(_) => format!("jr {}", target),
+            JrFA8(ref flag, _) => format!("jr {}, {}", flag, target),
+            JrNfA8(ref flag, _) => format!("jr n{}, {}", flag, target),
+            JpA16(_) => format!("jp {}", target),
+            JpFA16(ref flag, _) => format!("jp {}, {}", flag, target),
+            JpNfA16(ref flag, _) => format!("jp n{}, {}", flag, target),
+            CallA16(_) => format!("call {}", target),
+            CallFA16(ref flag, _) => format!("call {}, {}", flag, target),
+            CallNfA16(ref flag, _) => format!("call n{}, {}", flag, target),
+            LdR8A16(ref r, a) => format!("ld {}, [{}]", r, self.name(a)),
+            LdA16R8(a, ref r) => format!("ld [{}], {}", self.name(a), r),
+            LdA16R16(a, ref r) => format!("ld [{}], {}", self.name(a), r),
+            LdhR8A8(ref r, n) => format!("ldh {}, [{}]", r, self.name(0xff00 | n as u16)),
+            LdhA8R8(n, ref r) => format!("ldh [{}], {}", self.name(0xff00 | n as u16), r),
+            ref x => x.to_string(),
+        }
+    }
+
+    ///"0150  c3 50 01  jp Main", the bytes are padded so the mnemonics line up
+    pub fn format_line(&self, line: &Line) -> String {
+        let bytes: Vec<String> = line.bytes.iter().map(|x| format!("{:02x}", x)).collect();
+        format!(
+            "{:04x}  {:<8}  {}",
+            line.addr,
+            bytes.join(" "),
+            self.format_instruction(line)
+        )
+    }
+
+    ///Every line, with a label line before each address that has a symbol
+    pub fn listing(&self, lines: &[Line]) -> String {
+        let mut out = String::new();
+        for line in lines {
+            if let Some(name) = self.get_symbol(line.addr) {
+                writeln!(out, "{}:", name).unwrap();
+            }
+            writeln!(out, "{}", self.format_line(line)).unwrap();
+        }
+        out
+    }
+}
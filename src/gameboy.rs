@@ -0,0 +1,100 @@
+use bus::Bus;
+use cpu;
+use joypad;
+use mmu;
+use rom;
+
+///T-cycles in one frame (154 lines of 456 dots)
+pub const CYCLES_PER_FRAME: u32 = 70224;
+
+///IO registers the DMG boot rom leaves set when it hands over to the cartridge
+const POST_BOOT_IO: [(u16, u8); 7] = [
+    //sound on (has to come first, the other sound registers ignore writes while it's off)
+    (0xff26, 0x80),
+    (0xff11, 0x80),
+    (0xff12, 0xf3),
+    (0xff24, 0x77),
+    (0xff25, 0xf3),
+    //LCD on, background on, tile data at 0x8000
+    (0xff40, 0x91),
+    (0xff47, 0xfc),
+];
+
+///The whole machine, with the cpu driving the master clock
+pub struct GameBoy {
+    cpu: cpu::Cpu,
+    mmu: mmu::Mmu,
+    ///T-cycles run since power on
+    cycles: u64,
+    ///T-cycles the last frame ran past its end, taken off the next one
+    frame_overrun: u32,
+}
+
+impl GameBoy {
+    ///Starts the cartridge at 0x100 as if the boot rom had just run
+    pub fn new(rom: Box<dyn rom::Cartridge>) -> Self {
+        let mut mmu = mmu::Mmu::new(rom);
+        for &(addr, dat) in POST_BOOT_IO.iter() {
+            mmu.write8(addr, dat);
+        }
+        GameBoy::from_parts(cpu::Cpu::post_boot(), mmu)
+    }
+    pub fn from_parts(cpu: cpu::Cpu, mmu: mmu::Mmu) -> Self {
+        GameBoy {
+            cpu,
+            mmu,
+            cycles: 0,
+            frame_overrun: 0,
+        }
+    }
+    ///Runs one cpu step, advances everything else by the same amount, and returns the T-cycles taken
+    pub fn step(&mut self) -> u32 {
+        let cycles = self.cpu.step(&mut self.mmu);
+        self.mmu.tick(cycles);
+        self.cycles += cycles as u64;
+        cycles
+    }
+    ///Runs for (at least) the given number of T-cycles, returns how many were actually run
+    pub fn run_cycles(&mut self, cycles: u32) -> u32 {
+        let mut ran = 0;
+        while ran < cycles {
+            ran += self.step();
+        }
+        ran
+    }
+    ///Runs one frame's worth of cycles
+    ///Instructions can't be split, so any overrun is carried into the next frame to keep frames aligned
+    pub fn run_frame(&mut self) {
+        let target = CYCLES_PER_FRAME - self.frame_overrun.min(CYCLES_PER_FRAME);
+        let ran = self.run_cycles(target);
+        self.frame_overrun = ran - target;
+    }
+
+    ///Last finished frame, see `Ppu::get_framebuffer`
+    pub fn get_framebuffer(&self) -> &[u8] {
+        self.mmu.get_ppu().get_framebuffer()
+    }
+    ///Buttons, for frontends and scripts to press between frames
+    pub fn get_joypad_mut(&mut self) -> &mut joypad::Joypad {
+        self.mmu.get_joypad_mut()
+    }
+    ///Text sent over the link port so far, if the default capture device is plugged in
+    pub fn get_serial_text(&self) -> Option<String> {
+        self.mmu.get_serial().get_capture().map(|x| x.get_text())
+    }
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+    pub fn get_cpu(&self) -> &cpu::Cpu {
+        &self.cpu
+    }
+    pub fn get_cpu_mut(&mut self) -> &mut cpu::Cpu {
+        &mut self.cpu
+    }
+    pub fn get_mmu(&self) -> &mmu::Mmu {
+        &self.mmu
+    }
+    pub fn get_mmu_mut(&mut self) -> &mut mmu::Mmu {
+        &mut self.mmu
+    }
+}
@@ -0,0 +1,251 @@
+use crc32fast;
+use rom::*;
+use sha1_smol::Sha1;
+use std::fmt;
+
+///Everything worth knowing about a rom image, for the info command and rom scanning tools
+pub struct RomInfo {
+    pub path: String,
+    pub header: CartridgeHeader,
+    ///size of the (unpacked) file in bytes
+    pub file_size: usize,
+    ///16KB banks actually present in the file
+    pub file_banks: usize,
+    ///header checksum computed over 0x134 - 0x14c
+    pub computed_checksum: u8,
+    ///global checksum computed over the whole file
+    pub computed_global_checksum: u16,
+    pub crc32: u32,
+    pub sha1: String,
+}
+
+impl RomInfo {
+    pub fn from_bytes(path: &str, dat: &[u8]) -> Result<Self, RomError> {
+        let header = parse_header(dat)?;
+        Ok(RomInfo {
+            path: path.to_string(),
+            file_size: dat.len(),
+            file_banks: dat.len().div_ceil(0x4000),
+            computed_checksum: header_checksum(dat),
+            computed_global_checksum: global_checksum(dat),
+            crc32: crc32fast::hash(dat),
+            sha1: Sha1::from(dat).digest().to_string(),
+            header,
+        })
+    }
+    pub fn load(path: &str) -> Result<Self, RomError> {
+        let dat = load_rom_image(path)?;
+        RomInfo::from_bytes(path, &dat)
+    }
+    pub fn checksum_valid(&self) -> bool {
+        self.computed_checksum == self.header.checksum
+    }
+    pub fn global_checksum_valid(&self) -> bool {
+        self.computed_global_checksum == self.header.global_checksum
+    }
+    pub fn logo_valid(&self) -> bool {
+        self.header.logo[..] == NINTENDO_LOGO[..]
+    }
+    fn color_name(&self) -> &'static str {
+        match self.header.color {
+            ColorSupport::None => "none",
+            ColorSupport::Supported => "supported",
+            ColorSupport::Required => "required",
+        }
+    }
+    fn licensee_code(&self) -> String {
+        match self.header.licensee {
+            Licensee::Old(code) => format!("{:02x}", code),
+            Licensee::New(ref code) => code.clone(),
+        }
+    }
+    ///Controller name, or None if the cartridge type can't be emulated
+    fn controller_name(&self) -> Option<String> {
+        self.header.model.map(|x| format!("{:?}", x))
+    }
+    fn feature_names(&self) -> Vec<&'static str> {
+        let features = &self.header.features;
+        let mut names = Vec::new();
+        if features.ram {
+            names.push("ram");
+        }
+        if features.battery {
+            names.push("battery");
+        }
+        if features.timer {
+            names.push("timer");
+        }
+        if features.rumble {
+            names.push("rumble");
+        }
+        names
+    }
+    ///Single line json object
+    pub fn to_json(&self) -> String {
+        let header = &self.header;
+        let features: Vec<String> = self.feature_names().iter().map(|x| json_string(x)).collect();
+        let warnings: Vec<String> = header
+            .warnings
+            .iter()
+            .map(|x| json_string(&x.to_string()))
+            .collect();
+        let fields = vec![
+            ("path", json_string(&self.path)),
+            ("title", json_string(&header.title)),
+            (
+                "manufacturer_code",
+                json_option(header.manufacturer_code.as_ref().map(|x| json_string(x))),
+            ),
+            ("licensee_code", json_string(&self.licensee_code())),
+            ("licensee", json_option(header.licensee.name().map(json_string))),
+            ("cartridge_type", header.type_code.to_string()),
+            (
+                "cartridge_type_name",
+                json_option(cartridge_type_name(header.type_code).map(json_string)),
+            ),
+            ("mbc", json_option(self.controller_name().map(|x| json_string(&x)))),
+            ("emulated", header.model.is_some().to_string()),
+            ("features", format!("[{}]", features.join(","))),
+            ("rom_size_code", header.rom_size_code.to_string()),
+            ("rom_size_kb", header.rom_size_kb.to_string()),
+            ("rom_banks", header.rom_banks.to_string()),
+            ("file_size", self.file_size.to_string()),
+            ("file_banks", self.file_banks.to_string()),
+            ("ram_size_kb", header.ram_size_kb.to_string()),
+            ("ram_banks", header.ram_banks.to_string()),
+            ("cgb", json_string(self.color_name())),
+            ("sgb", header.sgb.to_string()),
+            ("japanese", header.japanese.to_string()),
+            ("version", header.version.to_string()),
+            ("header_checksum", header.checksum.to_string()),
+            ("header_checksum_valid", self.checksum_valid().to_string()),
+            ("global_checksum", header.global_checksum.to_string()),
+            ("global_checksum_valid", self.global_checksum_valid().to_string()),
+            ("logo_valid", self.logo_valid().to_string()),
+            ("crc32", json_string(&format!("{:08x}", self.crc32))),
+            ("sha1", json_string(&self.sha1)),
+            ("warnings", format!("[{}]", warnings.join(","))),
+        ];
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|&(key, ref val)| format!("{}:{}", json_string(key), val))
+            .collect();
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let header = &self.header;
+        let valid = |x: bool| if x { "ok" } else { "mismatch" };
+        writeln!(f, "File:             {}", self.path)?;
+        writeln!(f, "Title:            {}", header.title)?;
+        if let Some(ref code) = header.manufacturer_code {
+            writeln!(f, "Manufacturer:     {}", code)?;
+        }
+        writeln!(
+            f,
+            "Licensee:         {} ({})",
+            header.licensee.name().unwrap_or("unknown"),
+            self.licensee_code()
+        )?;
+        writeln!(
+            f,
+            "Cartridge type:   {} ({:#04x})",
+            cartridge_type_name(header.type_code).unwrap_or("unknown"),
+            header.type_code
+        )?;
+        writeln!(
+            f,
+            "Controller:       {}",
+            self.controller_name()
+                .unwrap_or_else(|| "not emulated".to_string())
+        )?;
+        let features = self.feature_names();
+        writeln!(
+            f,
+            "Features:         {}",
+            if features.is_empty() {
+                "none".to_string()
+            } else {
+                features.join(", ")
+            }
+        )?;
+        if header.rom_banks == 0 {
+            writeln!(
+                f,
+                "ROM size:         unknown code {:#04x} (file has {} banks in {} bytes)",
+                header.rom_size_code, self.file_banks, self.file_size
+            )?;
+        } else {
+            writeln!(
+                f,
+                "ROM size:         {} KB ({} banks, file has {} in {} bytes)",
+                header.rom_size_kb, header.rom_banks, self.file_banks, self.file_size
+            )?;
+        }
+        writeln!(
+            f,
+            "RAM size:         {} KB ({} banks)",
+            header.ram_size_kb, header.ram_banks
+        )?;
+        writeln!(f, "CGB support:      {}", self.color_name())?;
+        writeln!(f, "SGB support:      {}", if header.sgb { "yes" } else { "no" })?;
+        writeln!(
+            f,
+            "Destination:      {}",
+            if header.japanese { "Japan" } else { "overseas" }
+        )?;
+        writeln!(f, "Version:          {}", header.version)?;
+        writeln!(
+            f,
+            "Header checksum:  {:#04x} (computed {:#04x}, {})",
+            header.checksum,
+            self.computed_checksum,
+            valid(self.checksum_valid())
+        )?;
+        writeln!(
+            f,
+            "Global checksum:  {:#06x} (computed {:#06x}, {})",
+            header.global_checksum,
+            self.computed_global_checksum,
+            valid(self.global_checksum_valid())
+        )?;
+        writeln!(f, "Logo:             {}", valid(self.logo_valid()))?;
+        writeln!(f, "CRC32:            {:08x}", self.crc32)?;
+        write!(f, "SHA-1:            {}", self.sha1)
+    }
+}
+
+///Error entry for roms that couldn't be read, so batch scans still get one line per file
+pub fn json_error(path: &str, error: &RomError) -> String {
+    format!(
+        "{{\"path\":{},\"error\":{}}}",
+        json_string(path),
+        json_string(&error.to_string())
+    )
+}
+
+fn json_option(val: Option<String>) -> String {
+    val.unwrap_or_else(|| "null".to_string())
+}
+
+fn json_string(val: &str) -> String {
+    let mut res = String::with_capacity(val.len() + 2);
+    res.push('"');
+    for c in val.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 || (c as u32) > 0x7e => {
+                res.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
@@ -1,6 +1,7 @@
 use register::*;
 use shared::*;
-use mmu;
+use bus::Bus;
+use std::fmt;
 
 ///```markdown
 /// Instruction name format:
@@ -21,8 +22,8 @@ pub enum Instruction {
     Nop,
     ///Halt until interrupt
     Halt,
-    ///Halt cpu completely
-    Stop,
+    ///Halt cpu completely, the byte after the opcode is padding the cpu skips
+    Stop(u8),
     ///swap register nibbles
     SwapR8(Reg8Name),
     ///swap address nibbles
@@ -61,6 +62,8 @@ pub enum Instruction {
     LdhA8R8(Du8, Reg8Name),
     ///Store value in 8 bit register in address (FF00 + 8 bit register)
     LdhAR8R8(Reg8Name, Reg8Name),
+    ///Load value in address (FF00 + 8 bit register) in 8 bit register
+    LdhR8AR8(Reg8Name, Reg8Name),
     ///Add adds signed 8bit value to value in register and assign to HL
     LdhlR16D8(Reg16Name, Ds8),
     ///Inc value in 8 bit register
@@ -222,16 +225,18 @@ pub enum Instruction {
     RetNf(BitFlag),
     ///Restart at address (simple call to address)
     Rst(Addr),
+    ///Opcode the cpu doesn't have, running one locks it up
+    Illegal(u8),
 }
 
-pub fn decode(mmu: &mmu::Mmu, addr: Addr) -> Instruction {
+pub fn decode<B: Bus + ?Sized>(bus: &B, addr: Addr) -> Instruction {
     //op-code is first byte
-    let op = mmu.read8(addr);
+    let op = bus.read8(addr);
     //op-code may be followed by 01 byte arguments
-    let arg8_0 = mmu.read8(addr + 1);
-    // let arg8_1 = mmu.read8(addr + 2);
+    let arg8_0 = bus.read8(addr.wrapping_add(1));
+    // let arg8_1 = bus.read8(addr + 2);
     //or op-code may be followed by 01 2byte words
-    let arg16 = mmu.read16(addr + 1);
+    let arg16 = bus.read16(addr.wrapping_add(1));
     {
         use self::Instruction::*;
         use register::Reg8Name::*;
@@ -253,7 +258,7 @@ pub fn decode(mmu: &mmu::Mmu, addr: Addr) -> Instruction {
             0x0D => DecR8(C),
             0x0E => LdR8D8(C, arg8_0),
             0x0F => Rrca,
-            0x10 => Stop,
+            0x10 => Stop(arg8_0),
             0x11 => LdR16D16(DE, arg16),
             0x12 => LdAR16R8(DE, A),
             0x13 => IncR16(DE),
@@ -447,7 +452,7 @@ pub fn decode(mmu: &mmu::Mmu, addr: Addr) -> Instruction {
             0xD0 => RetNf(BitFlag::C),
             0xD1 => PopR16(DE),
             0xD2 => JpNfA16(BitFlag::C, arg16),
-            0xD3 => Nop,
+            0xD3 => Illegal(op),
             0xD4 => CallNfA16(BitFlag::C, arg16),
             0xD5 => PushR16(DE),
             0xD6 => SubR8D8(A, arg8_0),
@@ -455,32 +460,32 @@ pub fn decode(mmu: &mmu::Mmu, addr: Addr) -> Instruction {
             0xD8 => RetF(BitFlag::C),
             0xD9 => Reti,
             0xDA => JpFA16(BitFlag::C, arg16),
-            0xDB => Nop,
+            0xDB => Illegal(op),
             0xDC => CallFA16(BitFlag::C, arg16),
-            0xDD => Nop,
+            0xDD => Illegal(op),
             0xDE => SbcR8D8(A, arg8_0),
             0xDF => Rst(0x0018),
             0xE0 => LdhA8R8(arg8_0, A),
             0xE1 => PopR16(HL),
             0xE2 => LdhAR8R8(C, A),
-            0xE3 => Nop,
-            0xE4 => Nop,
+            0xE3 => Illegal(op),
+            0xE4 => Illegal(op),
             0xE5 => PushR16(HL),
             0xE6 => AndR8D8(A, arg8_0),
             0xE7 => Rst(0x0020),
             0xE8 => AddR16D8(SP, arg8_0 as i8),
             0xE9 => JpAR16(HL),
             0xEA => LdA16R8(arg16, A),
-            0xEB => Nop,
-            0xEC => Nop,
-            0xED => Nop,
+            0xEB => Illegal(op),
+            0xEC => Illegal(op),
+            0xED => Illegal(op),
             0xEE => XorR8D8(A, arg8_0),
             0xEF => Rst(0x0028),
             0xF0 => LdhR8A8(A, arg8_0),
             0xF1 => PopR16(AF),
-            0xF2 => Nop,
+            0xF2 => LdhR8AR8(A, C),
             0xF3 => Di,
-            0xF4 => Nop,
+            0xF4 => Illegal(op),
             0xF5 => PushR16(AF),
             0xF6 => OrR8D8(A, arg8_0),
             0xF7 => Rst(0x0030),
@@ -488,6 +493,8 @@ pub fn decode(mmu: &mmu::Mmu, addr: Addr) -> Instruction {
             0xF9 => LdR16R16(SP, HL),
             0xFA => LdR8A16(A, arg16),
             0xFB => Ei,
+            0xFC => Illegal(op),
+            0xFD => Illegal(op),
             0xFE => CpR8D8(A, arg8_0),
             0xFF => Rst(0x0038),
             0xCB => match arg8_0 {
@@ -747,9 +754,7 @@ pub fn decode(mmu: &mmu::Mmu, addr: Addr) -> Instruction {
                 0xFD => SetR8(7, L),
                 0xFE => SetAR16(7, HL),
                 0xFF => SetR8(7, A),
-                _ => Nop,
             },
-            _ => Nop,
         }
     }
 }
@@ -760,7 +765,6 @@ impl Instruction {
         {
             match self {
                 Nop
-                | Stop
                 | Halt
                 | AdcR8AR16(_, _)
                 | AdcR8R8(_, _)
@@ -786,6 +790,7 @@ impl Instruction {
                 | LddAR16R8(_, _)
                 | LddR8AR16(_, _)
                 | LdhAR8R8(_, _)
+                | LdhR8AR8(_, _)
                 | LdiAR16R8(_, _)
                 | LdiR8AR16(_, _)
                 | LdAR16R8(_, _)
@@ -811,9 +816,12 @@ impl Instruction {
                 | Reti
                 | RetF(_)
                 | RetNf(_)
-                | Rst(_) => 1,
+                | Rst(_)
+                | Illegal(_) => 1,
 
-                BitAR16(_, _)
+                //STOP is followed by a padding byte that gets skipped
+                Stop(_)
+                | BitAR16(_, _)
                 | BitR8(_, _)
                 | LdR8D8(_, _)
                 | AdcR8D8(_, _)
@@ -865,4 +873,407 @@ impl Instruction {
             }
         }
     }
+    ///Number of M-cycles (4 T-cycles each) the instruction takes
+    ///Conditional jumps, calls and returns take longer when the branch is taken
+    pub fn get_cycles(&self, branch_taken: bool) -> u8 {
+        use self::Instruction::*;
+        match *self {
+            Nop
+            | Halt
+            | Stop(_)
+            | LdR8R8(_, _)
+            | IncR8(_)
+            | DecR8(_)
+            | Scf
+            | Ccf
+            | Cpl
+            | Rlca
+            | Rla
+            | Rrca
+            | Rra
+            | JpAR16(_)
+            | AddR8R8(_, _)
+            | AdcR8R8(_, _)
+            | SubR8R8(_, _)
+            | SbcR8R8(_, _)
+            | AndR8R8(_, _)
+            | OrR8R8(_, _)
+            | XorR8R8(_, _)
+            | CpR8R8(_, _)
+            | Ei
+            | Di
+            | DaaR8(_)
+            | Illegal(_) => 1,
+
+            LdR8D8(_, _)
+            | LdR16R16(_, _)
+            | LdAR16R8(_, _)
+            | LdR8AR16(_, _)
+            | LdiAR16R8(_, _)
+            | LddAR16R8(_, _)
+            | LdiR8AR16(_, _)
+            | LddR8AR16(_, _)
+            | LdhAR8R8(_, _)
+            | LdhR8AR8(_, _)
+            | IncR16(_)
+            | DecR16(_)
+            | AddR8D8(_, _)
+            | AddR8AR16(_, _)
+            | AddR16R16(_, _)
+            | AdcR8D8(_, _)
+            | AdcR8AR16(_, _)
+            | SubR8D8(_, _)
+            | SubR8AR16(_, _)
+            | SbcR8D8(_, _)
+            | SbcR8AR16(_, _)
+            | AndR8D8(_, _)
+            | AndR8AR16(_, _)
+            | OrR8D8(_, _)
+            | OrR8AR16(_, _)
+            | XorR8D8(_, _)
+            | XorR8AR16(_, _)
+            | CpR8D8(_, _)
+            | CpR8AR16(_, _)
+            | SwapR8(_)
+            | BitR8(_, _)
+            | ResR8(_, _)
+            | SetR8(_, _)
+            | RlcR8(_)
+            | RlR8(_)
+            | RrcR8(_)
+            | RrR8(_)
+            | SlaR8(_)
+            | SraR8(_)
+            | SrlR8(_) => 2,
+
+            LdAR16D8(_, _)
+            | LdhR8A8(_, _)
+            | LdhA8R8(_, _)
+            | LdhlR16D8(_, _)
+            | LdR16D16(_, _)
+            | IncAR16(_)
+            | DecAR16(_)
+            | BitAR16(_, _)
+            | PopR16(_)
+            | JrA8(_) => 3,
+
+            LdR8A16(_, _)
+            | LdA16R8(_, _)
+            | AddR16D8(_, _)
+            | SwapAR16(_)
+            | ResAR16(_, _)
+            | SetAR16(_, _)
+            | RlcAR16(_)
+            | RlAR16(_)
+            | RrcAR16(_)
+            | RrAR16(_)
+            | SlaAR16(_)
+            | SraAR16(_)
+            | SrlAR16(_)
+            | PushR16(_)
+            | JpA16(_)
+            | Ret
+            | Reti
+            | Rst(_) => 4,
+
+            LdA16R16(_, _) => 5,
+            CallA16(_) => 6,
+
+            JrFA8(_, _) | JrNfA8(_, _) => if branch_taken { 3 } else { 2 },
+            JpFA16(_, _) | JpNfA16(_, _) => if branch_taken { 4 } else { 3 },
+            CallFA16(_, _) | CallNfA16(_, _) => if branch_taken { 6 } else { 3 },
+            RetF(_) | RetNf(_) => if branch_taken { 5 } else { 2 },
+        }
+    }
+    ///The opcode bytes decode turns back into this instruction
+    ///None for operands the cpu has no opcode for (like ld f, a or add b, c)
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        use self::Instruction::*;
+        use register::Reg16Name::*;
+        use register::Reg8Name::*;
+        let word = |op: u8, val: u16| {
+            let (hi, lo) = split_u16(val);
+            vec![op, lo, hi]
+        };
+        let cb = |op: u8| vec![0xcb, op];
+        let bytes = match *self {
+            Nop => vec![0x00],
+            Halt => vec![0x76],
+            Stop(n) => vec![0x10, n],
+            SwapR8(ref r) => cb(0x30 | r8_index(r)?),
+            SwapAR16(HL) => cb(0x36),
+            LdR8D8(ref r, n) => vec![0x06 | r8_index(r)? << 3, n],
+            LdR8A16(A, a) => word(0xfa, a),
+            LdA16R8(a, A) => word(0xea, a),
+            LdR8R8(ref to, ref from) => vec![0x40 | r8_index(to)? << 3 | r8_index(from)?],
+            LdR16D16(ref r, n) => word(0x01 | r16_index(r)? << 4, n),
+            LdR16R16(SP, HL) => vec![0xf9],
+            LdAR16R8(BC, A) => vec![0x02],
+            LdAR16R8(DE, A) => vec![0x12],
+            LdAR16R8(HL, ref r) => vec![0x70 | r8_index(r)?],
+            LdAR16D8(HL, n) => vec![0x36, n],
+            LdR8AR16(A, BC) => vec![0x0a],
+            LdR8AR16(A, DE) => vec![0x1a],
+            LdR8AR16(ref r, HL) => vec![0x46 | r8_index(r)? << 3],
+            LdA16R16(a, SP) => word(0x08, a),
+            LdiAR16R8(HL, A) => vec![0x22],
+            LddAR16R8(HL, A) => vec![0x32],
+            LdiR8AR16(A, HL) => vec![0x2a],
+            LddR8AR16(A, HL) => vec![0x3a],
+            LdhR8A8(A, n) => vec![0xf0, n],
+            LdhA8R8(n, A) => vec![0xe0, n],
+            LdhAR8R8(C, A) => vec![0xe2],
+            LdhR8AR8(A, C) => vec![0xf2],
+            LdhlR16D8(SP, e) => vec![0xf8, e as u8],
+            IncR8(ref r) => vec![0x04 | r8_index(r)? << 3],
+            IncR16(ref r) => vec![0x03 | r16_index(r)? << 4],
+            IncAR16(HL) => vec![0x34],
+            DecR8(ref r) => vec![0x05 | r8_index(r)? << 3],
+            DecR16(ref r) => vec![0x0b | r16_index(r)? << 4],
+            DecAR16(HL) => vec![0x35],
+            Scf => vec![0x37],
+            Ccf => vec![0x3f],
+            BitR8(b, ref r) if b < 8 => cb(0x40 | b << 3 | r8_index(r)?),
+            BitAR16(b, HL) if b < 8 => cb(0x46 | b << 3),
+            ResR8(b, ref r) if b < 8 => cb(0x80 | b << 3 | r8_index(r)?),
+            ResAR16(b, HL) if b < 8 => cb(0x86 | b << 3),
+            SetR8(b, ref r) if b < 8 => cb(0xc0 | b << 3 | r8_index(r)?),
+            SetAR16(b, HL) if b < 8 => cb(0xc6 | b << 3),
+            Cpl => vec![0x2f],
+            Rlca => vec![0x07],
+            Rla => vec![0x17],
+            Rrca => vec![0x0f],
+            Rra => vec![0x1f],
+            RlcR8(ref r) => cb(r8_index(r)?),
+            RlcAR16(HL) => cb(0x06),
+            RrcR8(ref r) => cb(0x08 | r8_index(r)?),
+            RrcAR16(HL) => cb(0x0e),
+            RlR8(ref r) => cb(0x10 | r8_index(r)?),
+            RlAR16(HL) => cb(0x16),
+            RrR8(ref r) => cb(0x18 | r8_index(r)?),
+            RrAR16(HL) => cb(0x1e),
+            SlaR8(ref r) => cb(0x20 | r8_index(r)?),
+            SlaAR16(HL) => cb(0x26),
+            SraR8(ref r) => cb(0x28 | r8_index(r)?),
+            SraAR16(HL) => cb(0x2e),
+            SrlR8(ref r) => cb(0x38 | r8_index(r)?),
+            SrlAR16(HL) => cb(0x3e),
+            JpA16(a) => word(0xc3, a),
+            JpAR16(HL) => vec![0xe9],
+            JpFA16(ref flag, a) => word(0xc2 | condition_index(flag, true)? << 3, a),
+            JpNfA16(ref flag, a) => word(0xc2 | condition_index(flag, false)? << 3, a),
+            JrA8(e) => vec![0x18, e as u8],
+            JrFA8(ref flag, e) => vec![0x20 | condition_index(flag, true)? << 3, e as u8],
+            JrNfA8(ref flag, e) => vec![0x20 | condition_index(flag, false)? << 3, e as u8],
+            AddR8R8(A, ref r) => vec![0x80 | r8_index(r)?],
+            AddR8D8(A, n) => vec![0xc6, n],
+            AddR8AR16(A, HL) => vec![0x86],
+            AddR16R16(HL, ref r) => vec![0x09 | r16_index(r)? << 4],
+            AddR16D8(SP, e) => vec![0xe8, e as u8],
+            AdcR8R8(A, ref r) => vec![0x88 | r8_index(r)?],
+            AdcR8D8(A, n) => vec![0xce, n],
+            AdcR8AR16(A, HL) => vec![0x8e],
+            SubR8R8(A, ref r) => vec![0x90 | r8_index(r)?],
+            SubR8D8(A, n) => vec![0xd6, n],
+            SubR8AR16(A, HL) => vec![0x96],
+            SbcR8R8(A, ref r) => vec![0x98 | r8_index(r)?],
+            SbcR8AR16(A, HL) => vec![0x9e],
+            SbcR8D8(A, n) => vec![0xde, n],
+            AndR8R8(A, ref r) => vec![0xa0 | r8_index(r)?],
+            AndR8D8(A, n) => vec![0xe6, n],
+            AndR8AR16(A, HL) => vec![0xa6],
+            OrR8R8(A, ref r) => vec![0xb0 | r8_index(r)?],
+            OrR8D8(A, n) => vec![0xf6, n],
+            OrR8AR16(A, HL) => vec![0xb6],
+            XorR8R8(A, ref r) => vec![0xa8 | r8_index(r)?],
+            XorR8D8(A, n) => vec![0xee, n],
+            XorR8AR16(A, HL) => vec![0xae],
+            Ei => vec![0xfb],
+            Di => vec![0xf3],
+            CpR8R8(A, ref r) => vec![0xb8 | r8_index(r)?],
+            CpR8AR16(A, HL) => vec![0xbe],
+            CpR8D8(A, n) => vec![0xfe, n],
+            DaaR8(A) => vec![0x27],
+            PushR16(ref r) => vec![0xc5 | stack_r16_index(r)? << 4],
+            PopR16(ref r) => vec![0xc1 | stack_r16_index(r)? << 4],
+            CallA16(a) => word(0xcd, a),
+            CallFA16(ref flag, a) => word(0xc4 | condition_index(flag, true)? << 3, a),
+            CallNfA16(ref flag, a) => word(0xc4 | condition_index(flag, false)? << 3, a),
+            Ret => vec![0xc9],
+            Reti => vec![0xd9],
+            RetF(ref flag) => vec![0xc0 | condition_index(flag, true)? << 3],
+            RetNf(ref flag) => vec![0xc0 | condition_index(flag, false)? << 3],
+            //the vector is stored in bits 3-5 of the opcode
+            Rst(a) if a & !0x38 == 0 => vec![0xc7 | a as u8],
+            //only the 11 holes in the opcode table, anything else decodes as a real instruction
+            Illegal(op)
+                if matches!(
+                    op,
+                    0xd3 | 0xdb | 0xdd | 0xe3 | 0xe4 | 0xeb | 0xec | 0xed | 0xf4 | 0xfc | 0xfd
+                ) =>
+            {
+                vec![op]
+            }
+            _ => return None,
+        };
+        Some(bytes)
+    }
+}
+
+///Register number in the 3 bit operand fields of an opcode, [hl] takes the missing 6
+fn r8_index(reg: &Reg8Name) -> Option<u8> {
+    match *reg {
+        Reg8Name::B => Some(0),
+        Reg8Name::C => Some(1),
+        Reg8Name::D => Some(2),
+        Reg8Name::E => Some(3),
+        Reg8Name::H => Some(4),
+        Reg8Name::L => Some(5),
+        Reg8Name::A => Some(7),
+        //f can't be an operand
+        Reg8Name::F => None,
+    }
+}
+///Register pair number in bits 4-5 of the 16 bit loads and arithmetic
+fn r16_index(reg: &Reg16Name) -> Option<u8> {
+    match *reg {
+        Reg16Name::BC => Some(0),
+        Reg16Name::DE => Some(1),
+        Reg16Name::HL => Some(2),
+        Reg16Name::SP => Some(3),
+        _ => None,
+    }
+}
+///Same as r16_index, but push and pop have af in place of sp
+fn stack_r16_index(reg: &Reg16Name) -> Option<u8> {
+    match *reg {
+        Reg16Name::AF => Some(3),
+        //sp can't be pushed or popped
+        Reg16Name::SP => None,
+        ref x => r16_index(x),
+    }
+}
+///Condition number in bits 3-4 of conditional jumps, calls and returns (nz, z, nc, c)
+fn condition_index(flag: &BitFlag, set: bool) -> Option<u8> {
+    let index = match *flag {
+        BitFlag::Z => 0,
+        BitFlag::C => 2,
+        _ => return None,
+    };
+    Some(index | set as u8)
+}
+
+///RGBDS syntax, relative jumps are written relative to the start of the instruction (@)
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Instruction::*;
+        match *self {
+            Nop => write!(f, "nop"),
+            Halt => write!(f, "halt"),
+            Stop(0) => write!(f, "stop"),
+            Stop(n) => write!(f, "stop ${:02x}", n),
+            SwapR8(ref r) => write!(f, "swap {}", r),
+            SwapAR16(ref r) => write!(f, "swap [{}]", r),
+            LdR8D8(ref r, n) => write!(f, "ld {}, ${:02x}", r, n),
+            LdR8A16(ref r, a) => write!(f, "ld {}, [${:04x}]", r, a),
+            LdA16R8(a, ref r) => write!(f, "ld [${:04x}], {}", a, r),
+            LdR8R8(ref r0, ref r1) => write!(f, "ld {}, {}", r0, r1),
+            LdR16D16(ref r, n) => write!(f, "ld {}, ${:04x}", r, n),
+            LdR16R16(ref r0, ref r1) => write!(f, "ld {}, {}", r0, r1),
+            LdAR16R8(ref r0, ref r1) => write!(f, "ld [{}], {}", r0, r1),
+            LdAR16D8(ref r, n) => write!(f, "ld [{}], ${:02x}", r, n),
+            LdR8AR16(ref r0, ref r1) => write!(f, "ld {}, [{}]", r0, r1),
+            LdA16R16(a, ref r) => write!(f, "ld [${:04x}], {}", a, r),
+            LdiAR16R8(ref r0, ref r1) => write!(f, "ld [{}+], {}", r0, r1),
+            LddAR16R8(ref r0, ref r1) => write!(f, "ld [{}-], {}", r0, r1),
+            LdiR8AR16(ref r0, ref r1) => write!(f, "ld {}, [{}+]", r0, r1),
+            LddR8AR16(ref r0, ref r1) => write!(f, "ld {}, [{}-]", r0, r1),
+            LdhR8A8(ref r, n) => write!(f, "ldh {}, [$ff{:02x}]", r, n),
+            LdhA8R8(n, ref r) => write!(f, "ldh [$ff{:02x}], {}", n, r),
+            LdhAR8R8(ref r0, ref r1) => write!(f, "ldh [{}], {}", r0, r1),
+            LdhR8AR8(ref r0, ref r1) => write!(f, "ldh {}, [{}]", r0, r1),
+            LdhlR16D8(ref r, e) => write!(f, "ld hl, {}{:+}", r, e),
+            IncR8(ref r) => write!(f, "inc {}", r),
+            IncR16(ref r) => write!(f, "inc {}", r),
+            IncAR16(ref r) => write!(f, "inc [{}]", r),
+            DecR8(ref r) => write!(f, "dec {}", r),
+            DecR16(ref r) => write!(f, "dec {}", r),
+            DecAR16(ref r) => write!(f, "dec [{}]", r),
+            Scf => write!(f, "scf"),
+            Ccf => write!(f, "ccf"),
+            BitR8(b, ref r) => write!(f, "bit {}, {}", b, r),
+            BitAR16(b, ref r) => write!(f, "bit {}, [{}]", b, r),
+            ResR8(b, ref r) => write!(f, "res {}, {}", b, r),
+            ResAR16(b, ref r) => write!(f, "res {}, [{}]", b, r),
+            SetR8(b, ref r) => write!(f, "set {}, {}", b, r),
+            SetAR16(b, ref r) => write!(f, "set {}, [{}]", b, r),
+            Cpl => write!(f, "cpl"),
+            Rlca => write!(f, "rlca"),
+            Rla => write!(f, "rla"),
+            Rrca => write!(f, "rrca"),
+            Rra => write!(f, "rra"),
+            RlcR8(ref r) => write!(f, "rlc {}", r),
+            RlcAR16(ref r) => write!(f, "rlc [{}]", r),
+            RlR8(ref r) => write!(f, "rl {}", r),
+            RlAR16(ref r) => write!(f, "rl [{}]", r),
+            RrcR8(ref r) => write!(f, "rrc {}", r),
+            RrcAR16(ref r) => write!(f, "rrc [{}]", r),
+            RrR8(ref r) => write!(f, "rr {}", r),
+            RrAR16(ref r) => write!(f, "rr [{}]", r),
+            SlaR8(ref r) => write!(f, "sla {}", r),
+            SlaAR16(ref r) => write!(f, "sla [{}]", r),
+            SraR8(ref r) => write!(f, "sra {}", r),
+            SraAR16(ref r) => write!(f, "sra [{}]", r),
+            SrlR8(ref r) => write!(f, "srl {}", r),
+            SrlAR16(ref r) => write!(f, "srl [{}]", r),
+            JpA16(a) => write!(f, "jp ${:04x}", a),
+            JpAR16(ref r) => write!(f, "jp {}", r),
+            JpFA16(ref flag, a) => write!(f, "jp {}, ${:04x}", flag, a),
+            JpNfA16(ref flag, a) => write!(f, "jp n{}, ${:04x}", flag, a),
+            //the offset counts from the end of the 2 byte instruction
+            JrA8(e) => write!(f, "jr @{:+}", e as i16 + 2),
+            JrFA8(ref flag, e) => write!(f, "jr {}, @{:+}", flag, e as i16 + 2),
+            JrNfA8(ref flag, e) => write!(f, "jr n{}, @{:+}", flag, e as i16 + 2),
+            AddR8R8(ref r0, ref r1) => write!(f, "add {}, {}", r0, r1),
+            AddR8D8(ref r, n) => write!(f, "add {}, ${:02x}", r, n),
+            AddR8AR16(ref r0, ref r1) => write!(f, "add {}, [{}]", r0, r1),
+            AddR16R16(ref r0, ref r1) => write!(f, "add {}, {}", r0, r1),
+            AddR16D8(ref r, e) => write!(f, "add {}, {}", r, e),
+            AdcR8R8(ref r0, ref r1) => write!(f, "adc {}, {}", r0, r1),
+            AdcR8D8(ref r, n) => write!(f, "adc {}, ${:02x}", r, n),
+            AdcR8AR16(ref r0, ref r1) => write!(f, "adc {}, [{}]", r0, r1),
+            SubR8R8(ref r0, ref r1) => write!(f, "sub {}, {}", r0, r1),
+            SubR8D8(ref r, n) => write!(f, "sub {}, ${:02x}", r, n),
+            SubR8AR16(ref r0, ref r1) => write!(f, "sub {}, [{}]", r0, r1),
+            SbcR8R8(ref r0, ref r1) => write!(f, "sbc {}, {}", r0, r1),
+            SbcR8AR16(ref r0, ref r1) => write!(f, "sbc {}, [{}]", r0, r1),
+            SbcR8D8(ref r, n) => write!(f, "sbc {}, ${:02x}", r, n),
+            AndR8R8(ref r0, ref r1) => write!(f, "and {}, {}", r0, r1),
+            AndR8D8(ref r, n) => write!(f, "and {}, ${:02x}", r, n),
+            AndR8AR16(ref r0, ref r1) => write!(f, "and {}, [{}]", r0, r1),
+            OrR8R8(ref r0, ref r1) => write!(f, "or {}, {}", r0, r1),
+            OrR8D8(ref r, n) => write!(f, "or {}, ${:02x}", r, n),
+            OrR8AR16(ref r0, ref r1) => write!(f, "or {}, [{}]", r0, r1),
+            XorR8R8(ref r0, ref r1) => write!(f, "xor {}, {}", r0, r1),
+            XorR8D8(ref r, n) => write!(f, "xor {}, ${:02x}", r, n),
+            XorR8AR16(ref r0, ref r1) => write!(f, "xor {}, [{}]", r0, r1),
+            Ei => write!(f, "ei"),
+            Di => write!(f, "di"),
+            CpR8R8(ref r0, ref r1) => write!(f, "cp {}, {}", r0, r1),
+            CpR8AR16(ref r0, ref r1) => write!(f, "cp {}, [{}]", r0, r1),
+            CpR8D8(ref r, n) => write!(f, "cp {}, ${:02x}", r, n),
+            DaaR8(_) => write!(f, "daa"),
+            PushR16(ref r) => write!(f, "push {}", r),
+            PopR16(ref r) => write!(f, "pop {}", r),
+            CallA16(a) => write!(f, "call ${:04x}", a),
+            CallFA16(ref flag, a) => write!(f, "call {}, ${:04x}", flag, a),
+            CallNfA16(ref flag, a) => write!(f, "call n{}, ${:04x}", flag, a),
+            Ret => write!(f, "ret"),
+            Reti => write!(f, "reti"),
+            RetF(ref flag) => write!(f, "ret {}", flag),
+            RetNf(ref flag) => write!(f, "ret n{}", flag),
+            Rst(a) => write!(f, "rst ${:02x}", a),
+            Illegal(op) => write!(f, "db ${:02x}", op),
+        }
+    }
 }
@@ -0,0 +1,56 @@
+///Interrupt sources, in priority order (VBlank is serviced first)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    ///LCD entered vertical blank
+    VBlank,
+    ///LCD STAT conditions (LYC=LY, mode 0/1/2)
+    Stat,
+    ///TIMA overflowed
+    Timer,
+    ///serial transfer finished
+    Serial,
+    ///a joypad line went from high to low
+    Joypad,
+}
+
+///Only the lower 5 bits of IE and IF are wired to interrupt sources
+pub const INTERRUPT_MASK: u8 = 0b0001_1111;
+
+impl Interrupt {
+    ///Every interrupt, highest priority first
+    pub const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::Stat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    ///Bit in IE/IF belonging to this interrupt
+    pub fn bit(self) -> u8 {
+        match self {
+            Interrupt::VBlank => 0b0000_0001,
+            Interrupt::Stat => 0b0000_0010,
+            Interrupt::Timer => 0b0000_0100,
+            Interrupt::Serial => 0b0000_1000,
+            Interrupt::Joypad => 0b0001_0000,
+        }
+    }
+    ///Address the cpu jumps to when servicing this interrupt
+    pub fn vector(self) -> u16 {
+        match self {
+            Interrupt::VBlank => 0x40,
+            Interrupt::Stat => 0x48,
+            Interrupt::Timer => 0x50,
+            Interrupt::Serial => 0x58,
+            Interrupt::Joypad => 0x60,
+        }
+    }
+    ///Highest priority interrupt set in the given IE & IF value
+    pub fn highest_priority(pending: u8) -> Option<Interrupt> {
+        Interrupt::ALL
+            .iter()
+            .cloned()
+            .find(|x| pending & x.bit() != 0)
+    }
+}
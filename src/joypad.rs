@@ -0,0 +1,152 @@
+///The eight buttons, wired as two groups of four lines
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    ///Bit in a ButtonSet, directions in the low nibble and buttons in the high one
+    ///Within each nibble the order matches the P10-P13 lines
+    fn bit(self) -> u8 {
+        match self {
+            Button::Right => 0x01,
+            Button::Left => 0x02,
+            Button::Up => 0x04,
+            Button::Down => 0x08,
+            Button::A => 0x10,
+            Button::B => 0x20,
+            Button::Select => 0x40,
+            Button::Start => 0x80,
+        }
+    }
+}
+
+///Which buttons are held down
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ButtonSet(u8);
+
+impl ButtonSet {
+    pub fn new() -> Self {
+        ButtonSet(0)
+    }
+    pub fn contains(self, button: Button) -> bool {
+        self.0 & button.bit() != 0
+    }
+    pub fn insert(&mut self, button: Button) {
+        self.0 |= button.bit();
+    }
+    pub fn remove(&mut self, button: Button) {
+        self.0 &= !button.bit();
+    }
+    ///Returns the set with the button added, for building sets in one expression
+    pub fn with(mut self, button: Button) -> Self {
+        self.insert(button);
+        self
+    }
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+///P1/JOYP (0xff00)
+///Bits 4 and 5 select the direction and button lines (0 = selected), bits 0-3 read 0 for each pressed button
+pub struct Joypad {
+    ///select bits as last written, only bits 4-5 are kept
+    select: u8,
+    pressed: ButtonSet,
+    ///an input line went from high to low since the interrupt was last passed on
+    interrupt: bool,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad {
+            select: 0x30,
+            pressed: ButtonSet::new(),
+            interrupt: false,
+        }
+    }
+    pub fn read8(&self) -> u8 {
+        //unused upper bits read as 1
+        0xc0 | self.select | (!self.lines() & 0x0f)
+    }
+    ///Only the select bits are writable, selecting a line with a button held counts as a press
+    pub fn write8(&mut self, dat: u8) {
+        let old = self.lines();
+        self.select = dat & 0x30;
+        self.check_edge(old);
+    }
+
+    pub fn press(&mut self, button: Button) {
+        let mut state = self.pressed;
+        state.insert(button);
+        self.set_state(state);
+    }
+    pub fn release(&mut self, button: Button) {
+        let mut state = self.pressed;
+        state.remove(button);
+        self.set_state(state);
+    }
+    ///Replaces every button's state at once
+    pub fn set_state(&mut self, state: ButtonSet) {
+        let old = self.lines();
+        self.pressed = state;
+        self.check_edge(old);
+    }
+    pub fn get_state(&self) -> ButtonSet {
+        self.pressed
+    }
+
+    ///A pressed button is pulling one of the selected lines low, which is what wakes the cpu from STOP
+    pub fn line_low(&self) -> bool {
+        self.lines() != 0
+    }
+    ///Returns true once for each time the joypad interrupt was triggered
+    pub fn take_interrupt(&mut self) -> bool {
+        let interrupt = self.interrupt;
+        self.interrupt = false;
+        interrupt
+    }
+
+    ///P10-P13 as active high (1 = pulled low by a selected, pressed button)
+    fn lines(&self) -> u8 {
+        let mut lines = 0;
+        if self.select & 0x10 == 0 {
+            lines |= self.pressed.0 & 0x0f;
+        }
+        if self.select & 0x20 == 0 {
+            lines |= self.pressed.0 >> 4;
+        }
+        lines
+    }
+    ///The interrupt fires when any line goes from high to low
+    fn check_edge(&mut self, old: u8) {
+        if !old & self.lines() != 0 {
+            self.interrupt = true;
+        }
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
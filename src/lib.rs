@@ -0,0 +1,28 @@
+#[macro_use]
+extern crate log;
+extern crate crc32fast;
+extern crate flate2;
+extern crate sha1_smol;
+extern crate zip;
+
+pub mod apu;
+pub mod audio;
+pub mod bus;
+pub mod cpu;
+pub mod disasm;
+pub mod gameboy;
+pub mod info;
+pub mod instructions;
+pub mod interrupt;
+pub mod joypad;
+pub mod licensee;
+pub mod mmu;
+pub mod mooneye;
+pub mod patch;
+pub mod ppu;
+pub mod register;
+pub mod rom;
+pub mod save;
+pub mod serial;
+pub mod shared;
+pub mod timer;
@@ -1,18 +1,421 @@
-mod shared;
-mod instructions;
-mod rom;
-mod cpu;
-mod mmu;
-mod register;
-#[macro_use]
-extern crate log;
+extern crate bouzu;
+
+use bouzu::audio::{AudioRecorder, AudioSink, RawPcmWriter, WavWriter};
+use bouzu::disasm::{self, Disassembler};
+use bouzu::info::{self, RomInfo};
+use bouzu::shared::CLOCK_SPEED;
+use bouzu::{apu, gameboy, mooneye, ppu, rom, save};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "usage:
+    bouzu [options] <rom>           run a rom
+    bouzu info [--json] <rom>...    print cartridge information
+    bouzu mooneye <rom|dir>...      run mooneye test roms and report which pass
+    bouzu disasm [options] <rom>    disassemble part of a rom
+
+run options:
+    --fifo-ppu                      use the pixel FIFO renderer
+    --frames <n>                    stop after n frames
+    --seconds <s>                   stop after s seconds of emulated time
+    --wav <file>                    record the sound to a 16 bit stereo .wav (needs --frames or --seconds)
+    --raw <file>                    record the sound as headerless 16 bit little endian stereo samples
+    --channels                      also record each sound channel to <file>.ch1-4, in mono
+    --sample-rate <hz>              recording sample rate, 44100 by default
+
+disasm options:
+    --bank <n>                      rom bank to disassemble, 0 by default (banks above 0 are mapped at 0x4000)
+    --start <addr>                  first address, the start of the bank by default (0x/$ for hex)
+    --len <n>                       number of bytes, up to the end of the bank by default
+    --sym <file>                    RGBDS .sym file to take label names from";
+
+///Options for running a rom, from the command line
+struct RunOptions {
+    rom: String,
+    fifo_ppu: bool,
+    ///T-cycles to run for, forever if None
+    limit: Option<u64>,
+    wav: Option<String>,
+    raw: Option<String>,
+    channels: bool,
+    sample_rate: u32,
+}
+
+impl RunOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = RunOptions {
+            rom: String::new(),
+            fifo_ppu: false,
+            limit: None,
+            wav: None,
+            raw: None,
+            channels: false,
+            sample_rate: apu::DEFAULT_SAMPLE_RATE,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{} needs a value", arg))
+            };
+            match arg.as_str() {
+                "--fifo-ppu" => options.fifo_ppu = true,
+                "--channels" => options.channels = true,
+                "--wav" => options.wav = Some(value()?),
+                "--raw" => options.raw = Some(value()?),
+                "--frames" => {
+                    let frames: u64 = parse_number(arg, &value()?)?;
+                    options.limit = Some(frames * gameboy::CYCLES_PER_FRAME as u64);
+                }
+                "--seconds" => {
+                    let seconds: f64 = parse_number(arg, &value()?)?;
+                    if seconds < 0.0 {
+                        return Err(format!("{} can't be negative", arg));
+                    }
+                    options.limit = Some((seconds * CLOCK_SPEED as f64) as u64);
+                }
+                "--sample-rate" => {
+                    options.sample_rate = parse_number(arg, &value()?)?;
+                    if options.sample_rate == 0 {
+                        return Err(format!("{} can't be 0", arg));
+                    }
+                }
+                x if x.starts_with("--") => return Err(format!("unknown option {}", x)),
+                x if options.rom.is_empty() => options.rom = x.to_string(),
+                x => return Err(format!("unexpected argument {}", x)),
+            }
+        }
+        if options.rom.is_empty() {
+            return Err("no rom given".to_string());
+        }
+        if options.wav.is_some() && options.raw.is_some() {
+            return Err("--wav and --raw can't be used together".to_string());
+        }
+        //the header can't be finished if we never stop
+        if options.wav.is_some() && options.limit.is_none() {
+            return Err("--wav needs --frames or --seconds".to_string());
+        }
+        if options.channels && options.wav.is_none() && options.raw.is_none() {
+            return Err("--channels needs --wav or --raw".to_string());
+        }
+        Ok(options)
+    }
+}
+
+///Options for disassembling a rom, from the command line
+struct DisasmOptions {
+    rom: String,
+    bank: usize,
+    start: Option<u16>,
+    len: Option<usize>,
+    sym: Option<String>,
+}
+
+impl DisasmOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = DisasmOptions {
+            rom: String::new(),
+            bank: 0,
+            start: None,
+            len: None,
+            sym: None,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{} needs a value", arg))
+            };
+            match arg.as_str() {
+                "--bank" => options.bank = parse_number(arg, &value()?)?,
+                "--start" => options.start = Some(parse_address(arg, &value()?)?),
+                "--len" => options.len = Some(parse_address(arg, &value()?)? as usize),
+                "--sym" => options.sym = Some(value()?),
+                x if x.starts_with("--") => return Err(format!("unknown option {}", x)),
+                x if options.rom.is_empty() => options.rom = x.to_string(),
+                x => return Err(format!("unexpected argument {}", x)),
+            }
+        }
+        if options.rom.is_empty() {
+            return Err("no rom given".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got {}", option, value))
+}
+
+///Decimal, or hex with a 0x or $ prefix
+fn parse_address(option: &str, value: &str) -> Result<u16, String> {
+    let hex = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix('$'));
+    match hex {
+        Some(hex) => u16::from_str_radix(hex, 16)
+            .map_err(|_| format!("{} expects an address, got {}", option, value)),
+        None => parse_number(option, value),
+    }
+}
 
 fn main() {
-    // rom::print_rom("roms/real/tetris.gb");
-    let rom = rom::load_rom("roms/real/tetris.gb").expect("Couldn't load rom");
-    let mut mmu = mmu::Mmu::new(rom);
-    let mut cpu = cpu::Cpu::new();
-    loop {
-        cpu.step(&mut mmu);
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|x| x.as_str()) {
+        Some("info") => process::exit(info_command(&args[1..])),
+        Some("mooneye") => process::exit(mooneye_command(&args[1..])),
+        Some("disasm") => process::exit(disasm_command(&args[1..])),
+        Some(_) => process::exit(run(&args)),
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+///Returns the exit code once the gameboy is dropped, so the save file always gets its last flush
+fn run(args: &[String]) -> i32 {
+    let options = match RunOptions::parse(args) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    let path = &options.rom;
+    let renderer = if options.fifo_ppu {
+        fifo_renderer()
+    } else {
+        ppu::Renderer::Scanline
+    };
+    let mut recorder = match audio_recorder(&options) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("Couldn't create audio output: {}", e);
+            return 1;
+        }
+    };
+    let rom = match rom::load_rom(path) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("Couldn't load {}: {}", path, e);
+            return 1;
+        }
+    };
+    let mut gameboy = gameboy::GameBoy::new(rom);
+    let mmu = gameboy.get_mmu_mut();
+    mmu.get_ppu_mut().set_renderer(renderer);
+    if let Some(rtc) = mmu.get_cartridge_mut().get_rtc_mut() {
+        rtc.set_clock_source(rom::ClockSource::Host);
+    }
+    mmu.attach_save_file(save::SaveFile::for_rom(path))
+        .expect("Couldn't load save file");
+    let apu = mmu.get_apu_mut();
+    apu.set_sample_rate(options.sample_rate);
+    apu.set_channel_capture(options.channels);
+
+    while options.limit.is_none_or(|x| gameboy.get_cycles() < x) {
+        match options.limit {
+            //don't run a whole frame past the end
+            Some(limit) if limit - gameboy.get_cycles() < gameboy::CYCLES_PER_FRAME as u64 => {
+                gameboy.run_cycles((limit - gameboy.get_cycles()) as u32);
+            }
+            _ => gameboy.run_frame(),
+        }
+        if let Some(ref mut recorder) = recorder {
+            if let Err(e) = recorder.record(gameboy.get_mmu_mut().get_apu_mut()) {
+                eprintln!("Couldn't write audio: {}", e);
+                return 1;
+            }
+        }
+    }
+    if let Some(ref mut recorder) = recorder {
+        if let Err(e) = recorder.finish() {
+            eprintln!("Couldn't write audio: {}", e);
+            return 1;
+        }
+    }
+    0
+}
+
+///Opens the audio files asked for, if any
+fn audio_recorder(options: &RunOptions) -> io::Result<Option<AudioRecorder>> {
+    let rate = options.sample_rate;
+    let (path, wav) = match (&options.wav, &options.raw) {
+        (Some(path), _) => (path, true),
+        (_, Some(path)) => (path, false),
+        _ => return Ok(None),
+    };
+    let open = |path: &Path, channels: u16| -> io::Result<Box<dyn AudioSink>> {
+        if wav {
+            Ok(Box::new(WavWriter::create(path, rate, channels)?))
+        } else {
+            Ok(Box::new(RawPcmWriter::create(path)?))
+        }
+    };
+    let path = Path::new(path);
+    let recorder = AudioRecorder::new(open(path, 2)?);
+    if !options.channels {
+        return Ok(Some(recorder));
+    }
+    //out.wav -> out.ch1.wav and so on
+    let channel_path = |i: usize| match path.extension() {
+        Some(ext) => path.with_extension(format!("ch{}.{}", i, ext.to_string_lossy())),
+        None => path.with_extension(format!("ch{}", i)),
+    };
+    let channels = [
+        open(&channel_path(1), 1)?,
+        open(&channel_path(2), 1)?,
+        open(&channel_path(3), 1)?,
+        open(&channel_path(4), 1)?,
+    ];
+    Ok(Some(recorder.with_channels(channels)))
+}
+
+#[cfg(feature = "fifo-ppu")]
+fn fifo_renderer() -> ppu::Renderer {
+    ppu::Renderer::Fifo
+}
+#[cfg(not(feature = "fifo-ppu"))]
+fn fifo_renderer() -> ppu::Renderer {
+    eprintln!("bouzu was built without the fifo-ppu feature");
+    process::exit(2);
+}
+
+///Prints the header and checksums of each rom, returns the exit code
+fn info_command(args: &[String]) -> i32 {
+    let json = args.iter().any(|x| x == "--json");
+    let paths: Vec<&String> = args.iter().filter(|x| !x.starts_with("--")).collect();
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+    let mut code = 0;
+    for (i, path) in paths.iter().enumerate() {
+        match RomInfo::load(path) {
+            Ok(rom) if json => println!("{}", rom.to_json()),
+            Ok(rom) => {
+                if i > 0 {
+                    println!();
+                }
+                println!("{}", rom);
+            }
+            Err(e) => {
+                if json {
+                    println!("{}", info::json_error(path, &e));
+                } else {
+                    eprintln!("{}: {}", path, e);
+                }
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+///Runs each mooneye test rom (or every rom in a directory), returns the exit code
+fn mooneye_command(args: &[String]) -> i32 {
+    if args.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+    let mut roms = Vec::new();
+    for arg in args {
+        let path = Path::new(arg);
+        if path.is_dir() {
+            match mooneye::find_roms(path) {
+                Ok(found) => roms.extend(found),
+                Err(e) => {
+                    eprintln!("Couldn't read {}: {}", arg, e);
+                    return 1;
+                }
+            }
+        } else {
+            roms.push(path.to_path_buf());
+        }
+    }
+    let mut passed = 0;
+    for path in &roms {
+        let name = path.display().to_string();
+        match mooneye::run_rom(&name, mooneye::DEFAULT_CYCLE_BUDGET) {
+            Ok(outcome) => {
+                if outcome.passed() {
+                    passed += 1;
+                }
+                println!("{}: {}", name, outcome);
+            }
+            Err(e) => println!("{}: couldn't load: {}", name, e),
+        }
+    }
+    println!("{}/{} passed", passed, roms.len());
+    if passed == roms.len() {
+        0
+    } else {
+        1
+    }
+}
+
+///Prints a listing of part of one rom bank, returns the exit code
+fn disasm_command(args: &[String]) -> i32 {
+    let options = match DisasmOptions::parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    let path = &options.rom;
+    let image = match rom::load_rom_image(path) {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("Couldn't load {}: {}", path, e);
+            return 1;
+        }
+    };
+    let bus = match disasm::map_rom_bank(&image, options.bank) {
+        Some(bus) => bus,
+        None => {
+            eprintln!("{} has no bank {}", path, options.bank);
+            return 1;
+        }
+    };
+    //the part of the address space the bank is visible in
+    let bank_start = if options.bank == 0 { 0 } else { disasm::ROM_BANK_SIZE };
+    let bank_len = (image.len() - options.bank * disasm::ROM_BANK_SIZE).min(disasm::ROM_BANK_SIZE);
+    let bank_end = bank_start + bank_len;
+    let start = options.start.map_or(bank_start, |x| x as usize);
+    if start < bank_start || start >= bank_end {
+        eprintln!(
+            "--start {:#06x} isn't in bank {} ({:#06x}-{:#06x})",
+            start,
+            options.bank,
+            bank_start,
+            bank_end - 1
+        );
+        return 2;
+    }
+    let len = options.len.unwrap_or(bank_end - start).min(bank_end - start);
+
+    let mut disassembler = Disassembler::new();
+    if let Some(ref sym) = options.sym {
+        match fs::read_to_string(sym) {
+            Ok(text) => {
+                disassembler.load_symbols(&text, options.bank);
+            }
+            Err(e) => {
+                eprintln!("Couldn't read {}: {}", sym, e);
+                return 1;
+            }
+        }
     }
+    let lines = disasm::disassemble(&bus, start as u16, len);
+    print!("{}", disassembler.listing(&lines));
+    0
 }
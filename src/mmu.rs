@@ -1,119 +1,250 @@
+use apu;
+use bus::Bus;
+use interrupt::*;
+use joypad;
+use ppu;
 use rom;
-use register;
+use save;
+use serial;
+use timer;
+use std::io;
 use shared::*;
 
 pub struct Mmu {
     ///cartridge provides 0x0000 - 0x7fff in two banks
-    rom: Box<rom::Cartridge>,
-    ///0x8000 - 0x9fff (1fff wide)
-    vram: [u8; 0x1fff],
-    ///0xc000 - 0xcfff (1fff wide) (0xc000 - 0xddff echoed in 0xe000-0xfdff)
-    work_ram_0: [u8; 0x1fff],
-    ///0xd000 - 0xdfff (1fff wide) (1 bank in DMG, 1~7 in CGB) (0xc000 - 0xddff echoed in 0xe000-0xfdff)
-    work_ram_1: [u8; 0x1fff],
-    ///0xfe00 - 0xfe9f (0x9f wide)
-    sprite_table: [u8; 0x9f],
-    ///0xff00 - 0xff7f (0x7f wide)
+    rom: Box<dyn rom::Cartridge>,
+    ///0xc000 - 0xcfff (0x1000 wide) (0xc000 - 0xddff echoed in 0xe000-0xfdff)
+    work_ram_0: [u8; 0x1000],
+    ///0xd000 - 0xdfff (0x1000 wide) (1 bank in DMG, 1~7 in CGB) (0xc000 - 0xddff echoed in 0xe000-0xfdff)
+    work_ram_1: [u8; 0x1000],
+    ///0xff00 - 0xff7f (0x80 wide)
     ///todo: encapsulate IO memory for easier use
-    io_registers: [u8; 0x7f],
-    ///0xff80 - 0xfffe (0x7e wide)
-    hram: [u8; 0x7e],
-    /// 0xffff
-    interrupts: u8,
+    io_registers: [u8; 0x80],
+    ///0xff80 - 0xfffe (0x7f wide)
+    hram: [u8; 0x7f],
+    ///IF (0xff0f), interrupts that have been requested
+    interrupt_flag: u8,
+    ///IE (0xffff), interrupts that are allowed to fire
+    interrupt_enable: u8,
+    ///where battery backed cartridge ram is persisted, if anywhere
+    save: Option<save::SaveFile>,
+    ///OAM DMA in progress, if any
+    dma: Option<OamDma>,
+    ///0xff00
+    joypad: joypad::Joypad,
+    ///0xff01 - 0xff02
+    serial: serial::Serial,
+    ///0xff04 - 0xff07
+    timer: timer::Timer,
+    ///vram (0x8000 - 0x9fff), sprite table (0xfe00 - 0xfe9f) and 0xff40 - 0xff4b
+    ppu: ppu::Ppu,
+    ///0xff10 - 0xff3f
+    apu: apu::Apu,
+}
+
+///Copies 0xa0 bytes from XX00 into the sprite table, one byte per M-cycle
+struct OamDma {
+    source: u16,
+    ///bytes copied so far
+    index: u16,
+    ///T-cycles left over from the last tick
+    cycles: u32,
 }
 
 impl Mmu {
-    pub fn new(rom: Box<rom::Cartridge>) -> Self {
+    pub fn new(rom: Box<dyn rom::Cartridge>) -> Self {
         Mmu {
-            rom: rom,
-            vram: [0; 0x1fff],
-            work_ram_0: [0; 0x1fff],
-            work_ram_1: [0; 0x1fff],
-            sprite_table: [0; 0x9f],
-            io_registers: [0; 0x7f],
-            hram: [0; 0x7e],
-            interrupts: 0,
+            rom,
+            work_ram_0: [0; 0x1000],
+            work_ram_1: [0; 0x1000],
+            io_registers: [0; 0x80],
+            hram: [0; 0x7f],
+            interrupt_flag: 0,
+            interrupt_enable: 0,
+            save: None,
+            dma: None,
+            joypad: joypad::Joypad::new(),
+            serial: serial::Serial::new(),
+            timer: timer::Timer::new(),
+            ppu: ppu::Ppu::new(),
+            apu: apu::Apu::new(apu::DEFAULT_SAMPLE_RATE),
+        }
+    }
+    fn tick_dma(&mut self, cycles: u32) {
+        let mut dma = match self.dma.take() {
+            Some(dma) => dma,
+            None => return,
+        };
+        dma.cycles += cycles;
+        while dma.cycles >= 4 && dma.index < 0xa0 {
+            dma.cycles -= 4;
+            //sources past 0xdfff read from echo ram
+            let source = dma.source.wrapping_add(dma.index);
+            let source = if source >= 0xe000 { source - 0x2000 } else { source };
+            let dat = self.read8(source);
+            self.ppu.write_oam_dma(dma.index as usize, dat);
+            dma.index += 1;
+        }
+        if dma.index < 0xa0 {
+            self.dma = Some(dma);
         }
     }
-    pub fn read8(&self, add: u16) -> u8 {
+    ///Loads battery backed ram from the save file and keeps it up to date from then on
+    ///Returns false if there was nothing to load
+    pub fn attach_save_file(&mut self, mut save: save::SaveFile) -> io::Result<bool> {
+        let loaded = save.load(&mut *self.rom)?;
+        self.save = Some(save);
+        Ok(loaded)
+    }
+    pub fn flush_save(&mut self) -> io::Result<()> {
+        match self.save {
+            Some(ref mut save) => save.flush(&*self.rom),
+            None => Ok(()),
+        }
+    }
+    pub fn get_cartridge_mut(&mut self) -> &mut dyn rom::Cartridge {
+        &mut *self.rom
+    }
+    pub fn get_apu(&self) -> &apu::Apu {
+        &self.apu
+    }
+    pub fn get_apu_mut(&mut self) -> &mut apu::Apu {
+        &mut self.apu
+    }
+    pub fn get_joypad(&self) -> &joypad::Joypad {
+        &self.joypad
+    }
+    pub fn get_joypad_mut(&mut self) -> &mut joypad::Joypad {
+        &mut self.joypad
+    }
+    pub fn get_serial(&self) -> &serial::Serial {
+        &self.serial
+    }
+    pub fn get_serial_mut(&mut self) -> &mut serial::Serial {
+        &mut self.serial
+    }
+    pub fn get_ppu(&self) -> &ppu::Ppu {
+        &self.ppu
+    }
+    pub fn get_ppu_mut(&mut self) -> &mut ppu::Ppu {
+        &mut self.ppu
+    }
+
+    ///Sets the interrupt's bit in IF
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt_flag |= interrupt.bit();
+    }
+}
+
+impl Bus for Mmu {
+    fn read8(&self, add: u16) -> u8 {
         let addr = add as usize;
         match addr {
             //rom memory banks
-            0x0000...0x7fff => self.rom.read8(add),
-            0x8000...0x9fff => self.vram[addr - 0x8000],
+            0x0000..=0x7fff => self.rom.read8(add),
+            0x8000..=0x9fff => self.ppu.read_vram(add),
             //external ram (handled by cartridge)
-            0xa000...0xbfff => self.rom.read8(add),
+            0xa000..=0xbfff => self.rom.read8(add),
             //work ram 0
-            0xc000...0xcfff => self.work_ram_0[addr - 0xa000],
+            0xc000..=0xcfff => self.work_ram_0[addr - 0xc000],
             //work ram 1..n
-            0xd000...0xdfff => self.work_ram_1[addr - 0xd000],
+            0xd000..=0xdfff => self.work_ram_1[addr - 0xd000],
             //echo ram
-            0xe000...0xfdff => self.read8(add - 0x2000),
+            0xe000..=0xfdff => self.read8(add - 0x2000),
             //sprite table
-            0xfe00...0xfe9f => self.sprite_table[addr - 0xfe00],
+            //the ppu's sprite table is locked while DMA is running
+            0xfe00..=0xfe9f if self.dma.is_some() => 0xff,
+            0xfe00..=0xfe9f => self.ppu.read_oam(add),
             //unusable, I'll just return a 0
-            0xfea0...0xfeff => 0,
+            0xfea0..=0xfeff => 0,
+            0xff00 => self.joypad.read8(),
+            0xff01..=0xff02 => self.serial.read8(add),
+            0xff04..=0xff07 => self.timer.read8(add),
+            0xff10..=0xff3f => self.apu.read8(add),
+            0xff40..=0xff45 | 0xff47..=0xff4b => self.ppu.read8(add),
+            //interrupt flag, the unused upper bits always read as 1
+            0xff0f => self.interrupt_flag | !INTERRUPT_MASK,
             //io registers
-            0xff00...0xff7f => self.io_registers[addr - 0xff00],
+            0xff03..=0xff7f => self.io_registers[addr - 0xff00],
             //hram
-            0xff80...0xfffe => self.hram[(addr - 0xff81) as usize],
-            //interrupt register
-            0xffff => self.interrupts,
+            0xff80..=0xfffe => self.hram[addr - 0xff80],
+            //interrupt enable, all 8 bits are readable and writable
+            0xffff => self.interrupt_enable,
             _ => 0,
         }
     }
-
-    pub fn read16(&self, addr: u16) -> u16 {
-        if addr < 0xffff {
-            join_u8(self.read8(addr), self.read8(addr + 1))
-        } else {
-            //todo: probably wrong
-            //this just left-shifts the last address's value 8 places when reading past the last byte
-            join_u8(self.read8(addr), 0)
-        }
-    }
-    pub fn write8(&mut self, add: Addr, dat: u8) {
+    fn write8(&mut self, add: Addr, dat: u8) {
         let addr = add as usize;
         match addr {
-            //rom memory banks
-            0x8000...0x9fff => self.vram[addr - 0x8000] = dat,
+            //rom memory banks (writes go to the memory bank controller)
+            0x0000..=0x7fff => self.rom.write8(add, dat),
+            0x8000..=0x9fff => self.ppu.write_vram(add, dat),
             //external ram (handled by cartridge)
-            // 0xa000...0xbfff => self.rom.read8(addr),
+            0xa000..=0xbfff => self.rom.write8(add, dat),
             //work ram 0
-            0xc000...0xcfff => self.work_ram_0[addr - 0xa000] = dat,
+            0xc000..=0xcfff => self.work_ram_0[addr - 0xc000] = dat,
             //work ram 1..n
-            0xd000...0xdfff => self.work_ram_1[addr - 0xd000] = dat,
+            0xd000..=0xdfff => self.work_ram_1[addr - 0xd000] = dat,
             //echo ram
-            0xe000...0xfdff => self.work_ram_0[addr - 0x2000] = dat,
+            0xe000..=0xfdff => self.write8(add - 0x2000, dat),
             //sprite table
-            0xfe00...0xfe9f => self.sprite_table[addr - 0xfe00] = dat,
-            //unusable, I'll just return a 0
-            // 0xfea0...0xfeff => 0,
+            0xfe00..=0xfe9f => self.ppu.write_oam(add, dat),
+            //unusable, writes are ignored
+            // 0xfea0..=0xfeff => 0,
+            0xff00 => self.joypad.write8(dat),
+            0xff01..=0xff02 => self.serial.write8(add, dat),
+            0xff04..=0xff07 => self.timer.write8(add, dat),
+            0xff10..=0xff3f => self.apu.write8(add, dat),
+            0xff40..=0xff45 | 0xff47..=0xff4b => self.ppu.write8(add, dat),
+            //interrupt flag
+            0xff0f => self.interrupt_flag = dat & INTERRUPT_MASK,
+            //OAM DMA, the source address is still readable afterwards
+            0xff46 => {
+                self.io_registers[0x46] = dat;
+                self.dma = Some(OamDma {
+                    source: (dat as u16) << 8,
+                    index: 0,
+                    cycles: 0,
+                });
+            }
             //io registers
-            0xff00...0xff7f => self.io_registers[addr] = dat,
+            0xff03..=0xff7f => self.io_registers[addr - 0xff00] = dat,
             //hram
-            0xff80...0xfffe => self.hram[addr - 0xff81] = dat,
-            //interrupt register
-            0xffff => self.interrupts = dat,
+            0xff80..=0xfffe => self.hram[addr - 0xff80] = dat,
+            //interrupt enable
+            0xffff => self.interrupt_enable = dat,
             _ => (),
         }
     }
-    pub fn write16(&mut self, addr: Addr, dat: u16) {
-        let (hi, lo) = split_u16(dat);
-        self.write8(addr, hi);
-        self.write8(addr + 1, lo);
+    ///Advances the components driven by the system clock by the given number of T-cycles
+    fn tick(&mut self, cycles: u32) {
+        self.tick_dma(cycles);
+        //buttons are pressed between ticks, so their interrupt is picked up here
+        if self.joypad.take_interrupt() {
+            self.request_interrupt(Interrupt::Joypad);
+        }
+        if self.timer.tick(cycles) {
+            self.request_interrupt(Interrupt::Timer);
+        }
+        if self.serial.tick(cycles) {
+            self.request_interrupt(Interrupt::Serial);
+        }
+        self.interrupt_flag |= self.ppu.tick(cycles);
+        let frame_sequencer_clocks = self.timer.take_frame_sequencer_clocks();
+        self.apu.tick(cycles, frame_sequencer_clocks);
+        self.rom.tick(cycles);
+        if let Some(ref mut save) = self.save {
+            if let Err(e) = save.tick(cycles, &*self.rom) {
+                warn!("Couldn't write {}: {}", save.get_path().display(), e);
+            }
+        }
     }
+}
 
-    pub fn push_stack(&mut self, sp: &mut u16, val: u16) {
-        *sp -= 2;
-        self.write16(*sp, val);
-    }
-    pub fn pop_stack(&mut self, sp: &mut u16) -> u16 {
-        let val = self.read16(*sp);
-        *sp += 2;
-        val
+impl Drop for Mmu {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush_save() {
+            warn!("Couldn't write save file: {}", e);
+        }
     }
-
-    pub fn enable_interrupts(&mut self) {}
-    pub fn disable_interrupts(&mut self) {}
 }
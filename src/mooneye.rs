@@ -0,0 +1,92 @@
+use gameboy::GameBoy;
+use register::CpuRegister;
+use rom::{self, RomError};
+use shared::*;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+///Emulated time a test rom gets before it counts as hung
+pub const DEFAULT_CYCLE_BUDGET: u64 = CLOCK_SPEED as u64 * 20;
+
+///B, C, D, E, H and L when a mooneye test passes
+const PASS_REGISTERS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+///Every register holds this when a mooneye test fails
+const FAIL_REGISTER: u8 = 0x42;
+
+///What a test rom reported when it hit its LD B,B breakpoint
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    ///the registers held the Fibonacci pass pattern
+    Passed,
+    ///the registers held 0x42
+    Failed,
+    ///the breakpoint was hit with some other register values, the rom probably crashed
+    Unknown([u8; 6]),
+    ///the breakpoint was never hit within the cycle budget
+    TimedOut,
+}
+
+impl Outcome {
+    fn from_registers(reg: &CpuRegister) -> Self {
+        let values = [reg.b, reg.c, reg.d, reg.e, reg.h, reg.l];
+        if values == PASS_REGISTERS {
+            Outcome::Passed
+        } else if values.iter().all(|&x| x == FAIL_REGISTER) {
+            Outcome::Failed
+        } else {
+            Outcome::Unknown(values)
+        }
+    }
+    pub fn passed(self) -> bool {
+        self == Outcome::Passed
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::Passed => write!(f, "passed"),
+            Outcome::Failed => write!(f, "failed"),
+            Outcome::Unknown(values) => write!(
+                f,
+                "unknown result (B={:02x} C={:02x} D={:02x} E={:02x} H={:02x} L={:02x})",
+                values[0], values[1], values[2], values[3], values[4], values[5]
+            ),
+            Outcome::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+///Runs until the rom executes LD B,B or the budget (in T-cycles) runs out
+pub fn run(gameboy: &mut GameBoy, cycle_budget: u64) -> Outcome {
+    while gameboy.get_cycles() < cycle_budget {
+        gameboy.step();
+        if gameboy.get_cpu_mut().take_breakpoint() {
+            return Outcome::from_registers(gameboy.get_cpu().get_register());
+        }
+    }
+    Outcome::TimedOut
+}
+
+///Loads and runs one test rom
+pub fn run_rom(path: &str, cycle_budget: u64) -> Result<Outcome, RomError> {
+    let mut gameboy = GameBoy::new(rom::load_rom(path)?);
+    Ok(run(&mut gameboy, cycle_budget))
+}
+
+///Every .gb file under dir (including subdirectories), sorted by path
+pub fn find_roms<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PathBuf>> {
+    let mut roms = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            roms.extend(find_roms(&path)?);
+        } else if path.extension().is_some_and(|x| x == "gb") {
+            roms.push(path);
+        }
+    }
+    roms.sort();
+    Ok(roms)
+}
@@ -0,0 +1,298 @@
+use crc32fast;
+use std::error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    ///The file doesn't start with an IPS, UPS or BPS magic number
+    UnknownFormat,
+    ///The patch ends in the middle of a record
+    Truncated,
+    ///A record reads or writes outside of the rom, or would make it bigger than any cartridge
+    OutOfBounds,
+    ///The patch file itself is corrupt
+    PatchChecksum { expected: u32, actual: u32 },
+    ///The rom isn't the one the patch was made for
+    SourceChecksum { expected: u32, actual: u32 },
+    ///Applying the patch didn't produce the rom it was made from
+    TargetChecksum { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatchError::UnknownFormat => write!(f, "not an IPS, UPS or BPS patch"),
+            PatchError::Truncated => write!(f, "patch ends in the middle of a record"),
+            PatchError::OutOfBounds => write!(f, "patch record points outside of the rom"),
+            PatchError::PatchChecksum { expected, actual } => write!(
+                f,
+                "patch is corrupt (crc32 {:08x}, expected {:08x})",
+                actual, expected
+            ),
+            PatchError::SourceChecksum { expected, actual } => write!(
+                f,
+                "patch was made for a different rom (crc32 {:08x}, expected {:08x})",
+                actual, expected
+            ),
+            PatchError::TargetChecksum { expected, actual } => write!(
+                f,
+                "patched rom doesn't match (crc32 {:08x}, expected {:08x})",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl error::Error for PatchError {}
+
+///Largest rom a patch may produce, the biggest cartridges hold 8MiB
+pub const MAX_ROM_SIZE: usize = 8 * 1024 * 1024;
+
+///File extensions of the supported patch formats, in the order soft-patching looks for them
+pub const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+///Applies an IPS, UPS or BPS patch, picking the format from the patch's magic number
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"UPS1") {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+///Sequential reads over patch data, failing instead of panicking at the end
+struct PatchReader<'a> {
+    dat: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(dat: &'a [u8], pos: usize) -> Self {
+        PatchReader { dat, pos }
+    }
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        let val = *self.dat.get(self.pos).ok_or(PatchError::Truncated)?;
+        self.pos += 1;
+        Ok(val)
+    }
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        if self.dat.len() - self.pos < len {
+            return Err(PatchError::Truncated);
+        }
+        let val = &self.dat[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(val)
+    }
+    ///Big endian number of the given width (IPS)
+    fn big_endian(&mut self, len: usize) -> Result<usize, PatchError> {
+        let mut val = 0;
+        for _ in 0..len {
+            val = (val << 8) | self.byte()? as usize;
+        }
+        Ok(val)
+    }
+    ///Variable length number used by UPS and BPS, 7 bits per byte with the high bit ending it
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut val: u64 = 0;
+        let mut shift: u64 = 1;
+        loop {
+            let x = self.byte()?;
+            val = (x as u64 & 0x7f)
+                .checked_mul(shift)
+                .and_then(|x| x.checked_add(val))
+                .ok_or(PatchError::OutOfBounds)?;
+            if x & 0x80 != 0 {
+                break;
+            }
+            shift = shift.checked_mul(0x80).ok_or(PatchError::OutOfBounds)?;
+            val = val.checked_add(shift).ok_or(PatchError::OutOfBounds)?;
+        }
+        Ok(val as usize)
+    }
+}
+
+fn read_le32(dat: &[u8]) -> u32 {
+    dat.iter()
+        .rev()
+        .fold(0u32, |val, x| (val << 8) | *x as u32)
+}
+
+///Checks the 12 byte UPS/BPS footer (source, target and patch crc32) against the patch and rom,
+///returns the expected target crc32
+fn check_footer(rom: &[u8], patch: &[u8]) -> Result<u32, PatchError> {
+    if patch.len() < 16 {
+        return Err(PatchError::Truncated);
+    }
+    let footer = &patch[patch.len() - 12..];
+    let expected = read_le32(&footer[8..12]);
+    let actual = crc32fast::hash(&patch[..patch.len() - 4]);
+    if expected != actual {
+        return Err(PatchError::PatchChecksum { expected, actual });
+    }
+    let expected = read_le32(&footer[0..4]);
+    let actual = crc32fast::hash(rom);
+    if expected != actual {
+        return Err(PatchError::SourceChecksum { expected, actual });
+    }
+    Ok(read_le32(&footer[4..8]))
+}
+
+fn check_target(target: &[u8], expected: u32) -> Result<(), PatchError> {
+    let actual = crc32fast::hash(target);
+    if expected != actual {
+        return Err(PatchError::TargetChecksum { expected, actual });
+    }
+    Ok(())
+}
+
+///IPS: records of (3 byte offset, 2 byte length, data), a zero length means an
+///RLE record of (2 byte length, value). Ends with "EOF", optionally followed by a 3 byte size to truncate to.
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut out = rom.to_vec();
+    let mut reader = PatchReader::new(patch, 5);
+    loop {
+        if reader.bytes(3)? == b"EOF" {
+            //"EOF" is also a valid offset (0x454f46), but never inside a game boy rom
+            break;
+        }
+        reader.pos -= 3;
+        let offset = reader.big_endian(3)?;
+        let len = reader.big_endian(2)?;
+        let (len, data) = if len == 0 {
+            let run = reader.big_endian(2)?;
+            (run, None)
+        } else {
+            (len, Some(reader.bytes(len)?))
+        };
+        let end = offset + len;
+        if end > MAX_ROM_SIZE {
+            return Err(PatchError::OutOfBounds);
+        }
+        if out.len() < end {
+            out.resize(end, 0);
+        }
+        match data {
+            Some(dat) => out[offset..end].copy_from_slice(dat),
+            None => {
+                let val = reader.byte()?;
+                for x in out[offset..end].iter_mut() {
+                    *x = val;
+                }
+            }
+        }
+    }
+    //truncation extension
+    if reader.dat.len() - reader.pos >= 3 {
+        let size = reader.big_endian(3)?;
+        out.truncate(size);
+    }
+    Ok(out)
+}
+
+///UPS: source and target sizes, then hunks of (distance to skip, bytes to xor ending with a 0)
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_crc = check_footer(rom, patch)?;
+    let body_end = patch.len() - 12;
+    let mut reader = PatchReader::new(&patch[..body_end], 4);
+    let _source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    if target_size > MAX_ROM_SIZE {
+        return Err(PatchError::OutOfBounds);
+    }
+    let mut out = rom.to_vec();
+    out.resize(target_size, 0);
+    let mut offset: usize = 0;
+    while reader.pos < body_end {
+        offset = offset
+            .checked_add(reader.varint()?)
+            .ok_or(PatchError::OutOfBounds)?;
+        loop {
+            let x = reader.byte()?;
+            if offset < out.len() {
+                out[offset] ^= x;
+            }
+            offset = offset.checked_add(1).ok_or(PatchError::OutOfBounds)?;
+            if x == 0 {
+                break;
+            }
+        }
+    }
+    check_target(&out, target_crc)?;
+    Ok(out)
+}
+
+///BPS: source, target and metadata sizes, metadata, then actions copying from the source,
+///the patch or earlier parts of the target
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_crc = check_footer(rom, patch)?;
+    let body_end = patch.len() - 12;
+    let mut reader = PatchReader::new(&patch[..body_end], 4);
+    let _source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    if target_size > MAX_ROM_SIZE {
+        return Err(PatchError::OutOfBounds);
+    }
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+    let mut out: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_offset: i64 = 0;
+    let mut target_offset: i64 = 0;
+    //offsets are stored as magnitude << 1 | sign
+    let relative = |offset: i64, x: usize| -> Result<i64, PatchError> {
+        let magnitude = (x >> 1) as i64;
+        let delta = if x & 1 == 1 { -magnitude } else { magnitude };
+        offset.checked_add(delta).ok_or(PatchError::OutOfBounds)
+    };
+    let source_range = |start: usize, len: usize| -> Result<&[u8], PatchError> {
+        start
+            .checked_add(len)
+            .and_then(|end| rom.get(start..end))
+            .ok_or(PatchError::OutOfBounds)
+    };
+    while reader.pos < body_end {
+        let action = reader.varint()?;
+        let len = (action >> 2) + 1;
+        //checked up front, a target copy could otherwise keep feeding itself
+        if target_size - out.len() < len {
+            return Err(PatchError::OutOfBounds);
+        }
+        match action & 0x3 {
+            //source read, copy from the same offset in the source
+            0 => {
+                let dat = source_range(out.len(), len)?;
+                out.extend_from_slice(dat);
+            }
+            //target read, copy from the patch
+            1 => out.extend_from_slice(reader.bytes(len)?),
+            //source copy, copy from anywhere in the source
+            2 => {
+                source_offset = relative(source_offset, reader.varint()?)?;
+                if source_offset < 0 {
+                    return Err(PatchError::OutOfBounds);
+                }
+                let dat = source_range(source_offset as usize, len)?;
+                out.extend_from_slice(dat);
+                //len is at most the target size, which is capped well below i64::MAX
+                source_offset += len as i64;
+            }
+            //target copy, copy from earlier output, one byte at a time as the ranges can overlap
+            _ => {
+                target_offset = relative(target_offset, reader.varint()?)?;
+                for _ in 0..len {
+                    if target_offset < 0 || target_offset as usize >= out.len() {
+                        return Err(PatchError::OutOfBounds);
+                    }
+                    let x = out[target_offset as usize];
+                    out.push(x);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    check_target(&out, target_crc)?;
+    Ok(out)
+}
@@ -0,0 +1,471 @@
+use interrupt::*;
+use std::mem;
+
+#[cfg(feature = "fifo-ppu")]
+mod fifo;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+///Dots (T-cycles) per scanline
+const DOTS_PER_LINE: u32 = 456;
+///Visible lines followed by 10 lines of vblank
+const LINES_PER_FRAME: u8 = 154;
+const OAM_SCAN_DOTS: u32 = 80;
+///Fixed mode 3 length for the scanline renderer, which doesn't model sprite or scroll penalties
+const DRAWING_DOTS: u32 = 172;
+///Sprites the ppu can pick up during OAM scan
+const SPRITES_PER_LINE: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    ///Mode 0
+    HBlank,
+    ///Mode 1
+    VBlank,
+    ///Mode 2, searching OAM for sprites on this line
+    OamScan,
+    ///Mode 3, pushing pixels to the LCD
+    Drawing,
+}
+
+impl Mode {
+    ///Value of the lower 2 bits of STAT
+    pub fn bits(self) -> u8 {
+        match self {
+            Mode::HBlank => 0,
+            Mode::VBlank => 1,
+            Mode::OamScan => 2,
+            Mode::Drawing => 3,
+        }
+    }
+}
+
+///How mode 3 turns VRAM into pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Renderer {
+    ///Draws each line in one go at the end of mode 3, fast but blind to mid-line register writes
+    Scanline,
+    ///Pushes one pixel per dot through the background and sprite FIFOs, with a variable length mode 3
+    #[cfg(feature = "fifo-ppu")]
+    Fifo,
+}
+
+///One OAM entry
+#[derive(Clone, Copy)]
+struct Sprite {
+    ///screen y + 16
+    y: u8,
+    ///screen x + 8
+    x: u8,
+    tile: u8,
+    ///bit 7 bg priority, 6 y flip, 5 x flip, 4 palette
+    flags: u8,
+}
+
+///Picture processing unit, owns VRAM, OAM and the LCD registers (0xff40 - 0xff4b)
+pub struct Ppu {
+    ///0x8000 - 0x9fff
+    vram: [u8; 0x2000],
+    ///0xfe00 - 0xfe9f
+    oam: [u8; 0xa0],
+    ///0xff40
+    lcdc: u8,
+    ///0xff41, only the interrupt select bits (3-6) are stored
+    stat: u8,
+    ///0xff42
+    scy: u8,
+    ///0xff43
+    scx: u8,
+    ///0xff44
+    ly: u8,
+    ///0xff45
+    lyc: u8,
+    ///0xff47
+    bgp: u8,
+    ///0xff48
+    obp0: u8,
+    ///0xff49
+    obp1: u8,
+    ///0xff4a
+    wy: u8,
+    ///0xff4b
+    wx: u8,
+
+    mode: Mode,
+    ///dot within the current line
+    dot: u32,
+    ///the window keeps its own line counter that only advances on lines it was drawn on
+    window_line: u8,
+    ///LY has matched WY at some point this frame
+    window_triggered: bool,
+    ///STAT interrupts fire on the rising edge of all enabled sources ORed together
+    stat_line: bool,
+    ///frame being drawn, shades 0 (lightest) - 3 (darkest), row major
+    framebuffer: Vec<u8>,
+    ///last finished frame, swapped with the one being drawn on entering vblank
+    finished_frame: Vec<u8>,
+    frames: u64,
+    renderer: Renderer,
+    ///mode 3 state of the FIFO renderer
+    #[cfg(feature = "fifo-ppu")]
+    fifo: Option<fifo::PixelFifo>,
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Ppu {
+            vram: [0; 0x2000],
+            oam: [0; 0xa0],
+            lcdc: 0,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            mode: Mode::HBlank,
+            dot: 0,
+            window_line: 0,
+            window_triggered: false,
+            stat_line: false,
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            finished_frame: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frames: 0,
+            renderer: Renderer::Scanline,
+            #[cfg(feature = "fifo-ppu")]
+            fifo: None,
+        }
+    }
+
+    ///Last finished frame, one shade (0-3) per pixel
+    pub fn get_framebuffer(&self) -> &[u8] {
+        &self.finished_frame
+    }
+    ///Frames finished since power on, can be polled to know when a new one is ready
+    pub fn get_frame_count(&self) -> u64 {
+        self.frames
+    }
+    pub fn get_renderer(&self) -> Renderer {
+        self.renderer
+    }
+    ///Takes effect from the next line
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = renderer;
+    }
+    pub fn get_mode(&self) -> Mode {
+        self.mode
+    }
+    pub fn lcd_enabled(&self) -> bool {
+        self.lcdc & 0x80 != 0
+    }
+
+    pub fn read_vram(&self, addr: u16) -> u8 {
+        match self.mode {
+            Mode::Drawing => 0xff,
+            _ => self.vram[addr as usize - 0x8000],
+        }
+    }
+    pub fn write_vram(&mut self, addr: u16, dat: u8) {
+        if self.mode != Mode::Drawing {
+            self.vram[addr as usize - 0x8000] = dat;
+        }
+    }
+    pub fn read_oam(&self, addr: u16) -> u8 {
+        match self.mode {
+            Mode::OamScan | Mode::Drawing => 0xff,
+            _ => self.oam[addr as usize - 0xfe00],
+        }
+    }
+    pub fn write_oam(&mut self, addr: u16, dat: u8) {
+        match self.mode {
+            Mode::OamScan | Mode::Drawing => (),
+            _ => self.oam[addr as usize - 0xfe00] = dat,
+        }
+    }
+    ///OAM DMA writes regardless of mode
+    pub fn write_oam_dma(&mut self, index: usize, dat: u8) {
+        self.oam[index] = dat;
+    }
+
+    pub fn read8(&self, addr: u16) -> u8 {
+        match addr {
+            0xff40 => self.lcdc,
+            0xff41 => {
+                let coincidence = if self.ly == self.lyc { 0b100 } else { 0 };
+                0x80 | self.stat | coincidence | self.mode.bits()
+            }
+            0xff42 => self.scy,
+            0xff43 => self.scx,
+            0xff44 => self.ly,
+            0xff45 => self.lyc,
+            0xff47 => self.bgp,
+            0xff48 => self.obp0,
+            0xff49 => self.obp1,
+            0xff4a => self.wy,
+            0xff4b => self.wx,
+            _ => 0xff,
+        }
+    }
+    pub fn write8(&mut self, addr: u16, dat: u8) {
+        match addr {
+            0xff40 => {
+                let was_enabled = self.lcd_enabled();
+                self.lcdc = dat;
+                if was_enabled && !self.lcd_enabled() {
+                    //turning the LCD off resets LY and parks the ppu in mode 0
+                    self.ly = 0;
+                    self.dot = 0;
+                    self.mode = Mode::HBlank;
+                    self.window_line = 0;
+                    self.window_triggered = false;
+                } else if !was_enabled && self.lcd_enabled() {
+                    self.start_line();
+                }
+            }
+            //mode and coincidence bits are read only
+            0xff41 => self.stat = dat & 0b0111_1000,
+            0xff42 => self.scy = dat,
+            0xff43 => self.scx = dat,
+            //LY is read only
+            0xff44 => (),
+            0xff45 => self.lyc = dat,
+            0xff47 => self.bgp = dat,
+            0xff48 => self.obp0 = dat,
+            0xff49 => self.obp1 = dat,
+            0xff4a => self.wy = dat,
+            0xff4b => self.wx = dat,
+            _ => (),
+        }
+    }
+
+    ///Advances the ppu by the given number of T-cycles, returns the IF bits it requested
+    pub fn tick(&mut self, cycles: u32) -> u8 {
+        if !self.lcd_enabled() {
+            return 0;
+        }
+        let mut interrupts = 0;
+        for _ in 0..cycles {
+            self.dot += 1;
+            if self.mode == Mode::OamScan && self.dot == OAM_SCAN_DOTS {
+                self.start_drawing();
+            } else if self.mode == Mode::Drawing && self.drawing_dot() {
+                self.mode = Mode::HBlank;
+            }
+            if self.dot == DOTS_PER_LINE {
+                self.dot = 0;
+                self.ly += 1;
+                if self.ly == LINES_PER_FRAME {
+                    self.ly = 0;
+                    self.window_line = 0;
+                    self.window_triggered = false;
+                }
+                if self.ly as usize == SCREEN_HEIGHT {
+                    self.mode = Mode::VBlank;
+                    mem::swap(&mut self.framebuffer, &mut self.finished_frame);
+                    self.frames += 1;
+                    interrupts |= Interrupt::VBlank.bit();
+                } else if (self.ly as usize) < SCREEN_HEIGHT {
+                    self.start_line();
+                }
+            }
+            if self.update_stat_line() {
+                interrupts |= Interrupt::Stat.bit();
+            }
+        }
+        interrupts
+    }
+
+    fn start_line(&mut self) {
+        self.mode = Mode::OamScan;
+        if self.ly == self.wy {
+            self.window_triggered = true;
+        }
+    }
+
+    fn start_drawing(&mut self) {
+        self.mode = Mode::Drawing;
+        #[cfg(feature = "fifo-ppu")]
+        {
+            self.fifo = match self.renderer {
+                Renderer::Fifo => Some(fifo::PixelFifo::new(self)),
+                Renderer::Scanline => None,
+            };
+        }
+    }
+
+    ///One dot of mode 3, returns true once the line is finished
+    fn drawing_dot(&mut self) -> bool {
+        #[cfg(feature = "fifo-ppu")]
+        {
+            if self.fifo.is_some() {
+                return self.fifo_dot();
+            }
+        }
+        if self.dot == OAM_SCAN_DOTS + DRAWING_DOTS {
+            self.render_line();
+            true
+        } else {
+            false
+        }
+    }
+
+    ///Returns true on a rising edge of the STAT interrupt line
+    fn update_stat_line(&mut self) -> bool {
+        let line = (self.stat & 0x40 != 0 && self.ly == self.lyc)
+            || match self.mode {
+                Mode::HBlank => self.stat & 0x08 != 0,
+                Mode::VBlank => self.stat & 0x10 != 0,
+                Mode::OamScan => self.stat & 0x20 != 0,
+                Mode::Drawing => false,
+            };
+        let rising = line && !self.stat_line;
+        self.stat_line = line;
+        rising
+    }
+
+    fn render_line(&mut self) {
+        let ly = self.ly;
+        //raw colour indices, needed to resolve sprite priority
+        let mut bg = [0u8; SCREEN_WIDTH];
+        //on DMG bit 0 blanks both background and window
+        if self.lcdc & 0x01 != 0 {
+            let map = if self.lcdc & 0x08 != 0 { 0x1c00 } else { 0x1800 };
+            let y = ly.wrapping_add(self.scy);
+            for (x, pixel) in bg.iter_mut().enumerate() {
+                *pixel = self.tile_pixel(map, (x as u8).wrapping_add(self.scx), y);
+            }
+            let window_x = self.wx as i16 - 7;
+            if self.lcdc & 0x20 != 0 && self.window_triggered && window_x < SCREEN_WIDTH as i16 {
+                let map = if self.lcdc & 0x40 != 0 { 0x1c00 } else { 0x1800 };
+                let start = window_x.max(0) as usize;
+                for (x, pixel) in bg.iter_mut().enumerate().skip(start) {
+                    *pixel = self.tile_pixel(map, (x as i16 - window_x) as u8, self.window_line);
+                }
+                self.window_line += 1;
+            }
+        }
+        let row = ly as usize * SCREEN_WIDTH;
+        for (x, &color) in bg.iter().enumerate() {
+            self.framebuffer[row + x] = apply_palette(self.bgp, color);
+        }
+        if self.lcdc & 0x02 != 0 {
+            self.render_sprites(&bg);
+        }
+    }
+
+    ///Colour index (0-3) of a background or window pixel
+    fn tile_pixel(&self, map: usize, x: u8, y: u8) -> u8 {
+        let tile = self.vram[map + (y as usize / 8) * 32 + x as usize / 8];
+        //0x8000 addressing is unsigned, 0x8800 is signed around 0x9000
+        let base = if self.lcdc & 0x10 != 0 {
+            tile as usize * 16
+        } else {
+            (0x1000 + tile as i8 as i32 * 16) as usize
+        };
+        self.tile_data_pixel(base + (y as usize % 8) * 2, 7 - x % 8)
+    }
+
+    fn tile_data_pixel(&self, addr: usize, bit: u8) -> u8 {
+        let lo = (self.vram[addr] >> bit) & 1;
+        let hi = (self.vram[addr + 1] >> bit) & 1;
+        (hi << 1) | lo
+    }
+
+    fn sprite_height(&self) -> u8 {
+        if self.lcdc & 0x04 != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    ///OAM scan, the first 10 sprites overlapping the line in OAM order
+    fn line_sprites(&self) -> Vec<Sprite> {
+        let height = self.sprite_height() as i16;
+        let ly = self.ly as i16;
+        self.oam
+            .chunks(4)
+            .map(|x| Sprite {
+                y: x[0],
+                x: x[1],
+                tile: x[2],
+                flags: x[3],
+            })
+            .filter(|x| {
+                let top = x.y as i16 - 16;
+                ly >= top && ly < top + height
+            })
+            .take(SPRITES_PER_LINE)
+            .collect()
+    }
+
+    ///Colour index of pixel i (0 is leftmost on screen) of the sprite's row on this line
+    fn sprite_pixel(&self, sprite: Sprite, i: u8) -> u8 {
+        let height = self.sprite_height();
+        let mut line = self.ly.wrapping_sub(sprite.y.wrapping_sub(16));
+        if sprite.flags & 0x40 != 0 {
+            line = height - 1 - line;
+        }
+        //8x16 sprites ignore bit 0 of the tile index
+        let tile = if height == 16 {
+            sprite.tile & 0xfe
+        } else {
+            sprite.tile
+        };
+        let bit = if sprite.flags & 0x20 != 0 { i } else { 7 - i };
+        self.tile_data_pixel(tile as usize * 16 + line as usize * 2, bit)
+    }
+
+    fn sprite_palette(&self, sprite: Sprite) -> u8 {
+        if sprite.flags & 0x10 != 0 {
+            self.obp1
+        } else {
+            self.obp0
+        }
+    }
+
+    fn render_sprites(&mut self, bg: &[u8; SCREEN_WIDTH]) {
+        let mut sprites = self.line_sprites();
+        //on DMG the sprite with the lowest x wins, ties go to the one earlier in OAM (sort is stable)
+        sprites.sort_by_key(|x| x.x);
+        let mut claimed = [false; SCREEN_WIDTH];
+        let row = self.ly as usize * SCREEN_WIDTH;
+        for sprite in sprites {
+            let palette = self.sprite_palette(sprite);
+            for i in 0..8 {
+                let x = sprite.x as i16 - 8 + i as i16;
+                if x < 0 || x >= SCREEN_WIDTH as i16 || claimed[x as usize] {
+                    continue;
+                }
+                let x = x as usize;
+                let color = self.sprite_pixel(sprite, i);
+                //colour 0 is transparent and lets lower priority sprites through
+                if color == 0 {
+                    continue;
+                }
+                claimed[x] = true;
+                //a hidden sprite pixel still hides the sprites below it
+                if sprite.flags & 0x80 != 0 && bg[x] != 0 {
+                    continue;
+                }
+                self.framebuffer[row + x] = apply_palette(palette, color);
+            }
+        }
+    }
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///Maps a colour index through BGP/OBP0/OBP1 to a shade
+fn apply_palette(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0b11
+}
@@ -0,0 +1,214 @@
+use super::*;
+use std::collections::VecDeque;
+
+///Dots the fetcher needs for one tile (tile number, data low, data high)
+const TILE_FETCH_DOTS: u32 = 6;
+///The first tile fetched on every line is thrown away, plus a dot of setup, for a 172 dot minimum mode 3
+const FIRST_FETCH_DOTS: u32 = 7;
+///Base cost of fetching a sprite
+const SPRITE_FETCH_DOTS: u32 = 6;
+
+#[derive(Clone, Copy)]
+struct ObjPixel {
+    color: u8,
+    ///uses OBP1 instead of OBP0
+    obp1: bool,
+    ///background colours 1-3 are drawn over this pixel
+    behind_bg: bool,
+}
+
+const TRANSPARENT: ObjPixel = ObjPixel {
+    color: 0,
+    obp1: false,
+    behind_bg: false,
+};
+
+///Mode 3 state of the pixel FIFO renderer, built fresh for every line
+pub struct PixelFifo {
+    ///background/window colour indices
+    bg: VecDeque<u8>,
+    ///sprite pixels, lined up with the front of the background FIFO
+    obj: VecDeque<ObjPixel>,
+    ///pixels sent to the LCD so far
+    lcd_x: u8,
+    ///fine scroll pixels still to be dropped from the background FIFO
+    discard: u8,
+    ///dots the FIFO is frozen for while the fetcher is busy elsewhere
+    stall: u32,
+    ///dots into the current tile fetch
+    fetch_dot: u32,
+    ///tile column the fetcher is on
+    fetch_x: u8,
+    ///the fetcher switched over to the window
+    window: bool,
+    ///sprites found by OAM scan that haven't been fetched yet, in x order
+    sprites: VecDeque<Sprite>,
+    ///background or window tile (window, column) the last sprite fetch waited on
+    penalty_tile: Option<(bool, i16)>,
+}
+
+impl PixelFifo {
+    pub fn new(ppu: &Ppu) -> Self {
+        let mut sprites = ppu.line_sprites();
+        //stable, so sprites sharing an x stay in OAM order
+        sprites.sort_by_key(|x| x.x);
+        PixelFifo {
+            bg: VecDeque::with_capacity(16),
+            obj: VecDeque::with_capacity(8),
+            lcd_x: 0,
+            //SCX is only sampled for fine scroll at the start of the line
+            discard: ppu.scx % 8,
+            stall: FIRST_FETCH_DOTS,
+            fetch_dot: 0,
+            fetch_x: 0,
+            window: false,
+            sprites: sprites.into_iter().collect(),
+            penalty_tile: None,
+        }
+    }
+}
+
+impl Ppu {
+    ///One dot of mode 3 using the FIFOs, returns true once all 160 pixels are out
+    pub(super) fn fifo_dot(&mut self) -> bool {
+        let mut fifo = match self.fifo.take() {
+            Some(fifo) => fifo,
+            None => return true,
+        };
+        let done = self.step_fifo(&mut fifo);
+        if !done {
+            self.fifo = Some(fifo);
+        }
+        done
+    }
+
+    fn step_fifo(&mut self, fifo: &mut PixelFifo) -> bool {
+        if fifo.stall > 0 {
+            fifo.stall -= 1;
+            return false;
+        }
+        //reaching WX restarts the fetcher on the window, throwing away whatever background was queued
+        if !fifo.window
+            && self.lcdc & 0x21 == 0x21
+            && self.window_triggered
+            && fifo.lcd_x as u16 + 7 >= self.wx as u16
+        {
+            fifo.window = true;
+            fifo.bg.clear();
+            fifo.fetch_x = 0;
+            fifo.fetch_dot = 0;
+            //WX below 7 pushes the start of the window off the left edge
+            fifo.discard = 7u8.saturating_sub(self.wx);
+        }
+
+        fifo.fetch_dot += 1;
+        if fifo.fetch_dot >= TILE_FETCH_DOTS && fifo.bg.is_empty() {
+            self.fetch_tile(fifo);
+        }
+        if fifo.bg.is_empty() {
+            return false;
+        }
+
+        if self.lcdc & 0x02 != 0 && fifo.discard == 0 {
+            let hit = match fifo.sprites.front() {
+                Some(sprite) => sprite.x as i16 - 8 <= fifo.lcd_x as i16,
+                None => false,
+            };
+            if hit {
+                let sprite = fifo.sprites.pop_front().unwrap();
+                self.fetch_sprite(fifo, sprite);
+                //this dot is the first of the penalty
+                fifo.stall = self.sprite_penalty(fifo, sprite) - 1;
+                return false;
+            }
+        }
+
+        let color = fifo.bg.pop_front().unwrap();
+        let obj = fifo.obj.pop_front().unwrap_or(TRANSPARENT);
+        if fifo.discard > 0 {
+            fifo.discard -= 1;
+            return false;
+        }
+        //palettes are applied as pixels leave the FIFO, so mid-line palette writes show up
+        let mut shade = apply_palette(self.bgp, color);
+        if obj.color != 0 && self.lcdc & 0x02 != 0 && !(obj.behind_bg && color != 0) {
+            let palette = if obj.obp1 { self.obp1 } else { self.obp0 };
+            shade = apply_palette(palette, obj.color);
+        }
+        let x = fifo.lcd_x as usize;
+        self.framebuffer[self.ly as usize * SCREEN_WIDTH + x] = shade;
+        fifo.lcd_x += 1;
+        if fifo.lcd_x as usize == SCREEN_WIDTH {
+            if fifo.window {
+                self.window_line += 1;
+            }
+            return true;
+        }
+        false
+    }
+
+    ///Pushes the next 8 background or window pixels, reading LCDC and scroll as they are now
+    fn fetch_tile(&self, fifo: &mut PixelFifo) {
+        fifo.fetch_dot = 0;
+        let column = fifo.fetch_x;
+        fifo.fetch_x = fifo.fetch_x.wrapping_add(1);
+        //on DMG bit 0 blanks both background and window
+        if self.lcdc & 0x01 == 0 {
+            fifo.bg.extend([0u8; 8].iter());
+            return;
+        }
+        let (map, x, y) = if fifo.window {
+            let map = if self.lcdc & 0x40 != 0 { 0x1c00 } else { 0x1800 };
+            (map, column.wrapping_mul(8), self.window_line)
+        } else {
+            let map = if self.lcdc & 0x08 != 0 { 0x1c00 } else { 0x1800 };
+            let tile_x = ((self.scx / 8).wrapping_add(column)) & 0x1f;
+            (map, tile_x * 8, self.ly.wrapping_add(self.scy))
+        };
+        for i in 0..8 {
+            fifo.bg.push_back(self.tile_pixel(map, x.wrapping_add(i), y));
+        }
+    }
+
+    ///Dots a sprite fetch holds up the FIFO for: the fetch itself, plus waiting for the background
+    ///fetch of the tile under the sprite's leftmost pixel to finish, unless an earlier sprite already did
+    fn sprite_penalty(&self, fifo: &mut PixelFifo, sprite: Sprite) -> u32 {
+        let left = sprite.x as i16 - 8;
+        let offset = if fifo.window {
+            left - (self.wx as i16 - 7)
+        } else {
+            left + (self.scx % 8) as i16
+        };
+        let tile = (fifo.window, offset.div_euclid(8));
+        if fifo.penalty_tile == Some(tile) {
+            return SPRITE_FETCH_DOTS;
+        }
+        fifo.penalty_tile = Some(tile);
+        //pixels of the tile to the right of the sprite, less the 2 the fetcher overlaps with
+        let right = 7 - offset.rem_euclid(8) as u32;
+        SPRITE_FETCH_DOTS + right.saturating_sub(2)
+    }
+
+    ///Mixes a sprite into the sprite FIFO, pixels already there from earlier sprites win
+    fn fetch_sprite(&self, fifo: &mut PixelFifo, sprite: Sprite) {
+        let left = sprite.x as i16 - 8;
+        for i in 0..8u8 {
+            let x = left + i as i16;
+            if x < fifo.lcd_x as i16 {
+                continue;
+            }
+            let slot = (x - fifo.lcd_x as i16) as usize;
+            while fifo.obj.len() <= slot {
+                fifo.obj.push_back(TRANSPARENT);
+            }
+            if fifo.obj[slot].color != 0 {
+                continue;
+            }
+            fifo.obj[slot] = ObjPixel {
+                color: self.sprite_pixel(sprite, i),
+                obp1: sprite.flags & 0x10 != 0,
+                behind_bg: sprite.flags & 0x80 != 0,
+            };
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use shared::*;
+use std::fmt;
 
 #[derive(Debug, Clone)]
 pub enum Reg8Name {
@@ -34,6 +35,48 @@ pub enum BitFlag {
     C,
 }
 
+///Lowercase, the way RGBDS writes registers
+impl fmt::Display for Reg8Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Reg8Name::A => "a",
+            Reg8Name::B => "b",
+            Reg8Name::C => "c",
+            Reg8Name::D => "d",
+            Reg8Name::E => "e",
+            Reg8Name::F => "f",
+            Reg8Name::H => "h",
+            Reg8Name::L => "l",
+        };
+        f.write_str(name)
+    }
+}
+impl fmt::Display for Reg16Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Reg16Name::AF => "af",
+            Reg16Name::BC => "bc",
+            Reg16Name::DE => "de",
+            Reg16Name::HL => "hl",
+            Reg16Name::SP => "sp",
+            Reg16Name::PC => "pc",
+        };
+        f.write_str(name)
+    }
+}
+///The condition code for "flag is set", prefix it with n for "not set"
+impl fmt::Display for BitFlag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            BitFlag::Z => "z",
+            BitFlag::N => "n",
+            BitFlag::H => "h",
+            BitFlag::C => "c",
+        };
+        f.write_str(name)
+    }
+}
+
 pub struct CpuRegister {
     ///Accumulator
     pub a: u8,
@@ -67,6 +110,12 @@ pub struct CpuRegister {
 
 }
 
+impl Default for CpuRegister {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CpuRegister {
     pub fn new() -> Self {
         CpuRegister {
@@ -82,6 +131,21 @@ impl CpuRegister {
             pc: 0
         }
     }
+    ///Register values the DMG boot rom leaves behind when it jumps to the cartridge at 0x100
+    pub fn post_boot() -> Self {
+        CpuRegister {
+            a: 0x01,
+            f: 0xb0,
+            b: 0x00,
+            c: 0x13,
+            d: 0x00,
+            e: 0xd8,
+            h: 0x01,
+            l: 0x4d,
+            sp: 0xfffe,
+            pc: 0x0100,
+        }
+    }
     pub fn set_flag(&mut self, flag: BitFlag) {
         match flag {
             BitFlag::Z => self.f |= 0b10000000,
@@ -95,12 +159,12 @@ impl CpuRegister {
             BitFlag::Z => self.f &= 0b01111111,
             BitFlag::N => self.f &= 0b10111111,
             BitFlag::H => self.f &= 0b11011111,
-            BitFlag::C => self.f &= 0b11111111,
+            BitFlag::C => self.f &= 0b11101111,
         }
     }
     pub fn flag_is_set(&self, flag: BitFlag) -> bool {
         match flag {
-            BitFlag::Z => self.f & 0b10000000 >> 7 == 1,
+            BitFlag::Z => (self.f & 0b10000000) >> 7 == 1,
             BitFlag::N => (self.f & 0b01000000) >> 6 == 1,
             BitFlag::H => (self.f & 0b00100000) >> 5 == 1,
             BitFlag::C => (self.f & 0b00010000) >> 4 == 1,
@@ -136,10 +200,11 @@ impl CpuRegister {
     }
     pub fn set_reg16(&mut self, reg: Reg16Name, val: u16) {
         match reg {
-            Reg16Name::AF => self.set_reg8_pair(Reg8Name::A, Reg8Name::F, val),
+            //the low 4 bits of F don't exist and always read as 0
+            Reg16Name::AF => self.set_reg8_pair(Reg8Name::A, Reg8Name::F, val & 0xfff0),
             Reg16Name::BC => self.set_reg8_pair(Reg8Name::B, Reg8Name::C, val),
-            Reg16Name::DE => self.set_reg8_pair(Reg8Name::A, Reg8Name::F, val),
-            Reg16Name::HL => self.set_reg8_pair(Reg8Name::A, Reg8Name::F, val),
+            Reg16Name::DE => self.set_reg8_pair(Reg8Name::D, Reg8Name::E, val),
+            Reg16Name::HL => self.set_reg8_pair(Reg8Name::H, Reg8Name::L, val),
             Reg16Name::SP => self.sp = val,
             Reg16Name::PC => self.pc = val,
         }
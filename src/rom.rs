@@ -1,41 +1,202 @@
+use flate2::read::GzDecoder;
+use std::error;
+use std::fmt;
 use std::io;
 use std::io::prelude::*;
+use std::io::Cursor;
 use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use licensee;
+use patch;
+use patch::PatchError;
+use zip::ZipArchive;
 use shared::*;
 
-fn load_rom_bytes(path: &str) -> Result<Vec<u8>, io::Error> {
-    let mut f = try!(File::open(path));
+fn load_rom_bytes<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, io::Error> {
+    let mut f = File::open(path)?;
     let mut buffer: Vec<u8> = Vec::new();
-    try!(f.read_to_end(&mut buffer));
+    f.read_to_end(&mut buffer)?;
     Ok(buffer)
 }
 
-pub fn load_rom(path: &str) -> Result<Box<Cartridge>, String> {
-    let bytes = match load_rom_bytes(path) {
-        Ok(x) => x,
-        Err(e) => return Err(e.to_string()),
-    };
-    let header = parse_header(bytes.clone())?;
+#[derive(Debug)]
+pub enum RomError {
+    ///The rom file couldn't be read
+    Io(io::Error),
+    ///The file ends before the end of the cartridge header (0x150)
+    TooSmall { len: usize },
+    ///0x147 holds a cartridge type that isn't known or can't be emulated
+    UnknownCartridgeType(u8),
+    ///0x148 holds an unknown rom size code
+    UnknownRomSize(u8),
+    ///The file is shorter than the rom size in the header (in bytes)
+    SizeMismatch { expected: usize, actual: usize },
+    ///0x14d doesn't match the checksum of 0x134 - 0x14c, real hardware refuses to boot these
+    ///Only strict loading treats this as an error, otherwise it's a warning
+    BadChecksum { expected: u8, actual: u8 },
+    ///A gzip or zip archive couldn't be unpacked
+    Archive(String),
+    ///A zip archive doesn't contain any .gb or .gbc file
+    NoRomInArchive,
+    ///An IPS, UPS or BPS patch couldn't be applied
+    Patch(PatchError),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RomError::Io(ref e) => write!(f, "couldn't read rom: {}", e),
+            RomError::TooSmall { len } => write!(
+                f,
+                "rom is {} bytes, too small to contain a header ({} bytes)",
+                len, HEADER_END
+            ),
+            RomError::UnknownCartridgeType(code) => {
+                write!(f, "unknown cartridge type code {:#04x}", code)
+            }
+            RomError::UnknownRomSize(code) => write!(f, "unknown rom size code {:#04x}", code),
+            RomError::SizeMismatch { expected, actual } => write!(
+                f,
+                "header says the rom is {} bytes, but the file is only {} bytes",
+                expected, actual
+            ),
+            RomError::BadChecksum { expected, actual } => write!(
+                f,
+                "header checksum mismatch (header says {:#04x}, computed {:#04x})",
+                expected, actual
+            ),
+            RomError::Archive(ref e) => write!(f, "couldn't unpack archive: {}", e),
+            RomError::NoRomInArchive => write!(f, "archive doesn't contain a .gb or .gbc file"),
+            RomError::Patch(ref e) => write!(f, "couldn't apply patch: {}", e),
+        }
+    }
+}
+
+impl error::Error for RomError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            RomError::Io(ref e) => Some(e),
+            RomError::Patch(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(e: io::Error) -> Self {
+        RomError::Io(e)
+    }
+}
+
+impl From<PatchError> for RomError {
+    fn from(e: PatchError) -> Self {
+        RomError::Patch(e)
+    }
+}
+
+///Loads a rom file, which may also be packed in a .gz or .zip archive.
+///A .ips, .ups or .bps patch with the same name next to the rom is applied automatically.
+pub fn load_rom(path: &str) -> Result<Box<dyn Cartridge>, RomError> {
+    let mut bytes = unpack_rom(load_rom_bytes(path)?)?;
+    if let Some(patch_path) = find_soft_patch(path) {
+        info!("Applying {}", patch_path.display());
+        let patch = load_rom_bytes(&patch_path)?;
+        bytes = patch::apply_patch(&bytes, &patch)?;
+    }
+    build_rom(bytes, false)
+}
+
+///Loads a rom file and applies the given IPS, UPS or BPS patches in order (instead of looking for a soft patch)
+pub fn load_rom_with_patches(path: &str, patches: &[&str]) -> Result<Box<dyn Cartridge>, RomError> {
+    let mut bytes = unpack_rom(load_rom_bytes(path)?)?;
+    for patch_path in patches {
+        let patch = load_rom_bytes(patch_path)?;
+        bytes = patch::apply_patch(&bytes, &patch)?;
+    }
+    build_rom(bytes, false)
+}
+
+///First patch next to the rom with the same name and a patch extension
+fn find_soft_patch(path: &str) -> Option<PathBuf> {
+    patch::PATCH_EXTENSIONS
+        .iter()
+        .map(|ext| Path::new(path).with_extension(ext))
+        .find(|x| x.is_file())
+}
+
+///Reads a rom file (unpacking .gz and .zip archives) without parsing or patching it
+pub fn load_rom_image(path: &str) -> Result<Vec<u8>, RomError> {
+    unpack_rom(load_rom_bytes(path)?)
+}
+
+///Loads a rom (or .gz/.zip archive) that's already in memory
+pub fn load_rom_from_bytes<B: Into<Vec<u8>>>(bytes: B) -> Result<Box<dyn Cartridge>, RomError> {
+    build_rom(unpack_rom(bytes.into())?, false)
+}
+
+///Like load_rom_from_bytes, but refuses roms with a bad header checksum the way the boot rom does
+pub fn load_rom_from_bytes_strict<B: Into<Vec<u8>>>(bytes: B) -> Result<Box<dyn Cartridge>, RomError> {
+    build_rom(unpack_rom(bytes.into())?, true)
+}
+
+fn build_rom(bytes: Vec<u8>, strict: bool) -> Result<Box<dyn Cartridge>, RomError> {
+    let header = parse_header(&bytes)?;
+    for warning in header.warnings.iter() {
+        match *warning {
+            HeaderWarning::HeaderChecksum { expected, actual } if strict => {
+                return Err(RomError::BadChecksum { expected, actual })
+            }
+            _ => warn!("{}: {}", header.title, warning),
+        }
+    }
     let rom = parse_rom(header, bytes)?;
     Ok(rom)
 }
 
-pub fn print_rom(path: &str) {
-    let bytes = load_rom_bytes(path).expect("Didn't load correctly");
-    let header = parse_header(bytes.clone()).expect("Couldn't parse header");
-    println!("header: {:?}", header);
-    let blocks = split_to_blocks(bytes);
-    println!("block count: {}", blocks.len());
-    let rom = load_rom(path).unwrap();
-    let head2 = rom.get_header();
-    println!(
-        "rom type: {:?}, rom color: {:?}, japanese?: {}",
-        head2.model, head2.color, head2.japanese
-    );
+///Reads a rom (or .gz/.zip archive) to the end and loads it
+pub fn load_rom_from_reader<R: Read>(mut reader: R) -> Result<Box<dyn Cartridge>, RomError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    load_rom_from_bytes(bytes)
 }
 
-#[derive(Debug)]
-enum CartridgeType {
+///Unpacks gzip and zip archives (taking the first .gb/.gbc entry), anything else is returned as is
+fn unpack_rom(bytes: Vec<u8>) -> Result<Vec<u8>, RomError> {
+    //a valid logo means this is a plain rom, whatever its first bytes look like
+    if bytes.len() >= HEADER_END && bytes[0x104..0x134] == NINTENDO_LOGO[..] {
+        return Ok(bytes);
+    }
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut rom = Vec::new();
+        GzDecoder::new(&bytes[..])
+            .read_to_end(&mut rom)
+            .map_err(|e| RomError::Archive(e.to_string()))?;
+        return Ok(rom);
+    }
+    if bytes.starts_with(b"PK\x03\x04") {
+        let mut archive =
+            ZipArchive::new(Cursor::new(bytes)).map_err(|e| RomError::Archive(e.to_string()))?;
+        for i in 0..archive.len() {
+            let mut file = archive
+                .by_index(i)
+                .map_err(|e| RomError::Archive(e.to_string()))?;
+            let name = file.name().to_lowercase();
+            if name.ends_with(".gb") || name.ends_with(".gbc") {
+                let mut rom = Vec::new();
+                file.read_to_end(&mut rom)
+                    .map_err(|e| RomError::Archive(e.to_string()))?;
+                return Ok(rom);
+            }
+        }
+        return Err(RomError::NoRomInArchive);
+    }
+    Ok(bytes)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum CartridgeType {
     ROM,
     MBC1,
     MBC2,
@@ -43,92 +204,318 @@ enum CartridgeType {
     MBC5,
 }
 
-#[derive(Debug)]
-enum ColorSupport {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorSupport {
     None,
     Supported,
     Required,
 }
 
+///Hardware on the cartridge besides the rom and memory bank controller, from the type code at 0x147
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CartridgeFeatures {
+    ///external (or for MBC2, built in) ram
+    pub ram: bool,
+    ///battery keeping the ram and/or clock alive
+    pub battery: bool,
+    ///real time clock
+    pub timer: bool,
+    ///rumble motor
+    pub rumble: bool,
+}
+
+///Publisher of the game, older cartridges use a single byte code, newer ones two ascii characters
+#[derive(Debug, Clone, PartialEq)]
+pub enum Licensee {
+    ///0x14b
+    Old(u8),
+    ///0x144 - 0x145, used when the old code is 0x33
+    New(String),
+}
+
+impl Licensee {
+    pub fn name(&self) -> Option<&'static str> {
+        match *self {
+            Licensee::Old(code) => licensee::old_licensee_name(code),
+            Licensee::New(ref code) => licensee::new_licensee_name(code),
+        }
+    }
+}
+
+///Problems with a header that don't stop the rom from running on an emulator
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderWarning {
+    ///0x104 - 0x133 isn't the Nintendo logo, the boot rom would lock up
+    BadLogo,
+    ///0x14d doesn't match the checksum of 0x134 - 0x14c, the boot rom would lock up
+    HeaderChecksum { expected: u8, actual: u8 },
+    ///0x14e - 0x14f doesn't match the sum of the rest of the rom (not checked by real hardware)
+    GlobalChecksum { expected: u16, actual: u16 },
+}
+
+impl fmt::Display for HeaderWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HeaderWarning::BadLogo => write!(f, "Nintendo logo doesn't match"),
+            HeaderWarning::HeaderChecksum { expected, actual } => write!(
+                f,
+                "header checksum mismatch (header says {:#04x}, computed {:#04x})",
+                expected, actual
+            ),
+            HeaderWarning::GlobalChecksum { expected, actual } => write!(
+                f,
+                "global checksum mismatch (header says {:#06x}, computed {:#06x})",
+                expected, actual
+            ),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct CartridgeHeader {
-    title: String,
-    color: ColorSupport,
-    model: CartridgeType,
-    logo: Vec<u8>,
-    rom_size_kb: u16,
-    rom_banks: u16,
-    ram_size_kb: u16,
-    ram_banks: u16,
-    japanese: bool,
-    checksum: u8,
+    ///0x134 - 0x143, shortened to make room for the manufacturer code and cgb flag on newer cartridges
+    pub title: String,
+    ///0x13f - 0x142, only present on some newer cartridges
+    pub manufacturer_code: Option<String>,
+    ///0x143
+    pub color: ColorSupport,
+    ///0x14b, or 0x144 - 0x145 if 0x14b is 0x33
+    pub licensee: Licensee,
+    ///0x146, supports super game boy functions
+    pub sgb: bool,
+    ///0x147, memory bank controller, None if it's one that can't be emulated
+    pub model: Option<CartridgeType>,
+    ///raw cartridge type code at 0x147, distinguishes variants of the same controller
+    pub type_code: u8,
+    pub features: CartridgeFeatures,
+    ///0x104 - 0x133
+    pub logo: Vec<u8>,
+    ///raw rom size code at 0x148
+    pub rom_size_code: u8,
+    ///0x148, both 0 if the size code is unknown
+    pub rom_size_kb: u16,
+    pub rom_banks: u16,
+    ///0x149
+    pub ram_size_kb: u16,
+    pub ram_banks: u16,
+    ///0x14a, destination code
+    pub japanese: bool,
+    ///0x14c, mask rom version number
+    pub version: u8,
+    ///0x14d, checksum of 0x134 - 0x14c
+    pub checksum: u8,
+    ///0x14e - 0x14f, big endian sum of every other byte in the rom
+    pub global_checksum: u16,
+    ///failed logo and checksum checks
+    pub warnings: Vec<HeaderWarning>,
 }
+
+impl CartridgeHeader {
+    ///Whether the cartridge type code includes a battery for its ram or clock
+    pub fn has_battery(&self) -> bool {
+        self.features.battery
+    }
+}
+
+///Logo the boot rom compares against 0x104 - 0x133
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xce, 0xed, 0x66, 0x66, 0xcc, 0x0d, 0x00, 0x0b, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0c, 0x00, 0x0d,
+    0x00, 0x08, 0x11, 0x1f, 0x88, 0x89, 0x00, 0x0e, 0xdc, 0xcc, 0x6e, 0xe6, 0xdd, 0xdd, 0xd9, 0x99,
+    0xbb, 0xbb, 0x67, 0x63, 0x6e, 0x0e, 0xec, 0xcc, 0xdd, 0xdc, 0x99, 0x9f, 0xbb, 0xb9, 0x33, 0x3e,
+];
+
+///Everything up to and including the global checksum
+const HEADER_END: usize = 0x150;
+
 type Block16Kb = [u8; 0x4000];
 
-fn parse_cartridge_type(code: u8) -> Result<CartridgeType, String> {
+///Controller for the cartridge type codes that can be emulated
+fn parse_cartridge_type(code: u8) -> Option<CartridgeType> {
     match code {
-        0x00 => Ok(CartridgeType::ROM),
-        0x1 | 0x2 | 0x3 => Ok(CartridgeType::MBC1),
-        0x5 | 0x6 => Ok(CartridgeType::MBC2),
-        0xf | 0x10 | 0x11 | 0x12 | 0x13 => Ok(CartridgeType::MBC3),
-        0x19 | 0x1a | 0x1b | 0x1c | 0x1d | 0x1e => Ok(CartridgeType::MBC5),
-        _ => Err("Unknown cartridge type code".to_string()),
+        0x00 => Some(CartridgeType::ROM),
+        0x1..=0x3 => Some(CartridgeType::MBC1),
+        0x5 | 0x6 => Some(CartridgeType::MBC2),
+        0xf..=0x13 => Some(CartridgeType::MBC3),
+        0x19..=0x1e => Some(CartridgeType::MBC5),
+        _ => None,
+    }
+}
+///Full name of a cartridge type code, as listed in the pan docs
+pub fn cartridge_type_name(code: u8) -> Option<&'static str> {
+    let name = match code {
+        0x00 => "ROM ONLY",
+        0x01 => "MBC1",
+        0x02 => "MBC1+RAM",
+        0x03 => "MBC1+RAM+BATTERY",
+        0x05 => "MBC2",
+        0x06 => "MBC2+BATTERY",
+        0x08 => "ROM+RAM",
+        0x09 => "ROM+RAM+BATTERY",
+        0x0b => "MMM01",
+        0x0c => "MMM01+RAM",
+        0x0d => "MMM01+RAM+BATTERY",
+        0x0f => "MBC3+TIMER+BATTERY",
+        0x10 => "MBC3+TIMER+RAM+BATTERY",
+        0x11 => "MBC3",
+        0x12 => "MBC3+RAM",
+        0x13 => "MBC3+RAM+BATTERY",
+        0x19 => "MBC5",
+        0x1a => "MBC5+RAM",
+        0x1b => "MBC5+RAM+BATTERY",
+        0x1c => "MBC5+RUMBLE",
+        0x1d => "MBC5+RUMBLE+RAM",
+        0x1e => "MBC5+RUMBLE+RAM+BATTERY",
+        0x20 => "MBC6",
+        0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+        0xfc => "POCKET CAMERA",
+        0xfd => "BANDAI TAMA5",
+        0xfe => "HuC3",
+        0xff => "HuC1+RAM+BATTERY",
+        _ => return None,
+    };
+    Some(name)
+}
+fn parse_cartridge_features(code: u8) -> CartridgeFeatures {
+    CartridgeFeatures {
+        ram: matches!(
+            code,
+            0x02 | 0x03 | 0x05 | 0x06 | 0x08 | 0x09 | 0x0c | 0x0d | 0x10 | 0x12 | 0x13 | 0x1a
+                | 0x1b | 0x1d | 0x1e | 0x22 | 0xfc | 0xfe | 0xff
+        ),
+        battery: matches!(
+            code,
+            0x03 | 0x06 | 0x09 | 0x0d | 0x0f | 0x10 | 0x13 | 0x1b | 0x1e | 0x22 | 0xfc | 0xfe
+                | 0xff
+        ),
+        timer: matches!(code, 0x0f | 0x10 | 0xfe),
+        rumble: matches!(code, 0x1c..=0x1e | 0x22),
     }
 }
-fn parse_rom_size(code: u8) -> Result<(u16, u16), String> {
+fn parse_rom_size(code: u8) -> Option<(u16, u16)> {
     match code {
-        0x0 => Ok((32, 1)),
-        0x1 => Ok((64, 4)),
-        0x2 => Ok((128, 8)),
-        0x3 => Ok((256, 16)),
-        0x4 => Ok((512, 32)),
-        0x5 => Ok((1024, 64)),
-        0x6 => Ok((2048, 128)),
-        0x7 => Ok((4096, 256)),
-        0x8 => Ok((8192, 512)),
-        _ => Err("Unrecognized rom size code".to_string()),
+        0x0 => Some((32, 2)),
+        0x1 => Some((64, 4)),
+        0x2 => Some((128, 8)),
+        0x3 => Some((256, 16)),
+        0x4 => Some((512, 32)),
+        0x5 => Some((1024, 64)),
+        0x6 => Some((2048, 128)),
+        0x7 => Some((4096, 256)),
+        0x8 => Some((8192, 512)),
+        _ => None,
     }
 }
 fn parse_ram_size(code: u8) -> (u16, u16) {
     match code {
-        0x1 => (2, 0),
-        0x2 => (8, 0),
+        0x1 => (2, 1),
+        0x2 => (8, 1),
         0x3 => (32, 4),
         0x4 => (128, 16),
         0x5 => (64, 8),
         _ => (0, 0),
     }
 }
-
-fn parse_header(dat: Vec<u8>) -> Result<CartridgeHeader, String> {
-    let title: String = dat[0x0134..0x0143]
+///Title and manufacturer code, whose layout depends on the cgb flag
+fn parse_title(dat: &[u8]) -> (String, Option<String>) {
+    let ascii = |bytes: &[u8]| -> String {
+        bytes
+            .iter()
+            .take_while(|x| **x != 0)
+            .map(|x| *x as char)
+            .collect()
+    };
+    //cartridges from before the cgb use all 16 bytes for the title
+    if dat[0x143] & 0x80 == 0 {
+        return (ascii(&dat[0x134..0x144]), None);
+    }
+    let manufacturer = &dat[0x13f..0x143];
+    if manufacturer
         .iter()
-        .map(|x| x.clone() as char)
-        .collect();
+        .all(|x| x.is_ascii_uppercase() || x.is_ascii_digit())
+    {
+        (ascii(&dat[0x134..0x13f]), Some(ascii(manufacturer)))
+    } else {
+        (ascii(&dat[0x134..0x143]), None)
+    }
+}
+///Checksum the boot rom verifies: x = x - byte - 1 over 0x134 - 0x14c
+pub fn header_checksum(dat: &[u8]) -> u8 {
+    dat[0x134..0x14d]
+        .iter()
+        .fold(0u8, |x, b| x.wrapping_sub(*b).wrapping_sub(1))
+}
+///Sum of every byte in the rom except the global checksum itself
+pub fn global_checksum(dat: &[u8]) -> u16 {
+    dat.iter()
+        .enumerate()
+        .filter(|&(i, _)| i != 0x14e && i != 0x14f)
+        .fold(0u16, |x, (_, b)| x.wrapping_add(*b as u16))
+}
+
+///Decodes the header, whether or not the cartridge can be emulated (parse_rom checks that)
+pub fn parse_header(dat: &[u8]) -> Result<CartridgeHeader, RomError> {
+    if dat.len() < HEADER_END {
+        return Err(RomError::TooSmall { len: dat.len() });
+    }
+    let (title, manufacturer_code) = parse_title(dat);
     let color = match dat[0x0143] {
         0x80 => ColorSupport::Supported,
         0xc0 => ColorSupport::Required,
         _ => ColorSupport::None,
     };
-    let logo: Vec<u8> = dat[0x104..0x0133].iter().cloned().collect();
-    let model = parse_cartridge_type(dat[0x0147])?;
-    let (romsize, rombanks) = parse_rom_size(dat[0x0148])?;
+    let licensee = match dat[0x014b] {
+        0x33 => Licensee::New(dat[0x0144..0x0146].iter().map(|x| *x as char).collect()),
+        code => Licensee::Old(code),
+    };
+    let logo: Vec<u8> = dat[0x104..0x0134].to_vec();
+    let model = parse_cartridge_type(dat[0x0147]);
+    let (romsize, rombanks) = parse_rom_size(dat[0x0148]).unwrap_or((0, 0));
     let (ramsize, rambanks) = parse_ram_size(dat[0x0149]);
     let japanese = dat[0x014a] == 0x00;
     let checksum = dat[0x014d];
+    let global = join_u8(dat[0x014e], dat[0x014f]);
+
+    let mut warnings = Vec::new();
+    if logo[..] != NINTENDO_LOGO[..] {
+        warnings.push(HeaderWarning::BadLogo);
+    }
+    let actual = header_checksum(dat);
+    if actual != checksum {
+        warnings.push(HeaderWarning::HeaderChecksum {
+            expected: checksum,
+            actual,
+        });
+    }
+    let actual = global_checksum(dat);
+    if actual != global {
+        warnings.push(HeaderWarning::GlobalChecksum {
+            expected: global,
+            actual,
+        });
+    }
 
-    return Ok(CartridgeHeader {
-        title: title,
-        color: color,
-        model: model,
-        logo: logo,
+    Ok(CartridgeHeader {
+        title,
+        manufacturer_code,
+        color,
+        licensee,
+        //only counts when the old licensee code also says so
+        sgb: dat[0x0146] == 0x03 && dat[0x014b] == 0x33,
+        model,
+        type_code: dat[0x0147],
+        features: parse_cartridge_features(dat[0x0147]),
+        logo,
+        rom_size_code: dat[0x0148],
         rom_size_kb: romsize,
         rom_banks: rombanks,
         ram_size_kb: ramsize,
         ram_banks: rambanks,
-        japanese: japanese,
-        checksum: checksum,
-    });
+        japanese,
+        version: dat[0x014c],
+        checksum,
+        global_checksum: global,
+        warnings,
+    })
 }
 
 fn split_to_blocks(dat: Vec<u8>) -> Vec<Block16Kb> {
@@ -143,31 +530,723 @@ fn split_to_blocks(dat: Vec<u8>) -> Vec<Block16Kb> {
             block.clone_from_slice(&dat[offset..offset + size]);
             res.push(block);
         } else {
-            let mut temp: Vec<u8> = dat[offset..end_address].iter().cloned().collect();
+            let mut temp: Vec<u8> = dat[offset..end_address].to_vec();
             temp.resize(size, 0);
             let mut block = [0; 0x4000];
             block.clone_from_slice(&temp[..]);
             res.push(block);
         }
     }
-    return res;
+    res
 }
 
-fn parse_rom(header: CartridgeHeader, data: Vec<u8>) -> Result<Box<Cartridge>, String> {
-    match header.model {
+fn parse_rom(header: CartridgeHeader, mut data: Vec<u8>) -> Result<Box<dyn Cartridge>, RomError> {
+    let model = header
+        .model
+        .ok_or(RomError::UnknownCartridgeType(header.type_code))?;
+    if header.rom_banks == 0 {
+        return Err(RomError::UnknownRomSize(header.rom_size_code));
+    }
+    let expected = header.rom_size_kb as usize * 1024;
+    if data.len() < expected {
+        return Err(RomError::SizeMismatch {
+            expected,
+            actual: data.len(),
+        });
+    }
+    //overdumped or padded images work, the cartridge just doesn't have the extra bytes
+    if data.len() > expected {
+        info!(
+            "{}: ignoring {} bytes past the rom size in the header",
+            header.title,
+            data.len() - expected
+        );
+        data.truncate(expected);
+    }
+    match model {
         CartridgeType::ROM => Ok(Box::new(RomCartridge {
-            header: header,
+            header,
             memory: split_to_blocks(data),
         })),
-        _ => Err("Not supported yet".to_string()),
+        CartridgeType::MBC1 => Ok(Box::new(Mbc1Cartridge::new(header, data))),
+        CartridgeType::MBC2 => Ok(Box::new(Mbc2Cartridge::new(header, data))),
+        CartridgeType::MBC3 => Ok(Box::new(Mbc3Cartridge::new(header, data))),
+        CartridgeType::MBC5 => Ok(Box::new(Mbc5Cartridge::new(header, data))),
     }
 }
 
-// pub struct Mbc1Cartridge{
-//     header : CartridgeHeader,
-//     blocks : Vec<Block16Kb>,
-//     current_block : usize
-// }
+///Size of a single switchable external ram bank (0xa000 - 0xbfff)
+const RAM_BANK_SIZE: usize = 0x2000;
+
+pub struct Mbc1Cartridge {
+    header: CartridgeHeader,
+    blocks: Vec<Block16Kb>,
+    ///0x0000 - 0x1fff, external ram is only accessible after writing 0x_a here
+    ram_enabled: bool,
+    ///0x2000 - 0x3fff, lower 5 bits of the rom bank mapped to 0x4000 - 0x7fff (0 is treated as 1)
+    rom_bank: u8,
+    ///0x4000 - 0x5fff, 2 bit ram bank number or upper 2 bits of the rom bank number
+    upper_bank: u8,
+    ///0x6000 - 0x7fff, false = simple banking, true = upper bank also applies to 0x0000 - 0x3fff and ram
+    advanced_banking: bool,
+    ///0xa000 - 0xbfff, up to 4 banks of 8KB
+    ram: Vec<u8>,
+}
+
+impl Mbc1Cartridge {
+    pub fn new(header: CartridgeHeader, data: Vec<u8>) -> Self {
+        let ram = vec![0; header.ram_size_kb as usize * 1024];
+        Mbc1Cartridge {
+            header,
+            blocks: split_to_blocks(data),
+            ram_enabled: false,
+            rom_bank: 1,
+            upper_bank: 0,
+            advanced_banking: false,
+            ram,
+        }
+    }
+    ///Bank mapped to 0x0000 - 0x3fff, only affected by the upper bits in advanced banking mode
+    fn block_0_index(&self) -> usize {
+        let bank = if self.advanced_banking {
+            (self.upper_bank as usize) << 5
+        } else {
+            0
+        };
+        bank % self.blocks.len()
+    }
+    ///Bank mapped to 0x4000 - 0x7fff
+    fn block_1_index(&self) -> usize {
+        let bank = ((self.upper_bank as usize) << 5) | self.rom_bank as usize;
+        bank % self.blocks.len()
+    }
+    ///Offset into external ram for an address in 0xa000 - 0xbfff, or None if no ram is mapped
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+        let bank = if self.advanced_banking {
+            self.upper_bank as usize
+        } else {
+            0
+        };
+        let offset = bank * RAM_BANK_SIZE + (addr - 0xa000) as usize;
+        Some(offset % self.ram.len())
+    }
+}
+
+impl Cartridge for Mbc1Cartridge {
+    fn get_header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+    fn get_block_0(&self) -> &Block16Kb {
+        &self.blocks[self.block_0_index()]
+    }
+    fn get_block_1(&self) -> &Block16Kb {
+        &self.blocks[self.block_1_index()]
+    }
+    fn swap_block_1(&mut self, bank: usize) {
+        self.rom_bank = match (bank & 0x1f) as u8 {
+            0 => 1,
+            x => x,
+        };
+        self.upper_bank = ((bank >> 5) & 0x3) as u8;
+    }
+    fn read8(&self, addr: u16) -> u8 {
+        match addr {
+            0x0..=0x3fff => self.get_block_0()[addr as usize],
+            0x4000..=0x7fff => self.get_block_1()[(addr - 0x4000) as usize],
+            0xa000..=0xbfff => match self.ram_offset(addr) {
+                Some(offset) => self.ram[offset],
+                //disabled or missing ram reads as an open bus
+                None => 0xff,
+            },
+            _ => 0,
+        }
+    }
+    fn write8(&mut self, addr: u16, dat: u8) {
+        match addr {
+            0x0..=0x1fff => self.ram_enabled = dat & 0xf == 0xa,
+            0x2000..=0x3fff => {
+                //the zero check only looks at the 5 bits of the register, so banks 0x20/0x40/0x60 can't be selected
+                self.rom_bank = match dat & 0x1f {
+                    0 => 1,
+                    x => x,
+                }
+            }
+            0x4000..=0x5fff => self.upper_bank = dat & 0x3,
+            0x6000..=0x7fff => self.advanced_banking = dat & 0x1 == 1,
+            0xa000..=0xbfff => {
+                if let Some(offset) = self.ram_offset(addr) {
+                    self.ram[offset] = dat;
+                }
+            }
+            _ => (),
+        }
+    }
+    fn get_save_data(&self) -> Vec<u8> {
+        self.ram.clone()
+    }
+    fn load_save_data(&mut self, dat: &[u8]) {
+        copy_save_ram(&mut self.ram, dat);
+    }
+}
+
+pub struct Mbc2Cartridge {
+    header: CartridgeHeader,
+    blocks: Vec<Block16Kb>,
+    ///0x0000 - 0x3fff with address bit 8 clear, ram is only accessible after writing 0x_a here
+    ram_enabled: bool,
+    ///0x0000 - 0x3fff with address bit 8 set, 4 bit rom bank mapped to 0x4000 - 0x7fff (0 is treated as 1)
+    rom_bank: u8,
+    ///0xa000 - 0xa1ff, 512 half-bytes of built in ram (only the lower nibble is stored)
+    ram: [u8; 0x200],
+}
+
+impl Mbc2Cartridge {
+    pub fn new(header: CartridgeHeader, data: Vec<u8>) -> Self {
+        Mbc2Cartridge {
+            header,
+            blocks: split_to_blocks(data),
+            ram_enabled: false,
+            rom_bank: 1,
+            ram: [0; 0x200],
+        }
+    }
+}
+
+impl Cartridge for Mbc2Cartridge {
+    fn get_header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+    fn get_block_0(&self) -> &Block16Kb {
+        &self.blocks[0]
+    }
+    fn get_block_1(&self) -> &Block16Kb {
+        &self.blocks[self.rom_bank as usize % self.blocks.len()]
+    }
+    fn swap_block_1(&mut self, bank: usize) {
+        self.rom_bank = match (bank & 0xf) as u8 {
+            0 => 1,
+            x => x,
+        };
+    }
+    fn read8(&self, addr: u16) -> u8 {
+        match addr {
+            0x0..=0x3fff => self.get_block_0()[addr as usize],
+            0x4000..=0x7fff => self.get_block_1()[(addr - 0x4000) as usize],
+            //only 9 address lines are wired to the ram, so it repeats across the whole area
+            //and the missing upper 4 data lines read as 1s
+            0xa000..=0xbfff if self.ram_enabled => 0xf0 | self.ram[(addr & 0x1ff) as usize],
+            //disabled ram reads as an open bus
+            0xa000..=0xbfff => 0xff,
+            _ => 0,
+        }
+    }
+    fn write8(&mut self, addr: u16, dat: u8) {
+        match addr {
+            //address bit 8 picks the register
+            0x0..=0x3fff if nth_bit16(addr, 8) => self.swap_block_1(dat as usize),
+            0x0..=0x3fff => self.ram_enabled = dat & 0xf == 0xa,
+            0xa000..=0xbfff if self.ram_enabled => self.ram[(addr & 0x1ff) as usize] = dat & 0xf,
+            _ => (),
+        }
+    }
+    ///One byte per half-byte cell, the same layout other emulators use
+    fn get_save_data(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+    fn load_save_data(&mut self, dat: &[u8]) {
+        copy_save_ram(&mut self.ram, dat);
+        for cell in self.ram.iter_mut() {
+            *cell &= 0xf;
+        }
+    }
+}
+
+pub struct Mbc3Cartridge {
+    header: CartridgeHeader,
+    blocks: Vec<Block16Kb>,
+    ///0x0000 - 0x1fff, enables both external ram and the rtc registers
+    ram_enabled: bool,
+    ///0x2000 - 0x3fff, 7 bit rom bank mapped to 0x4000 - 0x7fff (0 is treated as 1)
+    rom_bank: u8,
+    ///0x4000 - 0x5fff, 0x00 - 0x03 maps a ram bank, 0x08 - 0x0c maps an rtc register
+    bank_select: u8,
+    ///0x6000 - 0x7fff, last value written (writing 0x00 then 0x01 latches the clock)
+    latch: u8,
+    ///0xa000 - 0xbfff, up to 4 banks of 8KB
+    ram: Vec<u8>,
+    ///only cartridge types 0x0f and 0x10 actually have the clock
+    has_rtc: bool,
+    rtc: Rtc,
+}
+
+impl Mbc3Cartridge {
+    pub fn new(header: CartridgeHeader, data: Vec<u8>) -> Self {
+        let ram = vec![0; header.ram_size_kb as usize * 1024];
+        let has_rtc = header.features.timer;
+        Mbc3Cartridge {
+            header,
+            blocks: split_to_blocks(data),
+            ram_enabled: false,
+            rom_bank: 1,
+            bank_select: 0,
+            latch: 0xff,
+            ram,
+            has_rtc,
+            rtc: Rtc::new(),
+        }
+    }
+    ///Offset into external ram for an address in 0xa000 - 0xbfff, or None if no ram is mapped
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        let offset = self.bank_select as usize * RAM_BANK_SIZE + (addr - 0xa000) as usize;
+        Some(offset % self.ram.len())
+    }
+}
+
+impl Cartridge for Mbc3Cartridge {
+    fn get_header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+    fn get_block_0(&self) -> &Block16Kb {
+        &self.blocks[0]
+    }
+    fn get_block_1(&self) -> &Block16Kb {
+        &self.blocks[self.rom_bank as usize % self.blocks.len()]
+    }
+    fn swap_block_1(&mut self, bank: usize) {
+        self.rom_bank = match (bank & 0x7f) as u8 {
+            0 => 1,
+            x => x,
+        };
+    }
+    fn read8(&self, addr: u16) -> u8 {
+        match addr {
+            0x0..=0x3fff => self.get_block_0()[addr as usize],
+            0x4000..=0x7fff => self.get_block_1()[(addr - 0x4000) as usize],
+            0xa000..=0xbfff if self.ram_enabled => match self.bank_select {
+                0x0..=0x3 => match self.ram_offset(addr) {
+                    Some(offset) => self.ram[offset],
+                    None => 0xff,
+                },
+                0x8..=0xc if self.has_rtc => self.rtc.read(self.bank_select),
+                _ => 0xff,
+            },
+            //disabled ram reads as an open bus
+            0xa000..=0xbfff => 0xff,
+            _ => 0,
+        }
+    }
+    fn write8(&mut self, addr: u16, dat: u8) {
+        match addr {
+            0x0..=0x1fff => self.ram_enabled = dat & 0xf == 0xa,
+            0x2000..=0x3fff => self.swap_block_1(dat as usize),
+            0x4000..=0x5fff => self.bank_select = dat,
+            0x6000..=0x7fff => {
+                if self.latch == 0 && dat == 1 {
+                    self.rtc.latch();
+                }
+                self.latch = dat;
+            }
+            0xa000..=0xbfff if self.ram_enabled => match self.bank_select {
+                0x0..=0x3 => {
+                    if let Some(offset) = self.ram_offset(addr) {
+                        self.ram[offset] = dat;
+                    }
+                }
+                0x8..=0xc if self.has_rtc => self.rtc.write(self.bank_select, dat),
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+    fn tick(&mut self, cycles: u32) {
+        if self.has_rtc {
+            self.rtc.tick(cycles);
+        }
+    }
+    fn get_rtc_mut(&mut self) -> Option<&mut Rtc> {
+        if self.has_rtc {
+            Some(&mut self.rtc)
+        } else {
+            None
+        }
+    }
+    ///External ram followed by the 48 byte rtc trailer
+    fn get_save_data(&self) -> Vec<u8> {
+        let mut dat = self.ram.clone();
+        if self.has_rtc {
+            dat.extend_from_slice(&self.rtc.get_save_trailer());
+        }
+        dat
+    }
+    fn load_save_data(&mut self, dat: &[u8]) {
+        copy_save_ram(&mut self.ram, dat);
+        if self.has_rtc && dat.len() > self.ram.len() {
+            self.rtc.load_save_trailer(&dat[self.ram.len()..]);
+        }
+    }
+    fn get_save_timestamp_len(&self) -> usize {
+        if self.has_rtc {
+            RTC_TIMESTAMP_SIZE
+        } else {
+            0
+        }
+    }
+}
+
+pub struct Mbc5Cartridge {
+    header: CartridgeHeader,
+    blocks: Vec<Block16Kb>,
+    ///0x0000 - 0x1fff, external ram is only accessible after writing 0x0a here
+    ram_enabled: bool,
+    ///0x2000 - 0x2fff (lower 8 bits) and 0x3000 - 0x3fff (bit 8), bank 0 can be mapped too
+    rom_bank: u16,
+    ///0x4000 - 0x5fff, ram bank number (bit 3 drives the motor on rumble cartridges)
+    ram_bank: u8,
+    ///0xa000 - 0xbfff, up to 16 banks of 8KB
+    ram: Vec<u8>,
+    ///rumble cartridges use the 4th ram bank bit for the motor
+    has_rumble: bool,
+    rumble: bool,
+    rumble_callback: Option<Box<dyn FnMut(bool)>>,
+}
+
+impl Mbc5Cartridge {
+    pub fn new(header: CartridgeHeader, data: Vec<u8>) -> Self {
+        let ram = vec![0; header.ram_size_kb as usize * 1024];
+        let has_rumble = header.features.rumble;
+        Mbc5Cartridge {
+            header,
+            blocks: split_to_blocks(data),
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram,
+            has_rumble,
+            rumble: false,
+            rumble_callback: None,
+        }
+    }
+    ///Offset into external ram for an address in 0xa000 - 0xbfff, or None if no ram is mapped
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+        let offset = self.ram_bank as usize * RAM_BANK_SIZE + (addr - 0xa000) as usize;
+        Some(offset % self.ram.len())
+    }
+    fn set_rumble(&mut self, on: bool) {
+        if self.rumble == on {
+            return;
+        }
+        self.rumble = on;
+        if let Some(ref mut callback) = self.rumble_callback {
+            callback(on);
+        }
+    }
+}
+
+impl Cartridge for Mbc5Cartridge {
+    fn get_header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+    fn get_block_0(&self) -> &Block16Kb {
+        &self.blocks[0]
+    }
+    fn get_block_1(&self) -> &Block16Kb {
+        &self.blocks[self.rom_bank as usize % self.blocks.len()]
+    }
+    fn swap_block_1(&mut self, bank: usize) {
+        self.rom_bank = (bank & 0x1ff) as u16;
+    }
+    fn read8(&self, addr: u16) -> u8 {
+        match addr {
+            0x0..=0x3fff => self.get_block_0()[addr as usize],
+            0x4000..=0x7fff => self.get_block_1()[(addr - 0x4000) as usize],
+            0xa000..=0xbfff => match self.ram_offset(addr) {
+                Some(offset) => self.ram[offset],
+                //disabled or missing ram reads as an open bus
+                None => 0xff,
+            },
+            _ => 0,
+        }
+    }
+    fn write8(&mut self, addr: u16, dat: u8) {
+        match addr {
+            0x0..=0x1fff => self.ram_enabled = dat == 0x0a,
+            0x2000..=0x2fff => self.rom_bank = (self.rom_bank & 0x100) | dat as u16,
+            0x3000..=0x3fff => self.rom_bank = (self.rom_bank & 0xff) | ((dat as u16 & 0x1) << 8),
+            0x4000..=0x5fff => {
+                if self.has_rumble {
+                    self.set_rumble(nth_bit(dat, 3));
+                    self.ram_bank = dat & 0x7;
+                } else {
+                    self.ram_bank = dat & 0xf;
+                }
+            }
+            0xa000..=0xbfff => {
+                if let Some(offset) = self.ram_offset(addr) {
+                    self.ram[offset] = dat;
+                }
+            }
+            _ => (),
+        }
+    }
+    fn is_rumbling(&self) -> bool {
+        self.rumble
+    }
+    fn set_rumble_callback(&mut self, callback: Box<dyn FnMut(bool)>) {
+        self.rumble_callback = Some(callback);
+    }
+    fn get_save_data(&self) -> Vec<u8> {
+        self.ram.clone()
+    }
+    fn load_save_data(&mut self, dat: &[u8]) {
+        copy_save_ram(&mut self.ram, dat);
+    }
+}
+
+///Where the real time clock gets its notion of elapsed time from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockSource {
+    ///Advanced by emulated cycles, so it stops while the emulator is paused or fast-forwards with it
+    Emulated,
+    ///Follows the host's wall clock, so in-game time keeps passing between sessions
+    Host,
+}
+
+///Size of the rtc data appended to MBC3 save files
+pub const RTC_TRAILER_SIZE: usize = 48;
+///The unix timestamp closing the rtc trailer
+const RTC_TIMESTAMP_SIZE: usize = 8;
+
+///MBC3 real time clock
+pub struct Rtc {
+    ///0x08, 0 - 59
+    seconds: u8,
+    ///0x09, 0 - 59
+    minutes: u8,
+    ///0x0a, 0 - 23
+    hours: u8,
+    ///0x0b/0x0c, 9 bit day counter
+    days: u16,
+    ///0x0c bit 6, stops the clock
+    halted: bool,
+    ///0x0c bit 7, set when the day counter overflows, stays set until cleared by a write
+    day_carry: bool,
+    ///register values copied by the last latch, in 0x08 - 0x0c order
+    latched: [u8; 5],
+    ///T-cycles accumulated towards the next second
+    cycles: u32,
+    source: ClockSource,
+    ///host time up to which the clock has been advanced (only used with ClockSource::Host)
+    last_sync: SystemTime,
+}
+
+impl Rtc {
+    pub fn new() -> Self {
+        Rtc {
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
+            halted: false,
+            day_carry: false,
+            latched: [0; 5],
+            cycles: 0,
+            source: ClockSource::Emulated,
+            last_sync: SystemTime::now(),
+        }
+    }
+    pub fn get_clock_source(&self) -> ClockSource {
+        self.source
+    }
+    pub fn set_clock_source(&mut self, source: ClockSource) {
+        self.sync();
+        self.source = source;
+        self.last_sync = SystemTime::now();
+    }
+    ///Advances the clock by emulated T-cycles, ignored when following the host clock
+    pub fn tick(&mut self, cycles: u32) {
+        if self.source != ClockSource::Emulated || self.halted {
+            return;
+        }
+        self.cycles += cycles;
+        while self.cycles >= CLOCK_SPEED {
+            self.cycles -= CLOCK_SPEED;
+            self.advance_second();
+        }
+    }
+    ///Catches the clock up with the host clock, does nothing when driven by emulated cycles
+    pub fn sync(&mut self) {
+        if self.source != ClockSource::Host {
+            return;
+        }
+        let now = SystemTime::now();
+        //the host clock going backwards just leaves the rtc where it is
+        if let Ok(elapsed) = now.duration_since(self.last_sync) {
+            let seconds = elapsed.as_secs();
+            self.last_sync += Duration::from_secs(seconds);
+            if !self.halted {
+                self.advance_seconds(seconds);
+            }
+        } else {
+            self.last_sync = now;
+        }
+    }
+    ///Advances the running clock by whole seconds, e.g. to account for time spent outside the emulator
+    pub fn advance_seconds(&mut self, seconds: u64) {
+        let mut remaining = seconds;
+        //out of range values (only possible through writes) tick one at a time until they wrap back into range
+        while remaining > 0 && (self.seconds > 59 || self.minutes > 59 || self.hours > 23) {
+            self.advance_second();
+            remaining -= 1;
+        }
+        if remaining == 0 {
+            return;
+        }
+        let total = remaining
+            + self.seconds as u64
+            + self.minutes as u64 * 60
+            + self.hours as u64 * 3600
+            + self.days as u64 * 86400;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        let days = total / 86400;
+        if days > 0x1ff {
+            self.day_carry = true;
+        }
+        self.days = (days & 0x1ff) as u16;
+    }
+    fn advance_second(&mut self) {
+        //each counter only carries when it hits its limit, out of range values wrap at their bit width instead
+        self.seconds = (self.seconds + 1) & 0x3f;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3f;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1f;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.days += 1;
+        if self.days > 0x1ff {
+            self.days = 0;
+            self.day_carry = true;
+        }
+    }
+    ///Copies the running clock into the registers visible at 0xa000 - 0xbfff
+    pub fn latch(&mut self) {
+        self.sync();
+        self.latched = [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            self.day_high(),
+        ];
+    }
+    ///0x0c layout: bit 0 = day counter bit 8, bit 6 = halt, bit 7 = day counter carry
+    fn day_high(&self) -> u8 {
+        ((self.days >> 8) as u8 & 0x1) | ((self.halted as u8) << 6) | ((self.day_carry as u8) << 7)
+    }
+    ///Trailer appended to MBC3 saves by most emulators: the live registers and the latched registers
+    ///as 5 little endian u32 each (seconds, minutes, hours, day low, day high), then a 64 bit unix timestamp
+    pub fn get_save_trailer(&self) -> [u8; RTC_TRAILER_SIZE] {
+        let mut trailer = [0; RTC_TRAILER_SIZE];
+        let live = [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            self.day_high(),
+        ];
+        for (i, reg) in live.iter().chain(self.latched.iter()).enumerate() {
+            trailer[i * 4] = *reg;
+        }
+        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(x) => x.as_secs(),
+            Err(_) => 0,
+        };
+        for i in 0..RTC_TIMESTAMP_SIZE {
+            trailer[40 + i] = (timestamp >> (i * 8)) as u8;
+        }
+        trailer
+    }
+    ///Restores a save trailer, accepting both the 48 byte and the older 44 byte (32 bit timestamp) layout.
+    ///When following the host clock, the time since the save was written is added to the clock.
+    pub fn load_save_trailer(&mut self, dat: &[u8]) {
+        if dat.len() < 44 {
+            warn!("Ignoring truncated rtc save data ({} bytes)", dat.len());
+            return;
+        }
+        let reg = |i: usize| dat[i * 4];
+        self.seconds = reg(0) & 0x3f;
+        self.minutes = reg(1) & 0x3f;
+        self.hours = reg(2) & 0x1f;
+        self.days = reg(3) as u16 | ((reg(4) as u16 & 0x1) << 8);
+        self.halted = nth_bit(reg(4), 6);
+        self.day_carry = nth_bit(reg(4), 7);
+        for i in 0..5 {
+            self.latched[i] = reg(i + 5);
+        }
+        self.cycles = 0;
+        let timestamp_len = if dat.len() >= RTC_TRAILER_SIZE { 8 } else { 4 };
+        let mut timestamp: u64 = 0;
+        for i in 0..timestamp_len {
+            timestamp |= (dat[40 + i] as u64) << (i * 8);
+        }
+        if self.source == ClockSource::Host {
+            self.last_sync = UNIX_EPOCH + Duration::from_secs(timestamp);
+            self.sync();
+        }
+    }
+    ///Reads a latched register, reg is the 0x08 - 0x0c bank select value
+    pub fn read(&self, reg: u8) -> u8 {
+        match reg {
+            0x8..=0xc => self.latched[(reg - 0x8) as usize],
+            _ => 0xff,
+        }
+    }
+    ///Writes go straight to the running clock, reg is the 0x08 - 0x0c bank select value
+    pub fn write(&mut self, reg: u8, dat: u8) {
+        self.sync();
+        match reg {
+            0x8 => {
+                self.seconds = dat & 0x3f;
+                //writing the seconds resets the sub-second divider
+                self.cycles = 0;
+            }
+            0x9 => self.minutes = dat & 0x3f,
+            0xa => self.hours = dat & 0x1f,
+            0xb => self.days = (self.days & 0x100) | dat as u16,
+            0xc => {
+                self.days = (self.days & 0xff) | ((dat as u16 & 0x1) << 8);
+                self.halted = nth_bit(dat, 6);
+                self.day_carry = nth_bit(dat, 7);
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Rtc::new()
+    }
+}
 
 pub struct RomCartridge {
     header: CartridgeHeader,
@@ -179,7 +1258,44 @@ pub trait Cartridge {
     fn get_block_1(&self) -> &Block16Kb;
     fn swap_block_1(&mut self, bank: usize);
     fn read8(&self, addr: u16) -> u8;
-    fn read16(&self, addr: u16) -> u16;
+    ///Reads a little endian word (low byte at addr), like the cpu does
+    fn read16(&self, addr: u16) -> u16 {
+        join_u8(self.read8(addr.wrapping_add(1)), self.read8(addr))
+    }
+    ///Writes to 0x0000 - 0x7fff drive the memory bank controller, writes to 0xa000 - 0xbfff go to external ram
+    fn write8(&mut self, addr: u16, dat: u8);
+    ///Advances anything on the cartridge that runs off the system clock
+    fn tick(&mut self, _cycles: u32) {}
+    ///Real time clock, if the cartridge has one
+    fn get_rtc_mut(&mut self) -> Option<&mut Rtc> {
+        None
+    }
+    ///Whether the rumble motor is currently turned on
+    fn is_rumbling(&self) -> bool {
+        false
+    }
+    ///Registers a function called with the new motor state whenever the rumble motor turns on or off
+    fn set_rumble_callback(&mut self, _callback: Box<dyn FnMut(bool)>) {}
+    ///Whether the external ram (and clock) is kept alive by a battery
+    fn has_battery(&self) -> bool {
+        self.get_header().has_battery()
+    }
+    ///Battery backed contents in the raw .sav layout other emulators use
+    fn get_save_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    ///Restores data produced by get_save_data (or by another emulator)
+    fn load_save_data(&mut self, _dat: &[u8]) {}
+    ///Bytes at the end of get_save_data that only record when it was written, not what's saved
+    fn get_save_timestamp_len(&self) -> usize {
+        0
+    }
+}
+
+///Copies as much of a save file as fits into external ram, short files leave the rest untouched
+fn copy_save_ram(ram: &mut [u8], dat: &[u8]) {
+    let len = ram.len().min(dat.len());
+    ram[..len].copy_from_slice(&dat[..len]);
 }
 
 impl Cartridge for RomCartridge {
@@ -193,29 +1309,19 @@ impl Cartridge for RomCartridge {
         &self.memory[1]
     }
     ///Does nothing, as there is no memory bank controller
-    fn swap_block_1(&mut self, _bank: usize) {
-        ()
-    }
+    fn swap_block_1(&mut self, _bank: usize) {}
     fn read8(&self, addr: u16) -> u8 {
         match addr {
-            0x0...0x3fff => self.get_block_0()[addr as usize],
-            0x4000...0x7fff => self.get_block_1()[(0x4000 - addr) as usize],
-            _ => 0,
-        }
-    }
-    fn read16(&self, addr: u16) -> u16 {
-        match addr {
-            0x0...0x3ffe => join_u8(self.get_block_0()[addr as usize], self.get_block_0()[(addr+1) as usize]),
-            0x3fff => join_u8(self.get_block_0()[0x3fff], self.get_block_1()[0x0]),
-            0x4000...0x7ffe => join_u8(self.get_block_1()[(0x4000 - addr) as usize], self.get_block_1()[(0x4001 - addr) as usize]),
-            //todo: THIS IS PROBABLY THE WRONG WAY TO HANDLE THIS address OVERFLOW
-            0x7fff => join_u8(self.get_block_1()[0x3fff],0),
+            0x0..=0x3fff => self.get_block_0()[addr as usize],
+            0x4000..=0x7fff => self.get_block_1()[(addr - 0x4000) as usize],
             _ => 0,
         }
     }
+    ///No banking registers or external ram to write to
+    fn write8(&mut self, _addr: u16, _dat: u8) {}
 }
 
 
 // pub struct CartridgeUnit{
-//     rom : Box<Cartridge>
+//     rom : Box<dyn Cartridge>
 // }
@@ -0,0 +1,80 @@
+use rom::Cartridge;
+use shared::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+///Emulated time between periodic flushes of battery backed ram
+const FLUSH_INTERVAL: u32 = CLOCK_SPEED * 5;
+
+///Battery backed ram persisted in a .sav file, using the raw layout other emulators produce
+pub struct SaveFile {
+    path: PathBuf,
+    ///contents of the last load or flush, so unchanged saves aren't rewritten
+    last_written: Vec<u8>,
+    ///T-cycles since the last periodic flush
+    cycles: u32,
+}
+
+impl SaveFile {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        SaveFile {
+            path: path.into(),
+            last_written: Vec::new(),
+            cycles: 0,
+        }
+    }
+    ///Save file next to the rom, with the same name and a .sav extension
+    pub fn for_rom<P: AsRef<Path>>(rom_path: P) -> Self {
+        SaveFile::new(rom_path.as_ref().with_extension("sav"))
+    }
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+    ///Loads the save into the cartridge, returns false if the cartridge has no battery or there is no save yet
+    pub fn load(&mut self, cart: &mut dyn Cartridge) -> io::Result<bool> {
+        if !cart.has_battery() {
+            return Ok(false);
+        }
+        let dat = match fs::read(&self.path) {
+            Ok(x) => x,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        cart.load_save_data(&dat);
+        self.last_written = dat;
+        Ok(true)
+    }
+    ///Writes the cartridge's battery backed data if it changed since the last flush
+    pub fn flush(&mut self, cart: &dyn Cartridge) -> io::Result<()> {
+        self.cycles = 0;
+        if !cart.has_battery() {
+            return Ok(());
+        }
+        let dat = cart.get_save_data();
+        //the rtc trailer's timestamp is different every time, so only the ram and clock registers count
+        let timestamp = cart.get_save_timestamp_len();
+        if without_timestamp(&dat, timestamp) == without_timestamp(&self.last_written, timestamp) {
+            return Ok(());
+        }
+        //write next to the real file first so a crash mid-write can't corrupt the existing save
+        let temp = self.path.with_extension("sav.tmp");
+        fs::write(&temp, &dat)?;
+        fs::rename(&temp, &self.path)?;
+        self.last_written = dat;
+        Ok(())
+    }
+    ///Flushes every few seconds of emulated time
+    pub fn tick(&mut self, cycles: u32, cart: &dyn Cartridge) -> io::Result<()> {
+        self.cycles += cycles;
+        if self.cycles >= FLUSH_INTERVAL {
+            self.flush(cart)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn without_timestamp(dat: &[u8], timestamp_len: usize) -> &[u8] {
+    &dat[..dat.len().saturating_sub(timestamp_len)]
+}
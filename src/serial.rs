@@ -0,0 +1,131 @@
+use std::any::Any;
+
+///T-cycles per bit with the internal 8192Hz clock
+const CYCLES_PER_BIT: u32 = 512;
+
+///Whatever is plugged into the link port
+pub trait SerialDevice: Any {
+    ///Called when a byte has finished shifting out, returns the byte shifted in at the same time
+    fn exchange(&mut self, out: u8) -> u8;
+}
+
+///Collects everything sent over the link port, test roms print their results this way
+///Nothing drives the other end, so 0xff (an open line) is shifted in
+#[derive(Default)]
+pub struct SerialCapture {
+    output: Vec<u8>,
+}
+
+impl SerialCapture {
+    pub fn new() -> Self {
+        SerialCapture { output: Vec::new() }
+    }
+    ///Bytes sent so far
+    pub fn get_output(&self) -> &[u8] {
+        &self.output
+    }
+    ///Bytes sent so far as text, anything that isn't UTF-8 is replaced
+    pub fn get_text(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+    pub fn clear(&mut self) {
+        self.output.clear();
+    }
+}
+
+impl SerialDevice for SerialCapture {
+    fn exchange(&mut self, out: u8) -> u8 {
+        self.output.push(out);
+        0xff
+    }
+}
+
+///SB (0xff01) and SC (0xff02)
+pub struct Serial {
+    ///SB, byte being sent/received
+    data: u8,
+    ///SC, bit 7 starts a transfer and stays set until it's done, bit 0 selects the internal clock
+    control: u8,
+    ///bits shifted so far in the current transfer
+    bits: u8,
+    ///T-cycles towards the next bit
+    cycles: u32,
+    device: Box<dyn SerialDevice>,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Serial {
+            data: 0,
+            control: 0,
+            bits: 0,
+            cycles: 0,
+            device: Box::new(SerialCapture::new()),
+        }
+    }
+    pub fn read8(&self, addr: u16) -> u8 {
+        match addr {
+            0xff01 => self.data,
+            //unused bits read as 1
+            0xff02 => 0x7e | self.control,
+            _ => 0xff,
+        }
+    }
+    pub fn write8(&mut self, addr: u16, dat: u8) {
+        match addr {
+            0xff01 => self.data = dat,
+            0xff02 => {
+                self.control = dat & 0x81;
+                self.bits = 0;
+                self.cycles = 0;
+            }
+            _ => (),
+        }
+    }
+    ///Advances a transfer by the given number of T-cycles, returns true if it finished
+    ///With the external clock selected nothing ever clocks the transfer, as if no cable was plugged in
+    pub fn tick(&mut self, cycles: u32) -> bool {
+        if self.control & 0x81 != 0x81 {
+            return false;
+        }
+        self.cycles += cycles;
+        while self.cycles >= CYCLES_PER_BIT {
+            self.cycles -= CYCLES_PER_BIT;
+            self.bits += 1;
+            if self.bits == 8 {
+                self.data = self.device.exchange(self.data);
+                self.control &= !0x80;
+                self.bits = 0;
+                self.cycles = 0;
+                return true;
+            }
+        }
+        false
+    }
+
+    ///Plugs a different device into the link port
+    pub fn set_device(&mut self, device: Box<dyn SerialDevice>) {
+        self.device = device;
+    }
+    pub fn get_device(&self) -> &dyn SerialDevice {
+        &*self.device
+    }
+    pub fn get_device_mut(&mut self) -> &mut dyn SerialDevice {
+        &mut *self.device
+    }
+    ///The default capture device, unless it's been replaced
+    pub fn get_capture(&self) -> Option<&SerialCapture> {
+        let device: &dyn Any = &*self.device;
+        device.downcast_ref()
+    }
+    pub fn get_capture_mut(&mut self) -> Option<&mut SerialCapture> {
+        let device: &mut dyn Any = &mut *self.device;
+        device.downcast_mut()
+    }
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
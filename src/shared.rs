@@ -45,20 +45,17 @@ pub fn swap16(val: u16) -> u16 {
 pub fn add(u0: u8, u1: u8, c: bool) -> (u8, bool, bool) {
     let sum = u0 as u32 + u1 as u32 + c as u32;
     let carry = sum > 0xff;
-    let half = nth_bit(4, low_nibble(u0) + low_nibble(u1) + c as u8);
+    let half = low_nibble(u0) + low_nibble(u1) + c as u8 > 0x0f;
     (sum as u8, carry, half)
-    //todo
 }
 
 ///adds with wrap, return carry and half carry
 pub fn add16(u0: u16, u1: u16, c: bool) -> (u16, bool, bool) {
     let sum = u0 as u32 + u1 as u32 + c as u32;
     let carry = sum > 0xffff;
-    let (hi, _lo) = split_u16(u0);
-    let (_hi, lo) = split_u16(u1);
-    let half = nth_bit16(7, hi as u16 + lo as u16 + c as u16);
+    //16 bit adds carry out of bit 11 into the high byte's upper nibble
+    let half = (u0 & 0x0fff) + (u1 & 0x0fff) + c as u16 > 0x0fff;
     (sum as u16, carry, half)
-    //todo
 }
 ///subs with wrap, return carry and half carry
 pub fn sub(u0: u8, u1: u8, c: bool) -> (u8, bool, bool) {
@@ -66,9 +63,11 @@ pub fn sub(u0: u8, u1: u8, c: bool) -> (u8, bool, bool) {
     let carry = sub < 0;
     let half = (low_nibble(u0) as i16 - low_nibble(u1) as i16 - c as i16) < 0;
     (sub as u8, carry, half)
-    //todo
 }
 
+///T-cycles per second of the DMG master clock
+pub const CLOCK_SPEED: u32 = 4_194_304;
+
 pub type Addr = u16;
 pub type Du8 = u8;
 pub type Ds8 = i8;
@@ -0,0 +1,143 @@
+///DIV/TIMA/TMA/TAC, all driven by one 16 bit counter that ticks every T-cycle
+pub struct Timer {
+    ///internal divider, DIV (0xff04) is the upper 8 bits
+    divider: u16,
+    ///0xff05, counter
+    tima: u8,
+    ///0xff06, value TIMA is reloaded with when it overflows
+    tma: u8,
+    ///0xff07, bit 2 enables TIMA, bits 0-1 select its frequency
+    tac: u8,
+    ///TIMA overflowed during the last M-cycle and reads 0 until it's reloaded
+    overflowed: bool,
+    ///TIMA was reloaded from TMA during the last M-cycle, so writes to it are ignored
+    reloading: bool,
+    ///T-cycles left over from the last tick
+    cycles: u32,
+    ///falling edges of DIV bit 4 not yet passed on to the APU's frame sequencer
+    frame_sequencer_clocks: u32,
+}
+
+///DIV bit 4 (bit 12 of the divider) clocks the APU frame sequencer at 512Hz
+const FRAME_SEQUENCER_BIT: u16 = 1 << 12;
+
+impl Timer {
+    pub fn new() -> Self {
+        Timer {
+            divider: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            overflowed: false,
+            reloading: false,
+            cycles: 0,
+            frame_sequencer_clocks: 0,
+        }
+    }
+    pub fn read8(&self, addr: u16) -> u8 {
+        match addr {
+            0xff04 => (self.divider >> 8) as u8,
+            0xff05 => self.tima,
+            0xff06 => self.tma,
+            //unused bits read as 1
+            0xff07 => 0xf8 | self.tac,
+            _ => 0xff,
+        }
+    }
+    pub fn write8(&mut self, addr: u16, dat: u8) {
+        match addr {
+            //any write resets the whole divider, which can look like a falling edge to TIMA
+            0xff04 => {
+                let old = self.signal();
+                if self.divider & FRAME_SEQUENCER_BIT != 0 {
+                    self.frame_sequencer_clocks += 1;
+                }
+                self.divider = 0;
+                self.check_edge(old);
+            }
+            //TIMA is being loaded from TMA this cycle, so the write loses
+            0xff05 if self.reloading => (),
+            0xff05 => {
+                self.tima = dat;
+                //writing during the delay after an overflow cancels the reload and the interrupt
+                self.overflowed = false;
+            }
+            0xff06 => {
+                self.tma = dat;
+                //the reload happening this cycle picks up the new value
+                if self.reloading {
+                    self.tima = dat;
+                }
+            }
+            //disabling the timer or switching to a lower bit can clock TIMA
+            0xff07 => {
+                let old = self.signal();
+                self.tac = dat & 0b111;
+                self.check_edge(old);
+            }
+            _ => (),
+        }
+    }
+    ///Advances the timer by the given number of T-cycles, returns true if the timer interrupt fired
+    pub fn tick(&mut self, cycles: u32) -> bool {
+        self.cycles += cycles;
+        let mut interrupt = false;
+        while self.cycles >= 4 {
+            self.cycles -= 4;
+            interrupt |= self.step();
+        }
+        interrupt
+    }
+    ///One M-cycle
+    fn step(&mut self) -> bool {
+        self.reloading = false;
+        let mut interrupt = false;
+        //reload and interrupt happen one M-cycle after the overflow
+        if self.overflowed {
+            self.overflowed = false;
+            self.reloading = true;
+            self.tima = self.tma;
+            interrupt = true;
+        }
+        let old = self.signal();
+        let old_divider = self.divider;
+        self.divider = self.divider.wrapping_add(4);
+        if old_divider & !self.divider & FRAME_SEQUENCER_BIT != 0 {
+            self.frame_sequencer_clocks += 1;
+        }
+        self.check_edge(old);
+        interrupt
+    }
+    ///Frame sequencer clocks since the last call
+    pub fn take_frame_sequencer_clocks(&mut self) -> u32 {
+        let clocks = self.frame_sequencer_clocks;
+        self.frame_sequencer_clocks = 0;
+        clocks
+    }
+    ///Divider bit selected by TAC, ANDed with the enable bit
+    fn signal(&self) -> bool {
+        let bit = match self.tac & 0b11 {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            _ => 7,
+        };
+        self.tac & 0b100 != 0 && (self.divider >> bit) & 1 == 1
+    }
+    ///TIMA increments on the falling edge of the signal
+    fn check_edge(&mut self, old: bool) {
+        if old && !self.signal() {
+            let (tima, overflow) = self.tima.overflowing_add(1);
+            self.tima = tima;
+            if overflow {
+                self.overflowed = true;
+            }
+        }
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
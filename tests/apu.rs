@@ -0,0 +1,133 @@
+//! Length counters, channel 1's sweep overflow, NR52 power and the sample buffer
+extern crate bouzu;
+
+use bouzu::apu::Apu;
+use bouzu::shared::CLOCK_SPEED;
+
+const NR10: u16 = 0xff10;
+const NR11: u16 = 0xff11;
+const NR12: u16 = 0xff12;
+const NR13: u16 = 0xff13;
+const NR14: u16 = 0xff14;
+const NR50: u16 = 0xff24;
+const NR51: u16 = 0xff25;
+const NR52: u16 = 0xff26;
+
+///Powered on apu producing a stereo frame every 64 T-cycles
+fn powered_apu() -> Apu {
+    let mut apu = Apu::new(CLOCK_SPEED / 64);
+    apu.write8(NR52, 0x80);
+    apu
+}
+
+///Triggers channel 1 at full volume with the given sweep and frequency
+fn trigger_ch1(apu: &mut Apu, nr10: u8, frequency: u16, nr14: u8) {
+    apu.write8(NR10, nr10);
+    apu.write8(NR12, 0xf0);
+    apu.write8(NR13, frequency as u8);
+    apu.write8(NR14, 0x80 | nr14 | (frequency >> 8) as u8);
+}
+
+fn ch1_on(apu: &Apu) -> bool {
+    apu.read8(NR52) & 0x01 != 0
+}
+
+#[test]
+fn length_running_out_clears_the_nr52_bit() {
+    let mut apu = powered_apu();
+    //a length of 63 leaves one clock on the counter
+    apu.write8(NR11, 63);
+    trigger_ch1(&mut apu, 0, 0x400, 0x40);
+    assert!(ch1_on(&apu));
+    //the frame sequencer's first step clocks length
+    apu.tick(0, 1);
+    assert!(!ch1_on(&apu));
+    assert_eq!(apu.read8(NR52), 0xf0);
+
+    //without length enabled the channel keeps playing
+    let mut apu = powered_apu();
+    apu.write8(NR11, 63);
+    trigger_ch1(&mut apu, 0, 0x400, 0);
+    apu.tick(0, 8);
+    assert!(ch1_on(&apu));
+}
+
+#[test]
+fn sweep_overflow_on_trigger_disables_channel_1() {
+    //2047 + (2047 >> 1) is past 2047
+    let mut apu = powered_apu();
+    trigger_ch1(&mut apu, 0x01, 0x7ff, 0);
+    assert!(!ch1_on(&apu));
+
+    //with a shift of 0 the overflow check is skipped
+    let mut apu = powered_apu();
+    trigger_ch1(&mut apu, 0x00, 0x7ff, 0);
+    assert!(ch1_on(&apu));
+
+    //1024 + 512 fits
+    let mut apu = powered_apu();
+    trigger_ch1(&mut apu, 0x01, 0x400, 0);
+    assert!(ch1_on(&apu));
+
+    //subtracting never overflows
+    let mut apu = powered_apu();
+    trigger_ch1(&mut apu, 0x09, 0x7ff, 0);
+    assert!(ch1_on(&apu));
+}
+
+#[test]
+fn powering_off_clears_the_registers_and_drops_writes() {
+    let fresh = Apu::new(CLOCK_SPEED / 64);
+    let mut apu = powered_apu();
+    for addr in NR10..=NR51 {
+        apu.write8(addr, 0xff);
+    }
+    apu.write8(NR52, 0x00);
+    for addr in NR10..=NR51 {
+        assert_eq!(apu.read8(addr), fresh.read8(addr), "{:04x}", addr);
+    }
+    assert_eq!(apu.read8(NR52), 0x70);
+
+    for addr in NR10..=NR51 {
+        apu.write8(addr, 0xff);
+    }
+    assert_eq!(apu.read8(NR50), 0x00);
+    assert_eq!(apu.read8(NR51), 0x00);
+    assert_eq!(apu.read8(NR10), 0x80);
+    assert_eq!(apu.read8(NR12), 0x00);
+    //the writes are gone, not just hidden until power comes back
+    apu.write8(NR52, 0x80);
+    assert_eq!(apu.read8(NR50), 0x00);
+    assert_eq!(apu.read8(NR51), 0x00);
+    assert_eq!(apu.read8(NR52), 0xf0);
+}
+
+#[test]
+fn pull_samples_returns_whole_stereo_frames() {
+    let mut apu = powered_apu();
+    apu.tick(64 * 3, 0);
+    assert_eq!(apu.samples_available(), 3);
+
+    let mut buf = [0.0; 5];
+    assert_eq!(apu.pull_samples(&mut buf), 4);
+    assert_eq!(apu.samples_available(), 1);
+    assert_eq!(apu.pull_samples(&mut buf[..1]), 0);
+    assert_eq!(apu.samples_available(), 1);
+    assert_eq!(apu.pull_samples(&mut buf), 2);
+    assert_eq!(apu.pull_samples(&mut buf), 0);
+}
+
+#[test]
+fn buffer_keeps_at_most_one_second() {
+    let mut apu = Apu::new(1000);
+    apu.write8(NR52, 0x80);
+    apu.tick(CLOCK_SPEED * 2, 0);
+    assert_eq!(apu.samples_available(), 1000);
+    let mut buf = vec![0.0; 4000];
+    assert_eq!(apu.pull_samples(&mut buf), 2000);
+
+    //the limit follows the sample rate
+    apu.set_sample_rate(250);
+    apu.tick(CLOCK_SPEED * 2, 0);
+    assert_eq!(apu.samples_available(), 250);
+}
@@ -0,0 +1,82 @@
+//! .wav headers and raw PCM output
+extern crate bouzu;
+
+use bouzu::audio::{AudioSink, RawPcmWriter, WavWriter};
+use std::io::Cursor;
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[test]
+fn wav_header() {
+    let mut wav = WavWriter::new(Cursor::new(vec![]), 48_000, 2).unwrap();
+    wav.write_samples(&[0.0; 6]).unwrap();
+    wav.write_samples(&[0.5; 4]).unwrap();
+    let bytes = wav.into_inner().unwrap().into_inner();
+
+    assert_eq!(bytes.len(), 44 + 20);
+    assert_eq!(&bytes[0..4], b"RIFF");
+    assert_eq!(u32_at(&bytes, 4), 36 + 20);
+    assert_eq!(&bytes[8..16], b"WAVEfmt ");
+    assert_eq!(u32_at(&bytes, 16), 16);
+    //integer PCM
+    assert_eq!(u16_at(&bytes, 20), 1);
+    assert_eq!(u16_at(&bytes, 22), 2);
+    assert_eq!(u32_at(&bytes, 24), 48_000);
+    //byte rate and block align
+    assert_eq!(u32_at(&bytes, 28), 48_000 * 4);
+    assert_eq!(u16_at(&bytes, 32), 4);
+    assert_eq!(u16_at(&bytes, 34), 16);
+    assert_eq!(&bytes[36..40], b"data");
+    assert_eq!(u32_at(&bytes, 40), 20);
+}
+
+#[test]
+fn mono_wav_header() {
+    let mut wav = WavWriter::new(Cursor::new(vec![]), 22_050, 1).unwrap();
+    wav.write_samples(&[1.0; 3]).unwrap();
+    let bytes = wav.into_inner().unwrap().into_inner();
+
+    assert_eq!(u32_at(&bytes, 4), 36 + 6);
+    assert_eq!(u16_at(&bytes, 22), 1);
+    assert_eq!(u32_at(&bytes, 24), 22_050);
+    assert_eq!(u32_at(&bytes, 28), 22_050 * 2);
+    assert_eq!(u16_at(&bytes, 32), 2);
+    assert_eq!(u32_at(&bytes, 40), 6);
+}
+
+#[test]
+fn empty_wav_is_just_the_header() {
+    let wav = WavWriter::new(Cursor::new(vec![]), 44_100, 2).unwrap();
+    let bytes = wav.into_inner().unwrap().into_inner();
+    assert_eq!(bytes.len(), 44);
+    assert_eq!(u32_at(&bytes, 4), 36);
+    assert_eq!(u32_at(&bytes, 40), 0);
+}
+
+#[test]
+fn raw_pcm_is_interleaved_little_endian() {
+    let mut bytes = vec![];
+    {
+        let mut raw = RawPcmWriter::new(&mut bytes);
+        raw.write_samples(&[0.0, 1.0, -1.0]).unwrap();
+        //out of range samples clip
+        raw.write_samples(&[2.0, -2.0, 0.5]).unwrap();
+        raw.finish().unwrap();
+    }
+    let samples: Vec<i16> = bytes
+        .chunks(2)
+        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    assert_eq!(samples, [0, 32767, -32767, 32767, -32767, 16383]);
+}
@@ -0,0 +1,104 @@
+//! Runs Blargg's cpu_instrs roms and checks the verdict they print over the serial port
+extern crate bouzu;
+
+use bouzu::gameboy::GameBoy;
+use bouzu::rom;
+use bouzu::shared::CLOCK_SPEED;
+
+const ROM_DIR: &str = "roms/instruction_tests";
+///Emulated time a rom gets before it counts as hung, the slowest one needs about 18 seconds
+const CYCLE_BUDGET: u64 = CLOCK_SPEED as u64 * 60;
+
+enum Verdict {
+    Passed,
+    Failed(String),
+    TimedOut(String),
+}
+
+fn run(name: &str) -> Verdict {
+    let path = format!("{}/{}.gb", ROM_DIR, name);
+    let rom = rom::load_rom(&path).unwrap_or_else(|e| panic!("Couldn't load {}: {}", path, e));
+    let mut gameboy = GameBoy::new(rom);
+    while gameboy.get_cycles() < CYCLE_BUDGET {
+        gameboy.run_frame();
+        let output = gameboy.get_serial_text().unwrap_or_default();
+        if output.contains("Passed") {
+            return Verdict::Passed;
+        }
+        if output.contains("Failed") {
+            return Verdict::Failed(output);
+        }
+    }
+    Verdict::TimedOut(gameboy.get_serial_text().unwrap_or_default())
+}
+
+///Lines between the rom's name and the verdict, which are the opcodes that gave the wrong result
+fn failing_lines(output: &str) -> Vec<&str> {
+    output
+        .lines()
+        .skip(1)
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty() && !x.starts_with("Failed"))
+        .collect()
+}
+
+fn check(name: &str) {
+    match run(name) {
+        Verdict::Passed => (),
+        Verdict::Failed(output) => panic!(
+            "{} failed:\n{}\n\nfull output:\n{}",
+            name,
+            failing_lines(&output).join("\n"),
+            output
+        ),
+        Verdict::TimedOut(output) => panic!(
+            "{} didn't finish within {} cycles, output so far:\n{}",
+            name, CYCLE_BUDGET, output
+        ),
+    }
+}
+
+#[test]
+fn special() {
+    check("01-special");
+}
+#[test]
+fn interrupts() {
+    check("02-interrupts");
+}
+#[test]
+fn op_sp_hl() {
+    check("03-op sp,hl");
+}
+#[test]
+fn op_r_imm() {
+    check("04-op r,imm");
+}
+#[test]
+fn op_rp() {
+    check("05-op rp");
+}
+#[test]
+fn ld_r_r() {
+    check("06-ld r,r");
+}
+#[test]
+fn jr_jp_call_ret_rst() {
+    check("07-jr,jp,call,ret,rst");
+}
+#[test]
+fn misc_instrs() {
+    check("08-misc instrs");
+}
+#[test]
+fn op_r_r() {
+    check("09-op r,r");
+}
+#[test]
+fn bit_ops() {
+    check("10-bit ops");
+}
+#[test]
+fn op_a_hl() {
+    check("11-op a,(hl)");
+}
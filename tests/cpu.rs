@@ -0,0 +1,160 @@
+//! Interrupt dispatch, HALT and other cpu behaviour that spans more than one instruction
+extern crate bouzu;
+
+use bouzu::bus::{Bus, FlatRam};
+use bouzu::cpu::Cpu;
+
+#[test]
+fn halt_bug_reads_the_opcode_byte_twice() {
+    let mut ram = FlatRam::new();
+    //halt, ld a, $14, inc d
+    ram.load(0xc000, &[0x76, 0x3e, 0x14]);
+    //vblank requested and enabled, but IME is off
+    ram.write8(0xffff, 0x01);
+    ram.write8(0xff0f, 0x01);
+    let mut cpu = Cpu::new();
+    cpu.set_interrupts_enabled(false);
+    cpu.get_register_mut().pc = 0xc000;
+
+    cpu.step(&mut ram);
+    assert!(!cpu.is_halted());
+    assert_eq!(cpu.get_register().pc, 0xc001);
+    //the ld opcode is read again as its own operand
+    cpu.step(&mut ram);
+    assert_eq!(cpu.get_register().a, 0x3e);
+    assert_eq!(cpu.get_register().pc, 0xc002);
+    //so 0x14 ends up executed as inc d
+    let d = cpu.get_register().d;
+    cpu.step(&mut ram);
+    assert_eq!(cpu.get_register().d, d.wrapping_add(1));
+    assert_eq!(cpu.get_register().pc, 0xc003);
+}
+
+///Cpu at 0xc000 with the stack at 0xd000, over ram holding the given code at 0xc000
+fn setup(code: &[u8]) -> (Cpu, FlatRam) {
+    let mut ram = FlatRam::new();
+    ram.load(0xc000, code);
+    let mut cpu = Cpu::new();
+    cpu.get_register_mut().pc = 0xc000;
+    cpu.get_register_mut().sp = 0xd000;
+    (cpu, ram)
+}
+
+///Return address on top of the stack
+fn pushed_pc(cpu: &Cpu, ram: &FlatRam) -> u16 {
+    ram.read16(cpu.get_register().sp)
+}
+
+#[test]
+fn interrupts_are_serviced_in_priority_order() {
+    let (mut cpu, mut ram) = setup(&[]);
+    ram.write8(0xffff, 0x1f);
+    //timer, serial and joypad
+    ram.write8(0xff0f, 0x1c);
+    for &(vector, flags_left) in &[(0x50, 0x18), (0x58, 0x10), (0x60, 0x00)] {
+        cpu.set_interrupts_enabled(true);
+        cpu.step(&mut ram);
+        assert_eq!(cpu.get_register().pc, vector);
+        assert_eq!(ram.read8(0xff0f), flags_left);
+    }
+    //with IE masking vblank off, stat goes first
+    ram.write8(0xffff, 0x1e);
+    ram.write8(0xff0f, 0x03);
+    cpu.set_interrupts_enabled(true);
+    cpu.step(&mut ram);
+    assert_eq!(cpu.get_register().pc, 0x48);
+}
+
+#[test]
+fn dispatch_takes_20_cycles_and_24_out_of_halt() {
+    let (mut cpu, mut ram) = setup(&[0x00]);
+    ram.write8(0xffff, 0x01);
+    ram.write8(0xff0f, 0x01);
+    cpu.set_interrupts_enabled(true);
+    assert_eq!(cpu.step(&mut ram), 20);
+    assert_eq!(cpu.get_register().pc, 0x40);
+    assert_eq!(pushed_pc(&cpu, &ram), 0xc000);
+    assert!(!cpu.interrupts_enabled());
+
+    //woken up from halt, the extra M-cycle is on top of the dispatch
+    let (mut cpu, mut ram) = setup(&[0x76]);
+    ram.write8(0xffff, 0x01);
+    cpu.set_interrupts_enabled(true);
+    cpu.step(&mut ram);
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.step(&mut ram), 4);
+    ram.write8(0xff0f, 0x01);
+    assert_eq!(cpu.step(&mut ram), 24);
+    assert!(!cpu.is_halted());
+    assert_eq!(pushed_pc(&cpu, &ram), 0xc001);
+}
+
+#[test]
+fn ei_takes_effect_after_the_next_instruction() {
+    //ei, nop, nop
+    let (mut cpu, mut ram) = setup(&[0xfb, 0x00, 0x00]);
+    ram.write8(0xffff, 0x01);
+    ram.write8(0xff0f, 0x01);
+    cpu.step(&mut ram);
+    assert!(!cpu.interrupts_enabled());
+    cpu.step(&mut ram);
+    assert!(cpu.interrupts_enabled());
+    assert_eq!(cpu.get_register().pc, 0xc002);
+    cpu.step(&mut ram);
+    assert_eq!(cpu.get_register().pc, 0x40);
+    assert_eq!(pushed_pc(&cpu, &ram), 0xc002);
+}
+
+#[test]
+fn reti_enables_interrupts_straight_away() {
+    //reti
+    let (mut cpu, mut ram) = setup(&[0xd9]);
+    cpu.get_register_mut().sp = 0xcffe;
+    ram.write16(0xcffe, 0x1234);
+    ram.write8(0xffff, 0x04);
+    ram.write8(0xff0f, 0x04);
+    assert_eq!(cpu.step(&mut ram), 16);
+    assert_eq!(cpu.get_register().pc, 0x1234);
+    assert!(cpu.interrupts_enabled());
+    cpu.step(&mut ram);
+    assert_eq!(cpu.get_register().pc, 0x50);
+    assert_eq!(pushed_pc(&cpu, &ram), 0x1234);
+}
+
+#[test]
+fn halt_with_ime_off_wakes_without_dispatching() {
+    //halt, inc a
+    let (mut cpu, mut ram) = setup(&[0x76, 0x3c]);
+    ram.write8(0xffff, 0x01);
+    cpu.step(&mut ram);
+    assert!(cpu.is_halted());
+    ram.write8(0xff0f, 0x01);
+    assert_eq!(cpu.step(&mut ram), 4);
+    assert!(!cpu.is_halted());
+    let a = cpu.get_register().a;
+    cpu.step(&mut ram);
+    assert_eq!(cpu.get_register().a, a.wrapping_add(1));
+    assert_eq!(cpu.get_register().pc, 0xc002);
+    //the flag is left for the program to see
+    assert_eq!(ram.read8(0xff0f), 0x01);
+}
+
+#[test]
+fn pushing_onto_ie_can_cancel_the_dispatch() {
+    //with sp at 0, the high byte of pc lands in IE
+    for &(pc, vector) in &[(0x0100u16, 0x40u16), (0x0200, 0x00)] {
+        let mut ram = FlatRam::new();
+        let mut cpu = Cpu::new();
+        cpu.get_register_mut().pc = pc;
+        cpu.get_register_mut().sp = 0x0000;
+        ram.write8(0xffff, 0x01);
+        ram.write8(0xff0f, 0x01);
+        cpu.set_interrupts_enabled(true);
+        cpu.step(&mut ram);
+        assert_eq!(ram.read8(0xffff), (pc >> 8) as u8);
+        assert_eq!(cpu.get_register().pc, vector, "pc {:04x}", pc);
+        //a cancelled dispatch doesn't acknowledge anything
+        let flags = if vector == 0 { 0x01 } else { 0x00 };
+        assert_eq!(ram.read8(0xff0f), flags);
+    }
+}
@@ -0,0 +1,73 @@
+//! Checks the RGBDS syntax the disassembler emits
+extern crate bouzu;
+
+use bouzu::bus::FlatRam;
+use bouzu::disasm::{self, Disassembler};
+use bouzu::instructions;
+
+///Display of the single instruction in bytes, placed at 0xc000
+fn show(bytes: &[u8]) -> String {
+    let mut ram = FlatRam::new();
+    ram.load(0xc000, bytes);
+    instructions::decode(&ram, 0xc000).to_string()
+}
+
+#[test]
+fn rgbds_mnemonics() {
+    assert_eq!(show(&[0x00]), "nop");
+    assert_eq!(show(&[0x7e]), "ld a, [hl]");
+    assert_eq!(show(&[0x22]), "ld [hl+], a");
+    assert_eq!(show(&[0x3a]), "ld a, [hl-]");
+    assert_eq!(show(&[0x01, 0x34, 0x12]), "ld bc, $1234");
+    assert_eq!(show(&[0x08, 0x00, 0xc0]), "ld [$c000], sp");
+    assert_eq!(show(&[0xe0, 0x80]), "ldh [$ff80], a");
+    assert_eq!(show(&[0xf2]), "ldh a, [c]");
+    assert_eq!(show(&[0xf8, 0xfd]), "ld hl, sp-3");
+    assert_eq!(show(&[0xe8, 0x05]), "add sp, 5");
+    assert_eq!(show(&[0x18, 0xfe]), "jr @+0");
+    assert_eq!(show(&[0x20, 0x05]), "jr nz, @+7");
+    assert_eq!(show(&[0xda, 0x50, 0x01]), "jp c, $0150");
+    assert_eq!(show(&[0xd0]), "ret nc");
+    assert_eq!(show(&[0xff]), "rst $38");
+    assert_eq!(show(&[0xbe]), "cp a, [hl]");
+    assert_eq!(show(&[0xcb, 0x7e]), "bit 7, [hl]");
+    assert_eq!(show(&[0xcb, 0x37]), "swap a");
+}
+
+#[test]
+fn listing_resolves_targets_and_symbols() {
+    let mut ram = FlatRam::new();
+    //Loop: dec b, jr nz Loop, call $4000, ld [$c000], a
+    ram.load(
+        0x0150,
+        &[0x05, 0x20, 0xfd, 0xcd, 0x00, 0x40, 0xea, 0x00, 0xc0],
+    );
+    let mut disassembler = Disassembler::new();
+    let added = disassembler.load_symbols(
+        "; comment\n00:0150 Loop\n01:4000 Far\n02:4000 Other\n00:c000 wCounter\n",
+        1,
+    );
+    assert_eq!(added, 3);
+
+    let lines = disasm::disassemble(&ram, 0x0150, 9);
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[1].target(), Some(0x0150));
+    assert_eq!(
+        disassembler.listing(&lines),
+        "Loop:\n\
+         0150  05        dec b\n\
+         0151  20 fd     jr nz, Loop\n\
+         0153  cd 00 40  call Far\n\
+         0156  ea 00 c0  ld [wCounter], a\n"
+    );
+}
+
+#[test]
+fn rom_banks_map_where_the_cpu_sees_them() {
+    let mut rom = vec![0u8; disasm::ROM_BANK_SIZE * 4];
+    rom[disasm::ROM_BANK_SIZE * 2] = 0x76;
+    let bus = disasm::map_rom_bank(&rom, 2).unwrap();
+    let lines = disasm::disassemble(&bus, 0x4000, 1);
+    assert_eq!(lines[0].instruction.to_string(), "halt");
+    assert!(disasm::map_rom_bank(&rom, 4).is_none());
+}
@@ -0,0 +1,102 @@
+//! Checks that encode is the exact inverse of decode for every opcode
+extern crate bouzu;
+
+use bouzu::bus::FlatRam;
+use bouzu::instructions::{self, Instruction};
+use bouzu::register::{BitFlag, Reg16Name, Reg8Name};
+
+const ILLEGAL: [u8; 11] = [
+    0xd3, 0xdb, 0xdd, 0xe3, 0xe4, 0xeb, 0xec, 0xed, 0xf4, 0xfc, 0xfd,
+];
+///Operand bytes to follow each opcode with, so swapped or dropped operand bytes show up
+const OPERANDS: [[u8; 2]; 3] = [[0x34, 0x12], [0x80, 0xff], [0x00, 0x00]];
+
+fn decode(bytes: &[u8]) -> Instruction {
+    let mut ram = FlatRam::new();
+    ram.load(0xc000, bytes);
+    instructions::decode(&ram, 0xc000)
+}
+
+///Decodes the opcode with each set of operands and checks encoding gives back the same bytes
+fn check_round_trip(opcode: &[u8], failures: &mut Vec<String>) {
+    for operands in OPERANDS.iter() {
+        let mut bytes = opcode.to_vec();
+        bytes.extend_from_slice(operands);
+        let instruction = decode(&bytes);
+        let size = instruction.clone().get_size() as usize;
+        let encoded = instruction.encode();
+        if encoded.as_ref().map(|x| &x[..]) != Some(&bytes[..size]) {
+            failures.push(format!(
+                "{:02x?} decoded to {:?}, which encodes to {:02x?}",
+                &bytes[..size],
+                instruction,
+                encoded
+            ));
+        }
+    }
+}
+
+#[test]
+fn base_opcodes_round_trip() {
+    let mut failures = Vec::new();
+    for op in 0..=0xffu8 {
+        //0xcb is only a prefix, the cb table is checked separately
+        if op != 0xcb {
+            check_round_trip(&[op], &mut failures);
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn cb_opcodes_round_trip() {
+    let mut failures = Vec::new();
+    for op in 0..=0xffu8 {
+        check_round_trip(&[0xcb, op], &mut failures);
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn illegal_opcodes_are_not_nops() {
+    for op in 0..=0xffu8 {
+        match decode(&[op]) {
+            Instruction::Illegal(x) => {
+                assert_eq!(x, op);
+                assert!(ILLEGAL.contains(&op), "{:02x} decoded as illegal", op);
+            }
+            Instruction::Nop => assert_eq!(op, 0x00, "{:02x} decoded as nop", op),
+            _ => assert!(!ILLEGAL.contains(&op), "{:02x} isn't illegal", op),
+        }
+    }
+}
+
+#[test]
+fn unencodable_operands_have_no_bytes() {
+    assert!(Instruction::AddR8R8(Reg8Name::B, Reg8Name::C)
+        .encode()
+        .is_none());
+    assert!(Instruction::LdR8R8(Reg8Name::F, Reg8Name::A)
+        .encode()
+        .is_none());
+    assert!(Instruction::PushR16(Reg16Name::SP).encode().is_none());
+    assert!(Instruction::JrFA8(BitFlag::N, 0).encode().is_none());
+    assert!(Instruction::Rst(0x39).encode().is_none());
+    //only opcodes missing from the table can be illegal
+    for op in 0..=0xffu8 {
+        let encoded = Instruction::Illegal(op).encode();
+        if ILLEGAL.contains(&op) {
+            assert_eq!(encoded, Some(vec![op]));
+        } else {
+            assert_eq!(encoded, None, "illegal {:02x} encoded", op);
+        }
+    }
+}
+
+#[test]
+fn stop_keeps_its_padding_byte() {
+    let stop = decode(&[0x10, 0x42]);
+    assert_eq!(stop.to_string(), "stop $42");
+    assert_eq!(stop.encode(), Some(vec![0x10, 0x42]));
+    assert_eq!(decode(&[0x10, 0x00]).to_string(), "stop");
+}
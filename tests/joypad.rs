@@ -0,0 +1,144 @@
+//! P1 line selection, active-low reads, the joypad interrupt and waking from STOP
+extern crate bouzu;
+
+use bouzu::bus::Bus;
+use bouzu::cpu::Cpu;
+use bouzu::joypad::{Button, ButtonSet, Joypad};
+use bouzu::mmu::Mmu;
+use bouzu::rom;
+
+const SELECT_DIRECTIONS: u8 = 0x20;
+const SELECT_BUTTONS: u8 = 0x10;
+const SELECT_NONE: u8 = 0x30;
+const SELECT_BOTH: u8 = 0x00;
+
+#[test]
+fn select_lines_pick_the_nibble() {
+    let mut joypad = Joypad::new();
+    joypad.set_state(
+        ButtonSet::new()
+            .with(Button::Right)
+            .with(Button::Down)
+            .with(Button::B),
+    );
+
+    joypad.write8(SELECT_DIRECTIONS);
+    assert_eq!(joypad.read8(), 0xc0 | SELECT_DIRECTIONS | 0b0110);
+    joypad.write8(SELECT_BUTTONS);
+    assert_eq!(joypad.read8(), 0xc0 | SELECT_BUTTONS | 0b1101);
+    //both groups selected pull the same lines low
+    joypad.write8(SELECT_BOTH);
+    assert_eq!(joypad.read8(), 0xc0 | 0b0100);
+    joypad.write8(SELECT_NONE);
+    assert_eq!(joypad.read8(), 0xff);
+}
+
+#[test]
+fn reads_are_active_low_with_the_top_bits_set() {
+    let mut joypad = Joypad::new();
+    assert_eq!(joypad.read8(), 0xff);
+    //only the select bits are writable
+    joypad.write8(0x0f);
+    assert_eq!(joypad.read8(), 0xcf);
+    for (i, &button) in Button::ALL.iter().enumerate() {
+        joypad.set_state(ButtonSet::new().with(button));
+        let select = if i < 4 {
+            SELECT_DIRECTIONS
+        } else {
+            SELECT_BUTTONS
+        };
+        joypad.write8(select);
+        assert_eq!(
+            joypad.read8(),
+            0xc0 | select | (0x0f & !(1 << (i % 4))),
+            "{:?}",
+            button
+        );
+    }
+}
+
+#[test]
+fn interrupt_only_on_a_selected_line_going_low() {
+    let mut joypad = Joypad::new();
+    joypad.write8(SELECT_DIRECTIONS);
+
+    //buttons aren't selected
+    joypad.press(Button::A);
+    assert!(!joypad.take_interrupt());
+    joypad.press(Button::Left);
+    assert!(joypad.take_interrupt());
+    assert!(!joypad.take_interrupt());
+
+    //a second button on the same group is another line going low
+    joypad.press(Button::Up);
+    assert!(joypad.take_interrupt());
+    //releasing is a rising edge
+    joypad.release(Button::Left);
+    assert!(!joypad.take_interrupt());
+    //selecting both groups lets A pull P10 low
+    joypad.write8(SELECT_BOTH);
+    assert!(joypad.take_interrupt());
+    //up already holds P12 low, so select on the same line isn't an edge
+    joypad.press(Button::Select);
+    assert!(!joypad.take_interrupt());
+
+    joypad.write8(SELECT_NONE);
+    assert!(!joypad.take_interrupt());
+    //selecting a group with a button held is a falling edge too
+    joypad.write8(SELECT_BUTTONS);
+    assert!(joypad.take_interrupt());
+}
+
+///Mmu with an empty 32KiB rom
+fn empty_mmu() -> Mmu {
+    let mut dat = vec![0u8; 0x8000];
+    dat[0x14d] = rom::header_checksum(&dat);
+    Mmu::new(rom::load_rom_from_bytes(dat).unwrap())
+}
+
+///Cpu stopped by a STOP; NOP in work ram, with the direction lines selected
+fn stopped_cpu() -> (Cpu, Mmu) {
+    let mut mmu = empty_mmu();
+    mmu.write8(0xc000, 0x10);
+    mmu.write8(0xc001, 0x00);
+    mmu.write8(0xc002, 0x00);
+    mmu.write8(0xff00, SELECT_DIRECTIONS);
+    let mut cpu = Cpu::new();
+    cpu.get_register_mut().pc = 0xc000;
+    cpu.step(&mut mmu);
+    assert!(cpu.is_stopped());
+    (cpu, mmu)
+}
+
+#[test]
+fn a_selected_button_wakes_the_cpu_from_stop() {
+    let (mut cpu, mut mmu) = stopped_cpu();
+    for _ in 0..100 {
+        assert_eq!(cpu.step(&mut mmu), 4);
+    }
+    assert!(cpu.is_stopped());
+    assert_eq!(cpu.get_register().pc, 0xc002);
+
+    //a button on the other group isn't seen
+    mmu.get_joypad_mut().press(Button::Start);
+    cpu.step(&mut mmu);
+    assert!(cpu.is_stopped());
+
+    mmu.get_joypad_mut().press(Button::Down);
+    cpu.step(&mut mmu);
+    assert!(!cpu.is_stopped());
+    assert_eq!(cpu.get_register().pc, 0xc003);
+}
+
+#[test]
+fn stop_with_a_button_held_does_not_stop() {
+    let mut mmu = empty_mmu();
+    mmu.write8(0xc000, 0x10);
+    mmu.write8(0xff00, SELECT_BUTTONS);
+    mmu.get_joypad_mut().press(Button::A);
+    let mut cpu = Cpu::new();
+    cpu.get_register_mut().pc = 0xc000;
+    cpu.step(&mut mmu);
+    assert!(!cpu.is_stopped());
+    assert_eq!(cpu.get_register().pc, 0xc002);
+}
@@ -0,0 +1,150 @@
+//! Checks the mooneye pass/fail detection, and runs the mooneye test roms in roms/mooneye if they're there
+extern crate bouzu;
+
+use bouzu::gameboy::GameBoy;
+use bouzu::mooneye::{self, Outcome};
+use bouzu::rom;
+use std::fs;
+
+const ROM_DIR: &str = "roms/mooneye";
+///One rom per line, relative to ROM_DIR, blank lines and lines starting with # are ignored
+const PASSING_LIST: &str = "roms/mooneye/passing.txt";
+///The timer roms the timer is checked against, relative to ROM_DIR
+const TIMER_ROMS: [&str; 13] = [
+    "acceptance/timer/div_write.gb",
+    "acceptance/timer/rapid_toggle.gb",
+    "acceptance/timer/tim00.gb",
+    "acceptance/timer/tim00_div_trigger.gb",
+    "acceptance/timer/tim01.gb",
+    "acceptance/timer/tim01_div_trigger.gb",
+    "acceptance/timer/tim10.gb",
+    "acceptance/timer/tim10_div_trigger.gb",
+    "acceptance/timer/tim11.gb",
+    "acceptance/timer/tim11_div_trigger.gb",
+    "acceptance/timer/tima_reload.gb",
+    "acceptance/timer/tima_write_reloading.gb",
+    "acceptance/timer/tma_write_reloading.gb",
+];
+
+///32KiB rom with no mbc that loads B, C, D, E, H and L with the given values, then hits LD B,B
+fn breakpoint_rom(values: [u8; 6]) -> GameBoy {
+    let mut dat = vec![0u8; 0x8000];
+    //nop, jp 0x150
+    dat[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+    dat[0x14d] = rom::header_checksum(&dat);
+    let mut code = Vec::new();
+    //ld b/c/d/e/h/l, n
+    for (&op, &value) in [0x06u8, 0x0e, 0x16, 0x1e, 0x26, 0x2e]
+        .iter()
+        .zip(values.iter())
+    {
+        code.push(op);
+        code.push(value);
+    }
+    //ld b,b then jr to itself
+    code.extend_from_slice(&[0x40, 0x18, 0xfe]);
+    dat[0x150..0x150 + code.len()].copy_from_slice(&code);
+    GameBoy::new(rom::load_rom_from_bytes(dat).unwrap())
+}
+
+#[test]
+fn fibonacci_registers_pass() {
+    let mut gameboy = breakpoint_rom([3, 5, 8, 13, 21, 34]);
+    assert_eq!(mooneye::run(&mut gameboy, 100_000), Outcome::Passed);
+}
+
+#[test]
+fn fail_registers_fail() {
+    let mut gameboy = breakpoint_rom([0x42; 6]);
+    assert_eq!(mooneye::run(&mut gameboy, 100_000), Outcome::Failed);
+}
+
+#[test]
+fn other_registers_are_unknown() {
+    let mut gameboy = breakpoint_rom([1, 2, 3, 4, 5, 6]);
+    assert_eq!(
+        mooneye::run(&mut gameboy, 100_000),
+        Outcome::Unknown([1, 2, 3, 4, 5, 6])
+    );
+}
+
+#[test]
+fn no_breakpoint_times_out() {
+    let mut gameboy = breakpoint_rom([3, 5, 8, 13, 21, 34]);
+    //not enough time to get through the loads
+    assert_eq!(mooneye::run(&mut gameboy, 40), Outcome::TimedOut);
+}
+
+///Roms in the suite that are known to pass, relative to ROM_DIR
+fn expected_passes() -> Vec<String> {
+    let list = fs::read_to_string(PASSING_LIST)
+        .unwrap_or_else(|e| panic!("Couldn't read {}: {}", PASSING_LIST, e));
+    list.lines()
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+        .map(|x| x.to_string())
+        .collect()
+}
+
+///The suite isn't shipped with the repo, drop the roms into roms/mooneye to run them
+///Not every rom passes yet, so only the ones in roms/mooneye/passing.txt have to
+#[test]
+fn mooneye_suite() {
+    let roms = mooneye::find_roms(ROM_DIR).unwrap_or_default();
+    if roms.is_empty() {
+        println!("no roms in {}, skipping", ROM_DIR);
+        return;
+    }
+    let expected = expected_passes();
+    let mut passing = Vec::new();
+    for path in &roms {
+        let name = path.strip_prefix(ROM_DIR).unwrap().display().to_string();
+        match mooneye::run_rom(&path.display().to_string(), mooneye::DEFAULT_CYCLE_BUDGET) {
+            Ok(outcome) => {
+                if outcome.passed() {
+                    if !expected.contains(&name) {
+                        println!("{}: passes but isn't in {}", name, PASSING_LIST);
+                    }
+                    passing.push(name.clone());
+                }
+                println!("{}: {}", name, outcome);
+            }
+            Err(e) => println!("{}: couldn't load: {}", name, e),
+        }
+    }
+    println!("{}/{} passed", passing.len(), roms.len());
+    let regressions: Vec<&String> = expected.iter().filter(|x| !passing.contains(x)).collect();
+    assert!(
+        regressions.is_empty(),
+        "expected to pass but didn't: {:?}",
+        regressions
+    );
+}
+
+///Every timer rom is on the expected-pass list, and passes when the suite is there
+#[test]
+fn mooneye_timer_roms() {
+    let expected = expected_passes();
+    for name in TIMER_ROMS.iter() {
+        assert!(
+            expected.iter().any(|x| x == name),
+            "{} isn't in {}",
+            name,
+            PASSING_LIST
+        );
+    }
+    if mooneye::find_roms(ROM_DIR).unwrap_or_default().is_empty() {
+        println!("no roms in {}, skipping", ROM_DIR);
+        return;
+    }
+    let mut failures = Vec::new();
+    for name in TIMER_ROMS.iter() {
+        let path = format!("{}/{}", ROM_DIR, name);
+        match mooneye::run_rom(&path, mooneye::DEFAULT_CYCLE_BUDGET) {
+            Ok(ref outcome) if outcome.passed() => (),
+            Ok(outcome) => failures.push(format!("{}: {}", name, outcome)),
+            Err(e) => failures.push(format!("{}: couldn't load: {}", name, e)),
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
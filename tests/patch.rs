@@ -0,0 +1,214 @@
+//! IPS, UPS and BPS patching on small hand-built patches
+extern crate bouzu;
+extern crate crc32fast;
+
+use bouzu::patch::{self, PatchError};
+
+fn source() -> Vec<u8> {
+    (0..32).collect()
+}
+
+///UPS/BPS number encoding, 7 bits per byte with the high bit on the last one
+fn push_varint(out: &mut Vec<u8>, mut val: usize) {
+    loop {
+        let x = (val & 0x7f) as u8;
+        val >>= 7;
+        if val == 0 {
+            out.push(0x80 | x);
+            return;
+        }
+        out.push(x);
+        val -= 1;
+    }
+}
+
+fn push_le32(out: &mut Vec<u8>, val: u32) {
+    for i in 0..4 {
+        out.push((val >> (i * 8)) as u8);
+    }
+}
+
+///Source and target crc32, then the crc32 of the patch up to that point
+fn push_footer(out: &mut Vec<u8>, source: &[u8], target: &[u8]) {
+    push_le32(out, crc32fast::hash(source));
+    push_le32(out, crc32fast::hash(target));
+    let crc = crc32fast::hash(out);
+    push_le32(out, crc);
+}
+
+///UPS patch xoring the source into the target, one hunk per run of changed bytes
+fn ups_patch(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = b"UPS1".to_vec();
+    push_varint(&mut out, source.len());
+    push_varint(&mut out, target.len());
+    let byte = |i: usize| source.get(i).cloned().unwrap_or(0);
+    let mut last = 0;
+    let mut pos = 0;
+    while pos < target.len() {
+        if byte(pos) == target[pos] {
+            pos += 1;
+            continue;
+        }
+        push_varint(&mut out, pos - last);
+        while pos < target.len() && byte(pos) != target[pos] {
+            out.push(byte(pos) ^ target[pos]);
+            pos += 1;
+        }
+        //the terminator covers the next (unchanged) byte
+        out.push(0);
+        pos += 1;
+        last = pos;
+    }
+    push_footer(&mut out, source, target);
+    out
+}
+
+#[test]
+fn ips_plain_record() {
+    let mut ips = b"PATCH".to_vec();
+    //3 bytes at 0x000004
+    ips.extend_from_slice(&[0x00, 0x00, 0x04, 0x00, 0x03, 0xaa, 0xbb, 0xcc]);
+    ips.extend_from_slice(b"EOF");
+    let out = patch::apply_patch(&source(), &ips).unwrap();
+    let mut expected = source();
+    expected[4..7].copy_from_slice(&[0xaa, 0xbb, 0xcc]);
+    assert_eq!(out, expected);
+}
+
+#[test]
+fn ips_rle_record_grows_the_rom() {
+    let mut ips = b"PATCH".to_vec();
+    //0x55 repeated 8 times at 0x00001c, running past the end
+    ips.extend_from_slice(&[0x00, 0x00, 0x1c, 0x00, 0x00, 0x00, 0x08, 0x55]);
+    ips.extend_from_slice(b"EOF");
+    let out = patch::apply_patch(&source(), &ips).unwrap();
+    assert_eq!(out.len(), 36);
+    assert_eq!(&out[..0x1c], &source()[..0x1c]);
+    assert!(out[0x1c..].iter().all(|x| *x == 0x55));
+}
+
+#[test]
+fn ips_truncate_extension() {
+    let mut ips = b"PATCH".to_vec();
+    ips.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x01, 0xff]);
+    ips.extend_from_slice(b"EOF");
+    //cut the rom down to 16 bytes
+    ips.extend_from_slice(&[0x00, 0x00, 0x10]);
+    let out = patch::apply_patch(&source(), &ips).unwrap();
+    assert_eq!(out.len(), 16);
+    assert_eq!(out[0], 0xff);
+    assert_eq!(&out[1..], &source()[1..16]);
+}
+
+#[test]
+fn ips_truncated_record() {
+    let mut ips = b"PATCH".to_vec();
+    ips.extend_from_slice(&[0x00, 0x00, 0x04, 0x00, 0x03, 0xaa]);
+    assert_eq!(
+        patch::apply_patch(&source(), &ips),
+        Err(PatchError::Truncated)
+    );
+}
+
+#[test]
+fn ups_round_trip() {
+    let mut target = source();
+    target[3] = 0x99;
+    target[10..14].copy_from_slice(&[1, 2, 3, 4]);
+    target.extend_from_slice(&[0xde, 0xad]);
+    let ups = ups_patch(&source(), &target);
+    assert_eq!(patch::apply_patch(&source(), &ups).unwrap(), target);
+}
+
+#[test]
+fn ups_bad_crc() {
+    let mut target = source();
+    target[3] = 0x99;
+    let mut ups = ups_patch(&source(), &target);
+    //flip a byte of the hunk, the patch's own crc no longer matches
+    let hunk = ups.len() - 14;
+    ups[hunk] ^= 0x01;
+    match patch::apply_patch(&source(), &ups) {
+        Err(PatchError::PatchChecksum { .. }) => (),
+        x => panic!("expected a patch checksum error, got {:?}", x),
+    }
+    //made for a different rom
+    let ups = ups_patch(&source(), &target);
+    match patch::apply_patch(&target, &ups) {
+        Err(PatchError::SourceChecksum { .. }) => (),
+        x => panic!("expected a source checksum error, got {:?}", x),
+    }
+}
+
+#[test]
+fn ups_huge_target_size_is_rejected() {
+    let mut ups = b"UPS1".to_vec();
+    push_varint(&mut ups, 32);
+    push_varint(&mut ups, 1 << 40);
+    push_footer(&mut ups, &source(), &[]);
+    assert_eq!(
+        patch::apply_patch(&source(), &ups),
+        Err(PatchError::OutOfBounds)
+    );
+}
+
+///Source read, target read, source copy and an overlapping target copy
+fn bps_patch() -> (Vec<u8>, Vec<u8>) {
+    let mut target = source()[..8].to_vec();
+    target.extend_from_slice(&[0xaa, 0xbb]);
+    target.extend_from_slice(&source()[20..24]);
+    //8 bytes from offset 8 while the target is only 14 long, so the last 2 are bytes the copy wrote itself
+    target.extend_from_slice(&[0xaa, 0xbb, 20, 21, 22, 23, 0xaa, 0xbb]);
+
+    let mut bps = b"BPS1".to_vec();
+    push_varint(&mut bps, 32);
+    push_varint(&mut bps, target.len());
+    //metadata
+    push_varint(&mut bps, 0);
+    //action = (length - 1) << 2 | kind
+    push_varint(&mut bps, 7 << 2);
+    push_varint(&mut bps, 1 << 2 | 1);
+    bps.extend_from_slice(&[0xaa, 0xbb]);
+    push_varint(&mut bps, 3 << 2 | 2);
+    //relative offsets are magnitude << 1 | sign
+    push_varint(&mut bps, 20 << 1);
+    push_varint(&mut bps, 7 << 2 | 3);
+    push_varint(&mut bps, 8 << 1);
+    push_footer(&mut bps, &source(), &target);
+    (bps, target)
+}
+
+#[test]
+fn bps_round_trip() {
+    let (bps, target) = bps_patch();
+    assert_eq!(patch::apply_patch(&source(), &bps).unwrap(), target);
+}
+
+#[test]
+fn bps_bad_crc() {
+    let (mut bps, _) = bps_patch();
+    //the last 4 bytes are the patch's own crc
+    let len = bps.len();
+    bps[len - 1] ^= 0x80;
+    match patch::apply_patch(&source(), &bps) {
+        Err(PatchError::PatchChecksum { .. }) => (),
+        x => panic!("expected a patch checksum error, got {:?}", x),
+    }
+}
+
+#[test]
+fn bps_copy_past_the_source_is_rejected() {
+    let target = vec![0u8; 4];
+    let mut bps = b"BPS1".to_vec();
+    push_varint(&mut bps, 32);
+    push_varint(&mut bps, target.len());
+    push_varint(&mut bps, 0);
+    //source copy of 4 bytes from 30
+    push_varint(&mut bps, 3 << 2 | 2);
+    push_varint(&mut bps, 30 << 1);
+    push_footer(&mut bps, &source(), &target);
+    assert_eq!(
+        patch::apply_patch(&source(), &bps),
+        Err(PatchError::OutOfBounds)
+    );
+}
@@ -0,0 +1,390 @@
+//! Scanline rendering, STAT interrupts and the finished frame the ppu hands out
+extern crate bouzu;
+
+use bouzu::interrupt::Interrupt;
+use bouzu::ppu::{Ppu, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+///T-cycles in one frame, 154 lines of 456 dots
+const FRAME_CYCLES: u32 = 154 * LINE_CYCLES;
+const LINE_CYCLES: u32 = 456;
+
+///Ppu with tile 0 solid color 3 covering the background
+fn solid_background() -> Ppu {
+    let mut ppu = Ppu::new();
+    for addr in 0x8000..0x8010 {
+        ppu.write_vram(addr, 0xff);
+    }
+    ppu.write8(0xff47, 0b1110_0100);
+    //lcd on, tile data at 0x8000, background on
+    ppu.write8(0xff40, 0x91);
+    ppu
+}
+
+#[test]
+fn framebuffer_only_changes_at_vblank() {
+    let mut ppu = solid_background();
+    ppu.tick(FRAME_CYCLES);
+    assert_eq!(ppu.get_frame_count(), 1);
+    assert!(ppu.get_framebuffer().iter().all(|x| *x == 3));
+
+    //every color maps to shade 0 from here on
+    ppu.write8(0xff47, 0x00);
+    ppu.tick(FRAME_CYCLES / 2);
+    assert!(ppu.get_framebuffer().iter().all(|x| *x == 3));
+    ppu.tick(FRAME_CYCLES / 2);
+    assert_eq!(ppu.get_frame_count(), 2);
+    assert!(ppu.get_framebuffer().iter().all(|x| *x == 0));
+}
+
+///Ppu with the lcd off and every palette mapping colour n to shade n
+fn blank_ppu() -> Ppu {
+    let mut ppu = Ppu::new();
+    for addr in 0xff47..=0xff49 {
+        ppu.write8(addr, 0b1110_0100);
+    }
+    ppu
+}
+
+///Fills tile data entry index (0x8000 addressing) with a single colour
+fn solid_tile(ppu: &mut Ppu, index: u8, color: u8) {
+    let lo = if color & 1 != 0 { 0xff } else { 0x00 };
+    let hi = if color & 2 != 0 { 0xff } else { 0x00 };
+    for row in 0..8 {
+        let addr = 0x8000 + index as u16 * 16 + row * 2;
+        ppu.write_vram(addr, lo);
+        ppu.write_vram(addr + 1, hi);
+    }
+}
+
+///Sets OAM entry n, x and y are screen coordinates
+fn sprite(ppu: &mut Ppu, n: u16, x: i16, y: i16, tile: u8, flags: u8) {
+    let addr = 0xfe00 + n * 4;
+    ppu.write_oam(addr, (y + 16) as u8);
+    ppu.write_oam(addr + 1, (x + 8) as u8);
+    ppu.write_oam(addr + 2, tile);
+    ppu.write_oam(addr + 3, flags);
+}
+
+fn pixel(ppu: &Ppu, x: usize, y: usize) -> u8 {
+    ppu.get_framebuffer()[y * SCREEN_WIDTH + x]
+}
+
+///Turns the lcd on with the given LCDC and runs one whole frame
+fn render(ppu: &mut Ppu, lcdc: u8) {
+    ppu.write8(0xff40, lcdc);
+    ppu.tick(FRAME_CYCLES);
+}
+
+#[test]
+fn window_line_counter_only_counts_lines_it_was_drawn_on() {
+    let mut ppu = blank_ppu();
+    for color in 1..4 {
+        solid_tile(&mut ppu, color, color);
+    }
+    //window map rows 0, 1 and 2 use tiles of colour 1, 2 and 3
+    for row in 0..3 {
+        for column in 0..32 {
+            ppu.write_vram(0x9c00 + row * 32 + column, row as u8 + 1);
+        }
+    }
+    ppu.write8(0xff4a, 0);
+    ppu.write8(0xff4b, 7);
+    //lcd on, window map at 0x9c00, window on, tile data at 0x8000, background on
+    let window_on = 0xf1;
+    ppu.write8(0xff40, window_on);
+    ppu.tick(8 * LINE_CYCLES);
+    ppu.write8(0xff40, window_on & !0x20);
+    ppu.tick(8 * LINE_CYCLES);
+    ppu.write8(0xff40, window_on);
+    ppu.tick(FRAME_CYCLES - 16 * LINE_CYCLES);
+
+    assert_eq!(pixel(&ppu, 0, 7), 1);
+    //the background shows through while it's off
+    assert_eq!(pixel(&ppu, 0, 8), 0);
+    assert_eq!(pixel(&ppu, 0, 15), 0);
+    //and it carries on from its 9th line instead of jumping to LY
+    assert_eq!(pixel(&ppu, 0, 16), 2);
+    assert_eq!(pixel(&ppu, 0, 24), 3);
+}
+
+#[test]
+fn ten_sprites_per_line_and_lower_x_wins() {
+    let mut ppu = blank_ppu();
+    solid_tile(&mut ppu, 1, 1);
+    solid_tile(&mut ppu, 2, 2);
+    solid_tile(&mut ppu, 3, 3);
+    //11 sprites on lines 0-7, the last one in OAM order isn't drawn
+    for n in 0..11 {
+        sprite(&mut ppu, n, n as i16 * 10, 0, 1, 0);
+    }
+    //on lines 40-47 the one further left wins even though it comes later in OAM
+    sprite(&mut ppu, 11, 12, 40, 1, 0);
+    sprite(&mut ppu, 12, 8, 40, 2, 0);
+    //same x, the earlier OAM entry wins
+    sprite(&mut ppu, 13, 40, 40, 3, 0);
+    sprite(&mut ppu, 14, 40, 40, 2, 0);
+    //lcd on, tile data at 0x8000, sprites on
+    render(&mut ppu, 0x92);
+
+    for n in 0..10 {
+        assert_eq!(pixel(&ppu, n * 10, 0), 1, "sprite {}", n);
+    }
+    assert_eq!(pixel(&ppu, 100, 0), 0);
+    assert_eq!(pixel(&ppu, 8, 40), 2);
+    assert_eq!(pixel(&ppu, 15, 40), 2);
+    assert_eq!(pixel(&ppu, 16, 40), 1);
+    assert_eq!(pixel(&ppu, 40, 40), 3);
+}
+
+#[test]
+fn behind_bg_sprites_only_show_over_background_colour_0() {
+    let mut ppu = blank_ppu();
+    solid_tile(&mut ppu, 1, 1);
+    solid_tile(&mut ppu, 3, 3);
+    //background columns 8-15 use colour 3, the rest colour 0
+    for row in 0..32 {
+        ppu.write_vram(0x9800 + row * 32 + 1, 3);
+    }
+    //colour 0 is shown as shade 3, the flag goes by colour rather than shade
+    ppu.write8(0xff47, 0b1110_0111);
+    //behind the background at x 4-11, in front at x 4-11 on lines 8-15
+    sprite(&mut ppu, 0, 4, 0, 1, 0x80);
+    sprite(&mut ppu, 1, 4, 8, 1, 0x00);
+    render(&mut ppu, 0x93);
+
+    assert_eq!(pixel(&ppu, 4, 0), 1);
+    assert_eq!(pixel(&ppu, 7, 0), 1);
+    assert_eq!(pixel(&ppu, 8, 0), 3);
+    assert_eq!(pixel(&ppu, 11, 0), 3);
+    assert_eq!(pixel(&ppu, 8, 8), 1);
+    assert_eq!(pixel(&ppu, 11, 8), 1);
+    assert_eq!(pixel(&ppu, 12, 8), 3);
+}
+
+#[test]
+fn tall_sprites_ignore_bit_0_of_the_tile() {
+    let mut ppu = blank_ppu();
+    solid_tile(&mut ppu, 4, 1);
+    solid_tile(&mut ppu, 5, 2);
+    sprite(&mut ppu, 0, 0, 0, 4, 0);
+    sprite(&mut ppu, 1, 8, 0, 5, 0);
+    //flipped vertically the halves swap too
+    sprite(&mut ppu, 2, 16, 0, 5, 0x40);
+    //lcd on, tile data at 0x8000, 8x16 sprites on
+    render(&mut ppu, 0x96);
+
+    for &x in &[0, 8] {
+        assert_eq!(pixel(&ppu, x, 0), 1);
+        assert_eq!(pixel(&ppu, x, 7), 1);
+        assert_eq!(pixel(&ppu, x, 8), 2);
+        assert_eq!(pixel(&ppu, x, 15), 2);
+    }
+    assert_eq!(pixel(&ppu, 16, 0), 2);
+    assert_eq!(pixel(&ppu, 16, 15), 1);
+    assert_eq!(pixel(&ppu, 0, 16), 0);
+}
+
+#[test]
+fn sprite_flips() {
+    let mut ppu = blank_ppu();
+    //tile 1 only has its top left pixel set
+    ppu.write_vram(0x8010, 0x80);
+    ppu.write_vram(0x8011, 0x80);
+    for (n, &flags) in [0x00u8, 0x20, 0x40, 0x60].iter().enumerate() {
+        sprite(&mut ppu, n as u16, n as i16 * 16, 0, 1, flags);
+    }
+    render(&mut ppu, 0x92);
+
+    let corners = [(0, 0), (7, 0), (0, 7), (7, 7)];
+    for (n, &(x, y)) in corners.iter().enumerate() {
+        for &(cx, cy) in &corners {
+            let expected = if (cx, cy) == (x, y) { 3 } else { 0 };
+            assert_eq!(
+                pixel(&ppu, n * 16 + cx, cy),
+                expected,
+                "sprite {} at ({}, {})",
+                n,
+                cx,
+                cy
+            );
+        }
+    }
+}
+
+///Number of STAT interrupts requested over one frame with the given STAT sources and LYC
+fn stat_interrupts(stat: u8, lyc: u8) -> usize {
+    let mut ppu = blank_ppu();
+    ppu.write8(0xff41, stat);
+    ppu.write8(0xff45, lyc);
+    ppu.write8(0xff40, 0x91);
+    //a frame in steady state, from the last dot of one frame to the last dot of the next
+    ppu.tick(FRAME_CYCLES);
+    let mut count = 0;
+    let mut vblanks = 0;
+    for _ in 0..FRAME_CYCLES {
+        let interrupts = ppu.tick(1);
+        if interrupts & Interrupt::Stat.bit() != 0 {
+            count += 1;
+        }
+        if interrupts & Interrupt::VBlank.bit() != 0 {
+            vblanks += 1;
+        }
+    }
+    assert_eq!(vblanks, 1);
+    count
+}
+
+#[test]
+fn stat_interrupts_fire_once_per_rising_edge() {
+    //hblank, vblank and oam scan
+    assert_eq!(stat_interrupts(0x08, 0), SCREEN_HEIGHT);
+    assert_eq!(stat_interrupts(0x10, 0), 1);
+    assert_eq!(stat_interrupts(0x20, 0), SCREEN_HEIGHT);
+    //LYC=LY only rises once even though it stays true for the whole line
+    assert_eq!(stat_interrupts(0x40, 10), 1);
+    assert_eq!(stat_interrupts(0x40, 200), 0);
+    //the sources share one line: hblank on line 9 keeps it high into LY=10,
+    //which then covers line 10's hblank, so one edge goes missing
+    assert_eq!(stat_interrupts(0x48, 10), SCREEN_HEIGHT - 1);
+}
+
+#[cfg(feature = "fifo-ppu")]
+mod fifo {
+    use super::*;
+    use bouzu::ppu::{Mode, Renderer};
+
+    ///Dots line 8 spends in mode 3, with the background on and sprites at the given OAM x on it
+    fn mode_3_length(scx: u8, sprite_xs: &[u8]) -> u32 {
+        let mut ppu = blank_ppu();
+        ppu.set_renderer(Renderer::Fifo);
+        ppu.write8(0xff43, scx);
+        for (n, &x) in sprite_xs.iter().enumerate() {
+            sprite(&mut ppu, n as u16, x as i16 - 8, 8, 0, 0);
+        }
+        ppu.write8(0xff40, 0x93);
+        ppu.tick(8 * LINE_CYCLES);
+        let mut dots = 0;
+        for _ in 0..LINE_CYCLES {
+            ppu.tick(1);
+            if ppu.get_mode() == Mode::Drawing {
+                dots += 1;
+            }
+        }
+        dots
+    }
+
+    #[test]
+    fn mode_3_grows_with_fine_scroll() {
+        assert_eq!(mode_3_length(0, &[]), 172);
+        for scx in 1..8 {
+            assert_eq!(mode_3_length(scx, &[]), 172 + scx as u32, "scx {}", scx);
+        }
+        assert_eq!(mode_3_length(8, &[]), 172);
+        assert_eq!(mode_3_length(0x23, &[]), 175);
+    }
+
+    #[test]
+    fn sprite_fetches_add_dots() {
+        //6 dots each, plus 0-5 waiting on the background tile under the sprite's leftmost pixel
+        assert_eq!(mode_3_length(0, &[8]), 172 + 11);
+        assert_eq!(mode_3_length(0, &[9]), 172 + 10);
+        assert_eq!(mode_3_length(0, &[12]), 172 + 7);
+        assert_eq!(mode_3_length(0, &[15]), 172 + 6);
+        assert_eq!(mode_3_length(0, &[167]), 172 + 6);
+        //partly off the left edge still counts, fully off the right edge doesn't
+        assert_eq!(mode_3_length(0, &[0]), 172 + 11);
+        assert_eq!(mode_3_length(0, &[168]), 172);
+        //fine scroll moves the tile boundaries under the sprite
+        assert_eq!(mode_3_length(3, &[8]), 172 + 3 + 8);
+        //the wait is only paid once per background tile
+        assert_eq!(mode_3_length(0, &[8, 16]), 172 + 11 + 11);
+        assert_eq!(mode_3_length(0, &[8; 10]), 172 + 11 + 9 * 6);
+    }
+
+    ///Frame with line 8 interrupted roughly halfway through mode 3 by write
+    fn mid_line_write(renderer: Renderer, addr: u16, dat: u8) -> Ppu {
+        let mut ppu = blank_ppu();
+        ppu.set_renderer(renderer);
+        solid_tile(&mut ppu, 0, 1);
+        ppu.write8(0xff40, 0x91);
+        //oam scan, then about 12 dots before the first pixel comes out
+        ppu.tick(8 * LINE_CYCLES + 80 + 12 + 80);
+        assert_eq!(ppu.get_mode(), Mode::Drawing);
+        ppu.write8(addr, dat);
+        ppu.tick(FRAME_CYCLES - (8 * LINE_CYCLES + 80 + 12 + 80));
+        ppu
+    }
+
+    #[test]
+    fn mid_line_writes_show_up() {
+        //colour 1 goes from shade 1 to shade 3
+        let ppu = mid_line_write(Renderer::Fifo, 0xff47, 0b1110_1100);
+        assert_eq!(pixel(&ppu, 0, 7), 1);
+        assert_eq!(pixel(&ppu, 159, 7), 1);
+        assert_eq!(pixel(&ppu, 0, 8), 1);
+        assert_eq!(pixel(&ppu, 159, 8), 3);
+        assert_eq!(pixel(&ppu, 0, 9), 3);
+        let changed = (0..SCREEN_WIDTH).position(|x| pixel(&ppu, x, 8) == 3);
+        assert!(
+            changed.is_some_and(|x| (70..90).contains(&x)),
+            "{:?}",
+            changed
+        );
+        //the scanline renderer only sees the value at the end of the line
+        let ppu = mid_line_write(Renderer::Scanline, 0xff47, 0b1110_1100);
+        assert_eq!(pixel(&ppu, 0, 8), 3);
+
+        //turning the background off blanks the rest of the line
+        let ppu = mid_line_write(Renderer::Fifo, 0xff40, 0x90);
+        assert_eq!(pixel(&ppu, 0, 8), 1);
+        assert_eq!(pixel(&ppu, 159, 8), 0);
+    }
+
+    ///Background, window and a mix of sprites, all from a fixed pseudo random sequence
+    fn busy_scene(renderer: Renderer) -> Ppu {
+        let mut ppu = blank_ppu();
+        ppu.set_renderer(renderer);
+        let mut seed = 0x1234_5678u32;
+        let mut next = move || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        };
+        for addr in 0x8000..0x8200 {
+            ppu.write_vram(addr, next());
+        }
+        for addr in 0x9800..0xa000 {
+            ppu.write_vram(addr, next() & 0x1f);
+        }
+        for n in 0..40 {
+            let (x, y) = (next() % 176, next() % 160);
+            let (tile, flags) = (next() & 0x1f, next() & 0xf0);
+            sprite(&mut ppu, n, x as i16 - 8, y as i16 - 16, tile, flags);
+        }
+        ppu.write8(0xff42, 21);
+        ppu.write8(0xff43, 13);
+        ppu.write8(0xff48, 0b1110_0100);
+        ppu.write8(0xff49, 0b0001_1011);
+        ppu.write8(0xff4a, 60);
+        ppu.write8(0xff4b, 87);
+        //everything on, window map at 0x9c00
+        render(&mut ppu, 0xf3);
+        ppu
+    }
+
+    #[test]
+    fn static_scene_matches_the_scanline_renderer() {
+        let scanline = busy_scene(Renderer::Scanline);
+        let fifo = busy_scene(Renderer::Fifo);
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                assert_eq!(
+                    pixel(&fifo, x, y),
+                    pixel(&scanline, x, y),
+                    "pixel ({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+}
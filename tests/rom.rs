@@ -0,0 +1,446 @@
+//! Cartridge loading and memory bank controller checks, on small hand-built roms
+extern crate bouzu;
+
+use bouzu::info::RomInfo;
+use bouzu::rom::{self, Cartridge, ClockSource};
+use bouzu::shared::CLOCK_SPEED;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+///32KiB rom with the given cartridge type and a valid header checksum
+fn rom_image(cartridge_type: u8) -> Vec<u8> {
+    let mut dat = vec![0u8; 0x8000];
+    dat[0x147] = cartridge_type;
+    dat[0x14d] = rom::header_checksum(&dat);
+    dat
+}
+
+///Rom with the given type, rom size and ram size codes where every 16KiB bank starts with its own
+///number (little endian), so reads show which bank is mapped
+fn banked_rom(cartridge_type: u8, rom_size_code: u8, ram_size_code: u8) -> Box<dyn Cartridge> {
+    let banks = 2usize << rom_size_code;
+    let mut dat = vec![0u8; banks * 0x4000];
+    for bank in 0..banks {
+        dat[bank * 0x4000] = bank as u8;
+        dat[bank * 0x4000 + 1] = (bank >> 8) as u8;
+    }
+    dat[0x147] = cartridge_type;
+    dat[0x148] = rom_size_code;
+    dat[0x149] = ram_size_code;
+    dat[0x14d] = rom::header_checksum(&dat);
+    rom::load_rom_from_bytes(dat).unwrap()
+}
+
+///Number of the bank mapped at 0x0000 or 0x4000
+fn bank_at(cartridge: &dyn Cartridge, addr: u16) -> u16 {
+    cartridge.read16(addr)
+}
+
+#[test]
+fn read16_is_little_endian() {
+    //rom only, mbc1, mbc2, mbc3, mbc5
+    for &cartridge_type in &[0x00u8, 0x01, 0x05, 0x11, 0x19] {
+        let mut dat = rom_image(cartridge_type);
+        dat[0x0200] = 0x34;
+        dat[0x0201] = 0x12;
+        dat[0x4000] = 0x78;
+        dat[0x4001] = 0x56;
+        let cartridge = rom::load_rom_from_bytes(dat).unwrap();
+        assert_eq!(
+            cartridge.read16(0x0200),
+            0x1234,
+            "type {:02x}",
+            cartridge_type
+        );
+        assert_eq!(
+            cartridge.read16(0x4000),
+            0x5678,
+            "type {:02x}",
+            cartridge_type
+        );
+    }
+}
+
+#[test]
+fn bad_header_checksum_is_only_a_warning() {
+    let mut dat = rom_image(0x00);
+    dat[0x14d] = dat[0x14d].wrapping_add(1);
+    assert!(rom::load_rom_from_bytes(dat.clone()).is_ok());
+    match rom::load_rom_from_bytes_strict(dat) {
+        Err(rom::RomError::BadChecksum { .. }) => (),
+        Err(e) => panic!("wrong error: {}", e),
+        Ok(_) => panic!("strict loading accepted a bad checksum"),
+    }
+}
+
+#[test]
+fn oversized_images_load() {
+    let mut dat = rom_image(0x01);
+    dat[0x4000] = 0x42;
+    //padded out to 64KiB, the header still says 32KiB
+    dat.resize(0x10000, 0xff);
+    let cartridge = rom::load_rom_from_bytes(dat).unwrap();
+    assert_eq!(cartridge.read8(0x4000), 0x42);
+}
+
+#[test]
+fn truncated_images_are_rejected() {
+    let mut dat = rom_image(0x00);
+    dat.truncate(0x6000);
+    match rom::load_rom_from_bytes(dat) {
+        Err(rom::RomError::SizeMismatch { expected, actual }) => {
+            assert_eq!((expected, actual), (0x8000, 0x6000))
+        }
+        Err(e) => panic!("wrong error: {}", e),
+        Ok(_) => panic!("truncated rom loaded"),
+    }
+}
+
+#[test]
+fn unsupported_cartridges_still_have_a_header() {
+    //rom+ram, mmm01, mbc6, mbc7, pocket camera, huc3, huc1
+    for &cartridge_type in &[0x08u8, 0x0b, 0x20, 0x22, 0xfc, 0xfe, 0xff] {
+        let dat = rom_image(cartridge_type);
+        let info = RomInfo::from_bytes("test.gb", &dat).unwrap();
+        assert!(info.header.model.is_none());
+        assert_eq!(info.header.type_code, cartridge_type);
+        assert!(info
+            .to_string()
+            .contains(rom::cartridge_type_name(cartridge_type).unwrap()));
+        match rom::load_rom_from_bytes(dat) {
+            Err(rom::RomError::UnknownCartridgeType(x)) => assert_eq!(x, cartridge_type),
+            Err(e) => panic!("wrong error: {}", e),
+            Ok(_) => panic!("type {:02x} loaded", cartridge_type),
+        }
+    }
+}
+
+#[test]
+fn unknown_rom_size_is_only_an_error_when_loading() {
+    let mut dat = rom_image(0x00);
+    dat[0x148] = 0x52;
+    dat[0x14d] = rom::header_checksum(&dat);
+    let info = RomInfo::from_bytes("test.gb", &dat).unwrap();
+    assert_eq!(info.header.rom_size_code, 0x52);
+    assert_eq!(info.header.rom_banks, 0);
+    match rom::load_rom_from_bytes(dat) {
+        Err(rom::RomError::UnknownRomSize(0x52)) => (),
+        Err(e) => panic!("wrong error: {}", e),
+        Ok(_) => panic!("rom with an unknown size loaded"),
+    }
+}
+
+#[test]
+fn mbc1_rom_banking() {
+    //mbc1+ram+battery, 2MiB, 32KiB of ram
+    let mut cart = banked_rom(0x03, 0x06, 0x03);
+    assert_eq!(bank_at(&*cart, 0x4000), 1);
+    cart.write8(0x2000, 0x05);
+    assert_eq!(bank_at(&*cart, 0x4000), 0x05);
+    cart.write8(0x2000, 0x00);
+    assert_eq!(bank_at(&*cart, 0x4000), 0x01);
+    //only the lower 5 bits are checked for 0, so 0x20/0x40/0x60 map the bank after them
+    for &upper in &[1u8, 2, 3] {
+        cart.write8(0x4000, upper);
+        cart.write8(0x2000, 0x00);
+        assert_eq!(bank_at(&*cart, 0x4000), (upper as u16) << 5 | 1);
+        cart.write8(0x2000, 0x12);
+        assert_eq!(bank_at(&*cart, 0x4000), (upper as u16) << 5 | 0x12);
+    }
+    //simple banking leaves bank 0 alone
+    assert_eq!(bank_at(&*cart, 0x0000), 0);
+}
+
+#[test]
+fn mbc1_advanced_banking_moves_bank_0_and_ram() {
+    let mut cart = banked_rom(0x03, 0x06, 0x03);
+    cart.write8(0x0000, 0x0a);
+    cart.write8(0x6000, 0x01);
+    cart.write8(0x4000, 0x00);
+    cart.write8(0xa000, 0x11);
+    cart.write8(0x4000, 0x02);
+    assert_eq!(bank_at(&*cart, 0x0000), 0x40);
+    assert_eq!(cart.read8(0xa000), 0x00);
+    cart.write8(0xa000, 0x22);
+
+    //back in simple mode the upper bits only apply to 0x4000 - 0x7fff
+    cart.write8(0x6000, 0x00);
+    assert_eq!(bank_at(&*cart, 0x0000), 0x00);
+    assert_eq!(bank_at(&*cart, 0x4000), 0x41);
+    assert_eq!(cart.read8(0xa000), 0x11);
+    cart.write8(0x6000, 0x01);
+    assert_eq!(cart.read8(0xa000), 0x22);
+}
+
+#[test]
+fn mbc1_ram_enable() {
+    let mut cart = banked_rom(0x03, 0x00, 0x02);
+    assert_eq!(cart.read8(0xa000), 0xff);
+    cart.write8(0xa000, 0x12);
+    cart.write8(0x0000, 0x0a);
+    assert_eq!(cart.read8(0xa000), 0x00);
+    cart.write8(0xa000, 0x34);
+    assert_eq!(cart.read8(0xa000), 0x34);
+    cart.write8(0x1fff, 0x00);
+    assert_eq!(cart.read8(0xa000), 0xff);
+    cart.write8(0xa000, 0x56);
+    //only the lower nibble is checked
+    cart.write8(0x0000, 0x1a);
+    assert_eq!(cart.read8(0xa000), 0x34);
+}
+
+///MBC3 with the clock and 32KiB of ram, with ram and the clock registers enabled
+fn rtc_cartridge() -> Box<dyn Cartridge> {
+    let mut cart = banked_rom(0x10, 0x00, 0x03);
+    cart.write8(0x0000, 0x0a);
+    cart
+}
+
+fn latch(cart: &mut dyn Cartridge) {
+    cart.write8(0x6000, 0x00);
+    cart.write8(0x6000, 0x01);
+}
+
+///Writes one of the clock registers (0x08 - 0x0c)
+fn write_rtc(cart: &mut dyn Cartridge, reg: u8, dat: u8) {
+    cart.write8(0x4000, reg);
+    cart.write8(0xa000, dat);
+}
+
+///Latched value of one of the clock registers (0x08 - 0x0c)
+fn read_rtc(cart: &mut dyn Cartridge, reg: u8) -> u8 {
+    cart.write8(0x4000, reg);
+    cart.read8(0xa000)
+}
+
+///Latched seconds, minutes, hours, day low and day high after latching again
+fn latched_time(cart: &mut dyn Cartridge) -> [u8; 5] {
+    latch(cart);
+    let mut time = [0; 5];
+    for (i, reg) in (0x08..=0x0c).enumerate() {
+        time[i] = read_rtc(cart, reg);
+    }
+    time
+}
+
+#[test]
+fn rtc_latch_needs_0_then_1() {
+    let mut cart = rtc_cartridge();
+    write_rtc(&mut *cart, 0x08, 5);
+    //writes go to the running clock, reads come from the latched copy
+    assert_eq!(read_rtc(&mut *cart, 0x08), 0);
+    latch(&mut *cart);
+    assert_eq!(read_rtc(&mut *cart, 0x08), 5);
+
+    cart.tick(CLOCK_SPEED);
+    assert_eq!(read_rtc(&mut *cart, 0x08), 5);
+    //1 then 1 isn't a latch
+    cart.write8(0x6000, 0x01);
+    assert_eq!(read_rtc(&mut *cart, 0x08), 5);
+    latch(&mut *cart);
+    assert_eq!(read_rtc(&mut *cart, 0x08), 6);
+}
+
+#[test]
+fn rtc_rolls_over_seconds_minutes_and_hours() {
+    let mut cart = rtc_cartridge();
+    write_rtc(&mut *cart, 0x08, 58);
+    cart.tick(CLOCK_SPEED);
+    assert_eq!(latched_time(&mut *cart), [59, 0, 0, 0, 0]);
+    cart.tick(CLOCK_SPEED);
+    assert_eq!(latched_time(&mut *cart), [0, 1, 0, 0, 0]);
+
+    write_rtc(&mut *cart, 0x08, 59);
+    write_rtc(&mut *cart, 0x09, 59);
+    cart.tick(CLOCK_SPEED);
+    assert_eq!(latched_time(&mut *cart), [0, 0, 1, 0, 0]);
+
+    write_rtc(&mut *cart, 0x08, 59);
+    write_rtc(&mut *cart, 0x09, 59);
+    write_rtc(&mut *cart, 0x0a, 23);
+    cart.tick(CLOCK_SPEED);
+    assert_eq!(latched_time(&mut *cart), [0, 0, 0, 1, 0]);
+}
+
+#[test]
+fn rtc_day_counter_is_9_bits_with_a_carry() {
+    let mut cart = rtc_cartridge();
+    //day 0xff at 23:59:59 carries into bit 8 in DH
+    write_rtc(&mut *cart, 0x08, 59);
+    write_rtc(&mut *cart, 0x09, 59);
+    write_rtc(&mut *cart, 0x0a, 23);
+    write_rtc(&mut *cart, 0x0b, 0xff);
+    cart.tick(CLOCK_SPEED);
+    assert_eq!(latched_time(&mut *cart), [0, 0, 0, 0x00, 0x01]);
+
+    //day 0x1ff wraps to 0 and sets the carry flag
+    write_rtc(&mut *cart, 0x08, 59);
+    write_rtc(&mut *cart, 0x09, 59);
+    write_rtc(&mut *cart, 0x0a, 23);
+    write_rtc(&mut *cart, 0x0b, 0xff);
+    cart.tick(CLOCK_SPEED);
+    assert_eq!(latched_time(&mut *cart), [0, 0, 0, 0x00, 0x80]);
+    //the carry stays set until it's written
+    cart.tick(CLOCK_SPEED);
+    assert_eq!(latched_time(&mut *cart)[4], 0x80);
+    write_rtc(&mut *cart, 0x0c, 0x00);
+    assert_eq!(latched_time(&mut *cart)[4], 0x00);
+}
+
+#[test]
+fn rtc_halt_stops_the_clock() {
+    let mut cart = rtc_cartridge();
+    write_rtc(&mut *cart, 0x08, 10);
+    write_rtc(&mut *cart, 0x0c, 0x40);
+    cart.tick(CLOCK_SPEED * 3);
+    assert_eq!(latched_time(&mut *cart), [10, 0, 0, 0, 0x40]);
+    write_rtc(&mut *cart, 0x0c, 0x00);
+    cart.tick(CLOCK_SPEED * 3);
+    assert_eq!(latched_time(&mut *cart), [13, 0, 0, 0, 0x00]);
+}
+
+#[test]
+fn rtc_clock_sources() {
+    let mut cart = rtc_cartridge();
+    assert_eq!(
+        cart.get_rtc_mut().unwrap().get_clock_source(),
+        ClockSource::Emulated
+    );
+    cart.tick(CLOCK_SPEED / 2);
+    assert_eq!(latched_time(&mut *cart)[0], 0);
+    cart.tick(CLOCK_SPEED / 2);
+    assert_eq!(latched_time(&mut *cart)[0], 1);
+
+    //following the host clock, emulated cycles don't count but real time does
+    cart.get_rtc_mut()
+        .unwrap()
+        .set_clock_source(ClockSource::Host);
+    cart.tick(CLOCK_SPEED * 5);
+    assert_eq!(latched_time(&mut *cart)[0], 1);
+    thread::sleep(Duration::from_millis(1100));
+    assert!(latched_time(&mut *cart)[0] >= 2);
+}
+
+#[test]
+fn mbc3_bank_select_picks_ram_or_a_clock_register() {
+    let mut cart = rtc_cartridge();
+    for bank in 0..4 {
+        cart.write8(0x4000, bank);
+        cart.write8(0xa000, 0x10 + bank);
+    }
+    write_rtc(&mut *cart, 0x09, 42);
+    latch(&mut *cart);
+    for bank in 0..4 {
+        cart.write8(0x4000, bank);
+        assert_eq!(cart.read8(0xa000), 0x10 + bank);
+    }
+    assert_eq!(read_rtc(&mut *cart, 0x09), 42);
+    //nothing is mapped between the ram banks and the clock registers, or past them
+    assert_eq!(read_rtc(&mut *cart, 0x04), 0xff);
+    assert_eq!(read_rtc(&mut *cart, 0x0d), 0xff);
+
+    //without the clock the registers aren't there at all
+    let mut cart = banked_rom(0x13, 0x00, 0x03);
+    cart.write8(0x0000, 0x0a);
+    write_rtc(&mut *cart, 0x08, 5);
+    latch(&mut *cart);
+    assert_eq!(read_rtc(&mut *cart, 0x08), 0xff);
+}
+
+#[test]
+fn mbc5_9_bit_rom_bank() {
+    //mbc5+ram+battery, 8MiB
+    let mut cart = banked_rom(0x1b, 0x08, 0x03);
+    assert_eq!(bank_at(&*cart, 0x4000), 1);
+    cart.write8(0x2000, 0x34);
+    assert_eq!(bank_at(&*cart, 0x4000), 0x034);
+    //bit 8 lives in its own register at 0x3000 - 0x3fff
+    cart.write8(0x3000, 0x01);
+    assert_eq!(bank_at(&*cart, 0x4000), 0x134);
+    cart.write8(0x2fff, 0xff);
+    assert_eq!(bank_at(&*cart, 0x4000), 0x1ff);
+    cart.write8(0x3fff, 0xfe);
+    assert_eq!(bank_at(&*cart, 0x4000), 0x0ff);
+    //unlike the other controllers bank 0 can be mapped at 0x4000
+    cart.write8(0x2000, 0x00);
+    assert_eq!(bank_at(&*cart, 0x4000), 0);
+    assert_eq!(bank_at(&*cart, 0x0000), 0);
+}
+
+#[test]
+fn mbc5_ram_bank_bit_3_is_the_motor_on_rumble_carts() {
+    //without rumble bit 3 is a ram bank bit: mbc5+ram+battery with 128KiB of ram
+    let mut cart = banked_rom(0x1b, 0x00, 0x04);
+    cart.write8(0x0000, 0x0a);
+    cart.write8(0x4000, 0x00);
+    cart.write8(0xa000, 0x11);
+    cart.write8(0x4000, 0x08);
+    cart.write8(0xa000, 0x88);
+    assert!(!cart.is_rumbling());
+    cart.write8(0x4000, 0x00);
+    assert_eq!(cart.read8(0xa000), 0x11);
+    cart.write8(0x4000, 0x08);
+    assert_eq!(cart.read8(0xa000), 0x88);
+
+    //mbc5+rumble+ram+battery
+    let mut cart = banked_rom(0x1e, 0x00, 0x04);
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let log = events.clone();
+    cart.set_rumble_callback(Box::new(move |on| log.borrow_mut().push(on)));
+    cart.write8(0x0000, 0x0a);
+    cart.write8(0x4000, 0x03);
+    cart.write8(0xa000, 0x33);
+    assert!(!cart.is_rumbling());
+
+    cart.write8(0x4000, 0x0b);
+    assert!(cart.is_rumbling());
+    assert_eq!(*events.borrow(), [true]);
+    //the motor bit doesn't take part in picking the bank
+    assert_eq!(cart.read8(0xa000), 0x33);
+    //the callback only hears about changes
+    cart.write8(0x4000, 0x08);
+    assert_eq!(*events.borrow(), [true]);
+    assert_eq!(cart.read8(0xa000), 0x00);
+
+    cart.write8(0x4000, 0x03);
+    assert!(!cart.is_rumbling());
+    assert_eq!(*events.borrow(), [true, false]);
+    assert_eq!(cart.read8(0xa000), 0x33);
+}
+
+#[test]
+fn mbc2_address_bit_8_picks_the_register() {
+    //mbc2+battery, 256KiB
+    let mut cart = banked_rom(0x06, 0x03, 0x00);
+    cart.write8(0x0100, 0x05);
+    assert_eq!(bank_at(&*cart, 0x4000), 5);
+    //bit 8 clear is ram enable, even in the upper half
+    cart.write8(0x2000, 0x0a);
+    assert_eq!(bank_at(&*cart, 0x4000), 5);
+    assert_eq!(cart.read8(0xa000), 0xf0);
+    cart.write8(0x3eff, 0x00);
+    assert_eq!(cart.read8(0xa000), 0xff);
+    //bit 8 set is the 4 bit rom bank, anywhere in 0x0000 - 0x3fff
+    cart.write8(0x3f00, 0x1c);
+    assert_eq!(bank_at(&*cart, 0x4000), 0x0c);
+    cart.write8(0x2100, 0x00);
+    assert_eq!(bank_at(&*cart, 0x4000), 1);
+}
+
+#[test]
+fn mbc2_ram_is_512_half_bytes() {
+    let mut cart = banked_rom(0x06, 0x00, 0x00);
+    cart.write8(0x0000, 0x0a);
+    cart.write8(0xa000, 0xab);
+    //the upper nibble isn't stored and reads as 1s
+    assert_eq!(cart.read8(0xa000), 0xfb);
+    //9 address lines, so the cells repeat every 0x200 bytes up to 0xbfff
+    for addr in (0xa000u16..0xc000).step_by(0x200) {
+        assert_eq!(cart.read8(addr), 0xfb);
+    }
+    cart.write8(0xbfff, 0x03);
+    assert_eq!(cart.read8(0xa1ff), 0xf3);
+    assert_eq!(cart.read8(0xa3ff), 0xf3);
+}
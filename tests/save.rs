@@ -0,0 +1,142 @@
+//! Battery save files: raw ram, the MBC3 rtc trailer and when saves get rewritten
+extern crate bouzu;
+
+use bouzu::rom::{self, Cartridge, RTC_TRAILER_SIZE};
+use bouzu::save::SaveFile;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+const RAM_SIZE: usize = 0x2000;
+
+///32KiB rom with 8KiB of battery backed ram, MBC3 with or without the clock
+fn cartridge(rtc: bool) -> Box<dyn Cartridge> {
+    let mut dat = vec![0u8; 0x8000];
+    //mbc3+timer+ram+battery or mbc3+ram+battery
+    dat[0x147] = if rtc { 0x10 } else { 0x13 };
+    dat[0x149] = 0x02;
+    dat[0x14d] = rom::header_checksum(&dat);
+    rom::load_rom_from_bytes(dat).unwrap()
+}
+
+///Path in the temp directory that's unique to this test run, removed first in case it's left over
+fn save_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("bouzu-{}-{}.sav", name, process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn write_ram(cart: &mut dyn Cartridge, addr: u16, dat: u8) {
+    cart.write8(0x0000, 0x0a);
+    cart.write8(0x4000, 0x00);
+    cart.write8(addr, dat);
+}
+
+fn read_ram(cart: &mut dyn Cartridge, addr: u16) -> u8 {
+    cart.write8(0x0000, 0x0a);
+    cart.write8(0x4000, 0x00);
+    cart.read8(addr)
+}
+
+///Latches the clock and reads one of its registers (0x08 - 0x0c)
+fn read_rtc(cart: &mut dyn Cartridge, reg: u8) -> u8 {
+    cart.write8(0x0000, 0x0a);
+    cart.write8(0x6000, 0x00);
+    cart.write8(0x6000, 0x01);
+    cart.write8(0x4000, reg);
+    cart.read8(0xa000)
+}
+
+#[test]
+fn raw_save_round_trip() {
+    let path = save_path("raw");
+    let mut cart = cartridge(false);
+    write_ram(&mut *cart, 0xa000, 0x12);
+    write_ram(&mut *cart, 0xbfff, 0x34);
+    SaveFile::new(&path).flush(&*cart).unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().len(), RAM_SIZE as u64);
+
+    let mut loaded = cartridge(false);
+    assert!(SaveFile::new(&path).load(&mut *loaded).unwrap());
+    assert_eq!(read_ram(&mut *loaded, 0xa000), 0x12);
+    assert_eq!(read_ram(&mut *loaded, 0xbfff), 0x34);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn rtc_trailer_round_trip() {
+    let path = save_path("rtc");
+    let mut cart = cartridge(true);
+    write_ram(&mut *cart, 0xa000, 0x56);
+    //12:34:56 on day 0x1ab
+    for &(reg, val) in &[
+        (0x08u8, 56u8),
+        (0x09, 34),
+        (0x0a, 12),
+        (0x0b, 0xab),
+        (0x0c, 0x01),
+    ] {
+        cart.write8(0x4000, reg);
+        cart.write8(0xa000, val);
+    }
+    SaveFile::new(&path).flush(&*cart).unwrap();
+    assert_eq!(
+        fs::metadata(&path).unwrap().len(),
+        (RAM_SIZE + RTC_TRAILER_SIZE) as u64
+    );
+
+    let mut loaded = cartridge(true);
+    assert!(SaveFile::new(&path).load(&mut *loaded).unwrap());
+    assert_eq!(read_ram(&mut *loaded, 0xa000), 0x56);
+    assert_eq!(read_rtc(&mut *loaded, 0x08), 56);
+    assert_eq!(read_rtc(&mut *loaded, 0x09), 34);
+    assert_eq!(read_rtc(&mut *loaded, 0x0a), 12);
+    assert_eq!(read_rtc(&mut *loaded, 0x0b), 0xab);
+    assert_eq!(read_rtc(&mut *loaded, 0x0c), 0x01);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn old_44_byte_trailer_loads() {
+    let path = save_path("rtc44");
+    let mut dat = vec![0u8; RAM_SIZE];
+    dat[0] = 0x78;
+    //live then latched registers as little endian u32s, then a 32 bit timestamp
+    let registers = [10u8, 20, 3, 0x05, 0x00];
+    for reg in registers.iter().chain(registers.iter()) {
+        dat.extend_from_slice(&[*reg, 0, 0, 0]);
+    }
+    dat.extend_from_slice(&[0, 0, 0, 0]);
+    fs::write(&path, &dat).unwrap();
+
+    let mut cart = cartridge(true);
+    assert!(SaveFile::new(&path).load(&mut *cart).unwrap());
+    assert_eq!(read_ram(&mut *cart, 0xa000), 0x78);
+    assert_eq!(read_rtc(&mut *cart, 0x08), 10);
+    assert_eq!(read_rtc(&mut *cart, 0x09), 20);
+    assert_eq!(read_rtc(&mut *cart, 0x0a), 3);
+    assert_eq!(read_rtc(&mut *cart, 0x0b), 0x05);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn unchanged_rtc_save_isnt_rewritten() {
+    let path = save_path("rtc-unchanged");
+    let mut cart = cartridge(true);
+    write_ram(&mut *cart, 0xa000, 0x9a);
+    let mut save = SaveFile::new(&path);
+    save.flush(&*cart).unwrap();
+    fs::remove_file(&path).unwrap();
+    //long enough for the trailer's timestamp to change
+    thread::sleep(Duration::from_millis(1100));
+    save.flush(&*cart).unwrap();
+    assert!(!path.exists(), "save was rewritten without changes");
+
+    write_ram(&mut *cart, 0xa000, 0xbc);
+    save.flush(&*cart).unwrap();
+    assert!(path.exists());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,103 @@
+//! Internal clock transfers, the serial interrupt and the capture device
+extern crate bouzu;
+
+use bouzu::bus::Bus;
+use bouzu::mmu::Mmu;
+use bouzu::rom;
+use bouzu::serial::{Serial, SerialDevice};
+
+const SB: u16 = 0xff01;
+const SC: u16 = 0xff02;
+const IF: u16 = 0xff0f;
+const SERIAL_INTERRUPT: u8 = 0x08;
+///8 bits at 512 T-cycles each
+const TRANSFER_CYCLES: u32 = 8 * 512;
+
+///Mmu with an empty 32KiB rom
+fn empty_mmu() -> Mmu {
+    let mut dat = vec![0u8; 0x8000];
+    dat[0x14d] = rom::header_checksum(&dat);
+    Mmu::new(rom::load_rom_from_bytes(dat).unwrap())
+}
+
+#[test]
+fn internal_clock_transfer_takes_8_bits_of_512_cycles() {
+    let mut serial = Serial::new();
+    serial.write8(SB, 0x42);
+    serial.write8(SC, 0x81);
+    assert_eq!(serial.read8(SC), 0xff);
+    assert!(!serial.tick(TRANSFER_CYCLES - 1));
+    assert_eq!(serial.read8(SC), 0xff);
+    assert_eq!(serial.read8(SB), 0x42);
+    assert!(serial.tick(1));
+    //bit 7 clears once the byte is out, nothing connected shifts in 0xff
+    assert_eq!(serial.read8(SC), 0x7f);
+    assert_eq!(serial.read8(SB), 0xff);
+    //and nothing more happens until the next transfer
+    assert!(!serial.tick(TRANSFER_CYCLES * 2));
+}
+
+#[test]
+fn external_clock_never_finishes() {
+    let mut serial = Serial::new();
+    serial.write8(SB, 0x42);
+    serial.write8(SC, 0x80);
+    assert!(!serial.tick(TRANSFER_CYCLES * 4));
+    assert_eq!(serial.read8(SC), 0xfe);
+    assert_eq!(serial.read8(SB), 0x42);
+}
+
+#[test]
+fn finished_transfer_requests_the_serial_interrupt() {
+    let mut mmu = empty_mmu();
+    mmu.write8(IF, 0);
+    mmu.write8(SB, 0x42);
+    mmu.write8(SC, 0x81);
+    mmu.tick(TRANSFER_CYCLES - 4);
+    assert_eq!(mmu.read8(IF) & SERIAL_INTERRUPT, 0);
+    mmu.tick(4);
+    assert_eq!(mmu.read8(IF) & SERIAL_INTERRUPT, SERIAL_INTERRUPT);
+    assert_eq!(mmu.read8(SC), 0x7f);
+    assert_eq!(mmu.read8(SB), 0xff);
+}
+
+#[test]
+fn capture_records_each_byte_sent() {
+    let mut mmu = empty_mmu();
+    for &byte in b"ok\n" {
+        mmu.write8(SB, byte);
+        mmu.write8(SC, 0x81);
+        mmu.tick(TRANSFER_CYCLES);
+    }
+    let capture = mmu.get_serial().get_capture().unwrap();
+    assert_eq!(capture.get_output(), b"ok\n");
+    assert_eq!(capture.get_text(), "ok\n");
+
+    mmu.get_serial_mut().get_capture_mut().unwrap().clear();
+    assert!(mmu
+        .get_serial()
+        .get_capture()
+        .unwrap()
+        .get_output()
+        .is_empty());
+}
+
+///Echoes back each byte plus one
+struct Loopback;
+
+impl SerialDevice for Loopback {
+    fn exchange(&mut self, out: u8) -> u8 {
+        out.wrapping_add(1)
+    }
+}
+
+#[test]
+fn other_devices_shift_their_byte_in() {
+    let mut serial = Serial::new();
+    serial.set_device(Box::new(Loopback));
+    assert!(serial.get_capture().is_none());
+    serial.write8(SB, 0x41);
+    serial.write8(SC, 0x81);
+    assert!(serial.tick(TRANSFER_CYCLES));
+    assert_eq!(serial.read8(SB), 0x42);
+}
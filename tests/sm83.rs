@@ -0,0 +1,270 @@
+//! Single instruction conformance tests in the community SM83 JSON format
+//!
+//! Each file holds the tests for one opcode ("27.json", "cb37.json"; the upstream suite names the
+//! prefixed ones "cb 37.json"), each test gives the registers and ram before and after the instruction
+//! plus one entry per M-cycle on the bus.
+//! The full suite isn't shipped with the repo, point SM83_TESTS_DIR at a checkout of it to run it;
+//! otherwise the hand-checked cases in tests/sm83 are run.
+extern crate bouzu;
+extern crate serde_json;
+
+use bouzu::bus::{Bus, FlatRam};
+use bouzu::cpu::Cpu;
+use bouzu::instructions::decode;
+use bouzu::interrupt::Interrupt;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_DIR: &str = "tests/sm83";
+///Mismatches shown per opcode, the rest are only counted
+const SHOWN_FAILURES: usize = 3;
+
+fn test_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("Couldn't read {}: {}", dir.display(), e))
+        .map(|x| x.unwrap().path())
+        .filter(|x| x.extension().is_some_and(|x| x == "json"))
+        .collect();
+    files.sort();
+    files
+}
+
+///One memory access: address, value and whether it was a write
+type Access = (u16, u8, bool);
+
+///Flat 64KiB of ram that logs every access
+///IE and IF are looked at without logging, the hardware doesn't check them over the bus
+struct RecordingBus {
+    ram: FlatRam,
+    accesses: RefCell<Vec<Access>>,
+}
+
+impl RecordingBus {
+    fn take_accesses(&mut self) -> Vec<Access> {
+        self.accesses.replace(Vec::new())
+    }
+}
+
+impl Bus for RecordingBus {
+    fn read8(&self, addr: u16) -> u8 {
+        let dat = self.ram.read8(addr);
+        self.accesses.borrow_mut().push((addr, dat, false));
+        dat
+    }
+    fn write8(&mut self, addr: u16, dat: u8) {
+        self.accesses.borrow_mut().push((addr, dat, true));
+        self.ram.write8(addr, dat);
+    }
+    fn tick(&mut self, cycles: u32) {
+        self.ram.tick(cycles);
+    }
+    fn pending_interrupts(&self) -> u8 {
+        self.ram.pending_interrupts()
+    }
+    fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        self.ram.acknowledge_interrupt(interrupt);
+    }
+}
+
+///Cpu and recording 64KiB bus set up from a test's "initial" state
+fn load_state(state: &Value) -> (Cpu, RecordingBus) {
+    let mut ram = FlatRam::new();
+    for entry in state["ram"].as_array().unwrap() {
+        ram.write8(
+            entry[0].as_u64().unwrap() as u16,
+            entry[1].as_u64().unwrap() as u8,
+        );
+    }
+
+    let mut cpu = Cpu::new();
+    {
+        let reg = cpu.get_register_mut();
+        reg.a = byte(state, "a");
+        reg.f = byte(state, "f");
+        reg.b = byte(state, "b");
+        reg.c = byte(state, "c");
+        reg.d = byte(state, "d");
+        reg.e = byte(state, "e");
+        reg.h = byte(state, "h");
+        reg.l = byte(state, "l");
+        reg.pc = word(state, "pc");
+        reg.sp = word(state, "sp");
+    }
+    cpu.set_interrupts_enabled(byte(state, "ime") != 0);
+    let bus = RecordingBus {
+        ram,
+        accesses: RefCell::new(Vec::new()),
+    };
+    (cpu, bus)
+}
+
+fn byte(state: &Value, field: &str) -> u8 {
+    state[field].as_u64().unwrap_or(0) as u8
+}
+fn word(state: &Value, field: &str) -> u16 {
+    state[field].as_u64().unwrap_or(0) as u16
+}
+
+///Memory accesses of the test's bus cycles in order, internal cycles (null or "---") are left out
+fn expected_accesses(cycles: &[Value]) -> Vec<Access> {
+    cycles
+        .iter()
+        .filter_map(|cycle| {
+            let pins = cycle[2].as_str()?;
+            let write = pins.as_bytes().get(1) == Some(&b'w');
+            if !write && !pins.starts_with('r') {
+                return None;
+            }
+            Some((cycle[0].as_u64()? as u16, cycle[1].as_u64()? as u8, write))
+        })
+        .collect()
+}
+
+///Memory accesses the cpu made running one instruction, in bus order
+///The cpu decodes the whole instruction up front, reading more bytes than it uses, so those reads
+///are swapped for one fetch per byte of the instruction
+fn step_accesses(cpu: &mut Cpu, bus: &mut RecordingBus) -> (u32, Vec<Access>) {
+    let pc = cpu.get_register().pc;
+    let size = decode(bus, pc).get_size() as u16;
+    let decode_reads = bus.take_accesses().len();
+    let mut accesses: Vec<Access> = (0..size)
+        .map(|i| {
+            let addr = pc.wrapping_add(i);
+            (addr, bus.ram.read8(addr), false)
+        })
+        .collect();
+
+    let cycles = cpu.step(bus) / 4;
+    accesses.extend(bus.take_accesses().into_iter().skip(decode_reads));
+    (cycles, accesses)
+}
+
+fn describe(accesses: &[Access]) -> String {
+    let accesses: Vec<String> = accesses
+        .iter()
+        .map(|&(addr, dat, write)| {
+            format!("{}{:04x}={:02x}", if write { "w" } else { "r" }, addr, dat)
+        })
+        .collect();
+    format!("[{}]", accesses.join(" "))
+}
+
+///Runs one test, returns every field that didn't match
+fn run_test(test: &Value) -> Vec<String> {
+    let (mut cpu, mut bus) = load_state(&test["initial"]);
+    let (cycles, accesses) = step_accesses(&mut cpu, &mut bus);
+
+    let expected = &test["final"];
+    let reg = cpu.get_register();
+    let mut mismatches = Vec::new();
+    {
+        let mut check = |field: &str, actual: u16, width: usize| {
+            let wanted = expected[field].as_u64().unwrap_or(0) as u16;
+            if actual != wanted {
+                mismatches.push(format!(
+                    "{}: expected {:0w$x}, got {:0w$x}",
+                    field,
+                    wanted,
+                    actual,
+                    w = width
+                ));
+            }
+        };
+        check("a", reg.a as u16, 2);
+        check("f", reg.f as u16, 2);
+        check("b", reg.b as u16, 2);
+        check("c", reg.c as u16, 2);
+        check("d", reg.d as u16, 2);
+        check("e", reg.e as u16, 2);
+        check("h", reg.h as u16, 2);
+        check("l", reg.l as u16, 2);
+        check("pc", reg.pc, 4);
+        check("sp", reg.sp, 4);
+        check("ime", cpu.interrupts_enabled() as u16, 1);
+    }
+    for entry in expected["ram"].as_array().unwrap() {
+        let addr = entry[0].as_u64().unwrap() as u16;
+        let wanted = entry[1].as_u64().unwrap() as u8;
+        let actual = bus.ram.read8(addr);
+        if actual != wanted {
+            mismatches.push(format!(
+                "ram[{:04x}]: expected {:02x}, got {:02x}",
+                addr, wanted, actual
+            ));
+        }
+    }
+    //the cpu doesn't tick the bus per M-cycle, so where the internal cycles fall isn't checked,
+    //only how many cycles there are and the reads and writes in order
+    if let Some(expected_cycles) = test["cycles"].as_array() {
+        if expected_cycles.len() as u32 != cycles {
+            mismatches.push(format!(
+                "cycles: expected {}, got {}",
+                expected_cycles.len(),
+                cycles
+            ));
+        }
+        let wanted = expected_accesses(expected_cycles);
+        if accesses != wanted {
+            mismatches.push(format!(
+                "bus: expected {}, got {}",
+                describe(&wanted),
+                describe(&accesses)
+            ));
+        }
+    }
+    mismatches
+}
+
+#[test]
+fn sm83_single_step() {
+    let dir = env::var("SM83_TESTS_DIR").unwrap_or_else(|_| DEFAULT_DIR.to_string());
+    let files = test_files(Path::new(&dir));
+    assert!(!files.is_empty(), "no tests found in {}", dir);
+
+    let mut report = Vec::new();
+    let mut total = 0;
+    for file in &files {
+        let text = fs::read_to_string(file).unwrap();
+        let tests: Value = serde_json::from_str(&text)
+            .unwrap_or_else(|e| panic!("Couldn't parse {}: {}", file.display(), e));
+        let tests = tests.as_array().unwrap();
+        total += tests.len();
+        let mut failed = 0;
+        let mut shown = Vec::new();
+        for test in tests {
+            let mismatches = run_test(test);
+            if mismatches.is_empty() {
+                continue;
+            }
+            failed += 1;
+            if shown.len() < SHOWN_FAILURES {
+                shown.push(format!(
+                    "    {}: {}",
+                    test["name"].as_str().unwrap_or("?"),
+                    mismatches.join(", ")
+                ));
+            }
+        }
+        if failed > 0 {
+            let opcode = file.file_stem().unwrap().to_string_lossy();
+            report.push(format!(
+                "{}: {}/{} failed\n{}",
+                opcode,
+                failed,
+                tests.len(),
+                shown.join("\n")
+            ));
+        }
+    }
+    assert!(
+        report.is_empty(),
+        "{} of {} opcodes had failures ({} tests run):\n{}",
+        report.len(),
+        files.len(),
+        total,
+        report.join("\n")
+    );
+}
@@ -0,0 +1,102 @@
+//! TIMA edge detection, the DIV reset glitch and the delayed TMA reload
+//!
+//! The mooneye timer roms these behaviours come from are run by mooneye_timer_roms in tests/mooneye.rs
+extern crate bouzu;
+
+use bouzu::timer::Timer;
+
+const DIV: u16 = 0xff04;
+const TIMA: u16 = 0xff05;
+const TMA: u16 = 0xff06;
+const TAC: u16 = 0xff07;
+
+///Timer enabled on divider bit 3, so TIMA counts every 16 T-cycles
+fn fast_timer() -> Timer {
+    let mut timer = Timer::new();
+    timer.write8(TAC, 0b101);
+    timer
+}
+
+///Timer that overflowed on its last M-cycle, the reload from TMA (0x42) is still pending
+fn overflowed_timer() -> Timer {
+    let mut timer = fast_timer();
+    timer.write8(TMA, 0x42);
+    timer.write8(TIMA, 0xff);
+    assert!(!timer.tick(16));
+    assert_eq!(timer.read8(TIMA), 0x00);
+    timer
+}
+
+#[test]
+fn tima_counts_falling_edges() {
+    let mut timer = fast_timer();
+    timer.tick(12);
+    assert_eq!(timer.read8(TIMA), 0);
+    //bit 3 falls as the divider goes from 12 to 16
+    timer.tick(4);
+    assert_eq!(timer.read8(TIMA), 1);
+    timer.tick(16 * 3);
+    assert_eq!(timer.read8(TIMA), 4);
+
+    //the slowest rate is bit 9, every 1024 T-cycles
+    let mut timer = Timer::new();
+    timer.write8(TAC, 0b100);
+    timer.tick(1020);
+    assert_eq!(timer.read8(TIMA), 0);
+    timer.tick(4);
+    assert_eq!(timer.read8(TIMA), 1);
+}
+
+#[test]
+fn disabled_timer_doesnt_count() {
+    let mut timer = Timer::new();
+    timer.write8(TAC, 0b001);
+    timer.tick(1024);
+    assert_eq!(timer.read8(TIMA), 0);
+    assert_eq!(timer.read8(DIV), 4);
+}
+
+#[test]
+fn div_reset_while_the_bit_is_high_increments_tima() {
+    let mut timer = fast_timer();
+    //divider at 8, bit 3 set
+    timer.tick(8);
+    timer.write8(DIV, 0x12);
+    assert_eq!(timer.read8(DIV), 0);
+    assert_eq!(timer.read8(TIMA), 1);
+    //with the bit low the reset is harmless
+    timer.tick(4);
+    timer.write8(DIV, 0);
+    assert_eq!(timer.read8(TIMA), 1);
+}
+
+#[test]
+fn tma_reload_and_interrupt_are_delayed() {
+    let mut timer = overflowed_timer();
+    assert!(timer.tick(4));
+    assert_eq!(timer.read8(TIMA), 0x42);
+    assert!(!timer.tick(4));
+}
+
+#[test]
+fn tima_write_during_the_reload_cycle_is_ignored() {
+    let mut timer = overflowed_timer();
+    assert!(timer.tick(4));
+    timer.write8(TIMA, 0x99);
+    assert_eq!(timer.read8(TIMA), 0x42);
+    //but a TMA write in the same cycle goes through to TIMA
+    timer.write8(TMA, 0x77);
+    assert_eq!(timer.read8(TIMA), 0x77);
+    //a cycle later TIMA can be written again
+    timer.tick(4);
+    timer.write8(TIMA, 0x99);
+    assert_eq!(timer.read8(TIMA), 0x99);
+}
+
+#[test]
+fn tima_write_before_the_reload_cancels_it() {
+    let mut timer = overflowed_timer();
+    timer.write8(TIMA, 0x10);
+    assert!(!timer.tick(4));
+    assert_eq!(timer.read8(TIMA), 0x10);
+}